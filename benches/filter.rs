@@ -0,0 +1,39 @@
+//! Cost of the threshold matching, run with `cargo bench --bench filter`.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use log::Level;
+use shvlog::{FileLog, LogConfig};
+
+fn bench(name: &str, iterations: u32, mut f: impl FnMut()) {
+    for _ in 0..iterations / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    println!("{:<44} {:>10.1} ns/iter", name, start.elapsed().as_nanos() as f64 / f64::from(iterations));
+}
+
+fn main() {
+    let module_tresholds: Vec<String> = ["bench::rpc:D", "bench::broker::mount:T", "shvbroker:W", "ws:I", ":I"].iter().map(|s| s.to_string()).collect();
+    let target_tresholds: Vec<String> = ["RpcMsg:D", "Access:I", "Tunnel:W"].iter().map(|s| s.to_string()).collect();
+    let path = std::env::temp_dir().join("shvlog-bench-filter.log");
+    shvlog::init(LogConfig::new(&module_tresholds, &target_tresholds).with_file(FileLog::new(&path))).expect("logger starts");
+
+    bench("log_enabled!() of a ruled target", 1_000_000, || {
+        black_box(log::log_enabled!(target: "RpcMsg", Level::Debug));
+    });
+    bench("log_enabled!() of an unknown target", 1_000_000, || {
+        black_box(log::log_enabled!(target: "bench::other", Level::Debug));
+    });
+    bench("debug! below the module threshold", 1_000_000, || {
+        log::debug!(target: "bench::other", "suppressed {}", black_box(1));
+    });
+    bench("trace! below the target threshold", 1_000_000, || {
+        log::trace!(target: "Access", "suppressed {}", black_box(1));
+    });
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,42 @@
+//! Cost of formatting and writing records to a file, run with `cargo bench --bench format`.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use shvlog::{FileLog, LogConfig};
+
+fn bench(name: &str, iterations: u32, mut f: impl FnMut()) {
+    for _ in 0..iterations / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    println!("{:<44} {:>10.1} ns/iter", name, start.elapsed().as_nanos() as f64 / f64::from(iterations));
+}
+
+fn main() {
+    let path = std::env::temp_dir().join("shvlog-bench-format.log");
+    let config = LogConfig::new(&[":D".to_string()], &[])
+        .show_thread(true)
+        .with_field("device", "SN-0042")
+        .with_file(FileLog::new(&path));
+    let handle = shvlog::init(config).expect("logger starts");
+
+    bench("info! with a short message", 200_000, || {
+        log::info!("client {} connected", black_box(42));
+    });
+    bench("info! with an explicit target", 200_000, || {
+        log::info!(target: "RpcMsg", "<== {}", black_box("<1:1,8:3>i{1:\"test/device\",2:\"ls\"}"));
+    });
+    bench("warn! with key-value fields", 200_000, || {
+        log::warn!(peer = "10.0.0.1", attempt = black_box(3); "login failed");
+    });
+    bench("debug! within a scope", 200_000, || {
+        let _scope = shvlog::scope("mount:/test/device");
+        log::debug!("reading {} bytes", black_box(512));
+    });
+    handle.flush();
+    let _ = std::fs::remove_file(&path);
+}
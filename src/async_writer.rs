@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use flexi_logger::writers::LogWriter;
+use flexi_logger::{DeferredNow, FormatFunction};
+
+use crate::{DropReason, Stream};
+
+/// What the async writer does with a record when its queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Waits until the writer thread makes room, no record is lost.
+    #[default]
+    Block,
+    /// Drops the oldest queued record.
+    DropOldest,
+    /// Drops the record being logged.
+    DropNewest,
+}
+
+#[derive(Default)]
+struct Queue {
+    lines: VecDeque<Vec<u8>>,
+    /// Dropped records not yet reported by the writer thread.
+    unreported: u64,
+    writing: bool,
+    closed: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    changed: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy,
+    stream: Stream,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Writes records to stderr or stdout from a dedicated thread.
+///
+/// Records are formatted by the logging thread, the format functions read the thread-local
+/// context, scope and group, only the writing is done by the "shvlog-async" thread.
+pub(crate) struct AsyncWriter {
+    shared: Arc<Shared>,
+    format: FormatFunction,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+impl AsyncWriter {
+    pub(crate) fn new(format: FormatFunction, capacity: usize, policy: OverflowPolicy, stream: Stream) -> std::io::Result<AsyncWriter> {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            changed: Condvar::new(),
+            capacity: capacity.max(1),
+            policy,
+            stream,
+            dropped: Arc::new(AtomicU64::new(0)),
+        });
+        let thread_shared = shared.clone();
+        let thread = thread::Builder::new()
+            .name("shvlog-async".into())
+            .spawn(move || write_queued(&thread_shared))?;
+        Ok(AsyncWriter { shared, format, thread: Mutex::new(Some(thread)) })
+    }
+    /// Total number of records dropped because of a full queue.
+    pub(crate) fn dropped(&self) -> Arc<AtomicU64> {
+        self.shared.dropped.clone()
+    }
+}
+impl LogWriter for AsyncWriter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        let mut line = Vec::new();
+        (self.format)(&mut line, now, record)?;
+        line.push(b'\n');
+        let shared = &self.shared;
+        let mut queue = shared.queue.lock().unwrap();
+        while queue.lines.len() >= shared.capacity && !queue.closed {
+            match shared.policy {
+                OverflowPolicy::Block => queue = shared.changed.wait(queue).unwrap(),
+                OverflowPolicy::DropOldest => {
+                    queue.lines.pop_front();
+                    queue.unreported += 1;
+                    shared.dropped.fetch_add(1, Ordering::Relaxed);
+                    crate::drops::count(DropReason::AsyncOverflow, None);
+                }
+                OverflowPolicy::DropNewest => {
+                    queue.unreported += 1;
+                    shared.dropped.fetch_add(1, Ordering::Relaxed);
+                    crate::drops::count(DropReason::AsyncOverflow, None);
+                    return Ok(());
+                }
+            }
+        }
+        if queue.closed {
+            // after shutdown the record is written directly
+            drop(queue);
+            return shared.stream.lock().write_all(&line);
+        }
+        queue.lines.push_back(line);
+        shared.changed.notify_all();
+        Ok(())
+    }
+    fn format(&mut self, format: FormatFunction) {
+        self.format = format;
+    }
+    /// Waits until the queued records are written.
+    fn flush(&self) -> std::io::Result<()> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        while (!queue.lines.is_empty() || queue.writing) && !queue.closed {
+            queue = self.shared.changed.wait(queue).unwrap();
+        }
+        Ok(())
+    }
+    /// Writes the queued records and stops the writer thread.
+    fn shutdown(&self) {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn write_queued(shared: &Shared) {
+    loop {
+        let (lines, unreported) = {
+            let mut queue = shared.queue.lock().unwrap();
+            while queue.lines.is_empty() && queue.unreported == 0 && !queue.closed {
+                queue = shared.changed.wait(queue).unwrap();
+            }
+            if queue.lines.is_empty() && queue.unreported == 0 {
+                return;
+            }
+            queue.writing = true;
+            let lines: Vec<_> = queue.lines.drain(..).collect();
+            let unreported = std::mem::take(&mut queue.unreported);
+            // room for the blocked loggers
+            shared.changed.notify_all();
+            (lines, unreported)
+        };
+        let mut out = shared.stream.lock();
+        if unreported > 0 {
+            let _ = writeln!(out, "shvlog: {} records dropped by the async writer, queue of {} records was full", unreported, shared.capacity);
+        }
+        for line in lines {
+            let _ = out.write_all(&line);
+        }
+        let _ = out.flush();
+        drop(out);
+        shared.queue.lock().unwrap().writing = false;
+        shared.changed.notify_all();
+    }
+}
@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+
+use crate::LogConfig;
+
+/// Builder of `LogConfig` from typed levels, see `LogConfig::builder()`.
+pub struct LogConfigBuilder {
+    module_levels: HashMap<String, log::LevelFilter>,
+    target_levels: HashMap<String, log::LevelFilter>,
+    default_level: log::LevelFilter,
+}
+impl LogConfigBuilder {
+    pub(crate) fn new() -> LogConfigBuilder {
+        LogConfigBuilder {
+            module_levels: HashMap::new(),
+            target_levels: HashMap::new(),
+            default_level: log::LevelFilter::Info,
+        }
+    }
+    /// Threshold for records from modules whose path contains `module`, like `-d module:L`.
+    pub fn module(mut self, module: &str, level: log::LevelFilter) -> Self {
+        self.module_levels.insert(module.into(), level);
+        self
+    }
+    /// Threshold for records with target containing `target`, like `-v target:L`.
+    pub fn target(mut self, target: &str, level: log::LevelFilter) -> Self {
+        self.target_levels.insert(target.into(), level);
+        self
+    }
+    /// Threshold for records not matched by any module or target rule, Info by default.
+    pub fn default_level(mut self, level: log::LevelFilter) -> Self {
+        self.default_level = level;
+        self
+    }
+    pub fn build(self) -> LogConfig {
+        LogConfig::from_levels(self.module_levels, self.target_levels, self.default_level)
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use flexi_logger::filter::LogLineWriter;
+use flexi_logger::DeferredNow;
+
+struct BurstState {
+    window_start: Instant,
+    count: usize,
+    muted: usize,
+}
+
+/// Lets through the first records of a target or module within a time window and mutes the rest.
+pub(crate) struct Burst {
+    pattern: String,
+    level: log::Level,
+    max_count: usize,
+    window: Duration,
+    state: Mutex<BurstState>,
+}
+impl Burst {
+    pub(crate) fn new(pattern: &str, level: log::Level, max_count: usize, window: Duration) -> Burst {
+        Burst {
+            pattern: pattern.into(),
+            level,
+            max_count,
+            window,
+            state: Mutex::new(BurstState { window_start: Instant::now(), count: 0, muted: 0 }),
+        }
+    }
+    pub(crate) fn matches(&self, record: &log::Record) -> bool {
+        record.level() <= self.level
+            && (record.target().contains(&self.pattern) || record.module_path().unwrap_or("").contains(&self.pattern))
+    }
+    /// Counts `record` and returns whether it is within the burst allowance.
+    /// Writes a notice when muting starts and when the window expires after muting.
+    pub(crate) fn admit(&self, now: &mut DeferredNow, record: &log::Record, log_line_writer: &dyn LogLineWriter) -> std::io::Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        if state.window_start.elapsed() >= self.window {
+            if state.muted > 0 {
+                let notice = format!("Records of '{}' unmuted, {} records were muted", self.pattern, state.muted);
+                write_notice(now, record, &notice, log::Level::Info, log_line_writer)?;
+            }
+            *state = BurstState { window_start: Instant::now(), count: 0, muted: 0 };
+        }
+        if state.count < self.max_count {
+            state.count += 1;
+            return Ok(true);
+        }
+        if state.muted == 0 {
+            let notice = format!(
+                "More than {} records of '{}' within {:?}, muting them until the window expires",
+                self.max_count,
+                self.pattern,
+                self.window
+            );
+            write_notice(now, record, &notice, log::Level::Warn, log_line_writer)?;
+        }
+        state.muted += 1;
+        Ok(false)
+    }
+}
+
+fn write_notice(now: &mut DeferredNow, record: &log::Record, notice: &str, level: log::Level, log_line_writer: &dyn LogLineWriter) -> std::io::Result<()> {
+    log_line_writer.write(now, &log::Record::builder()
+        .args(format_args!("{}", notice))
+        .level(level)
+        .target(record.target())
+        .module_path(record.module_path())
+        .file(record.file())
+        .line(record.line())
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Collect(Mutex<Vec<String>>);
+    impl LogLineWriter for Collect {
+        fn write(&self, _now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+            self.0.lock().unwrap().push(record.args().to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn muted_after_max_count() {
+        let burst = Burst::new("modbus", log::Level::Warn, 2, Duration::from_secs(1));
+        let output = Collect(Mutex::new(Vec::new()));
+        let log = |record: &log::Record| {
+            if !burst.matches(record) || burst.admit(&mut DeferredNow::new(), record, &output).unwrap() {
+                output.write(&mut DeferredNow::new(), record).unwrap();
+            }
+        };
+        for (level, message) in [
+            (log::Level::Error, "one"),
+            (log::Level::Info, "info"),
+            (log::Level::Warn, "two"),
+            (log::Level::Error, "three"),
+            (log::Level::Error, "four"),
+            (log::Level::Error, "five"),
+        ] {
+            if message == "five" {
+                burst.state.lock().unwrap().window_start -= Duration::from_secs(1);
+            }
+            log(&log::Record::builder().level(level).target("app").module_path(Some("shvapp::modbus")).args(format_args!("{}", message)).build());
+        }
+        assert_eq!(*output.0.lock().unwrap(), [
+            "one",
+            "info",
+            "two",
+            "More than 2 records of 'modbus' within 1s, muting them until the window expires",
+            "Records of 'modbus' unmuted, 2 records were muted",
+            "five",
+        ]);
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::Ordering;
+use std::sync::RwLock;
+
+use log::LevelFilter;
+
+use crate::rules::Hits;
+
+const SHARDS: usize = 16;
+/// A shard with more entries is emptied, generated targets cannot grow the cache without limit.
+const SHARD_CAPACITY: usize = 256;
+
+struct Entry {
+    module: Box<str>,
+    target: Box<str>,
+    threshold: LevelFilter,
+    /// Hit counter of the rule deciding the threshold.
+    hits: Option<Hits>,
+}
+
+/// Thresholds resolved from the module and target rules per module and target, sharded so
+/// that threads logging to different targets rarely contend for a lock.
+#[derive(Default)]
+pub(crate) struct ThresholdCache {
+    shards: [RwLock<HashMap<u64, Entry>>; SHARDS],
+}
+impl ThresholdCache {
+    /// Cached threshold of `module` and `target`, `resolve` computes a missing one.
+    pub(crate) fn get_or_resolve(&self, module: &str, target: &str, resolve: impl FnOnce() -> LevelFilter) -> LevelFilter {
+        self.get_or_resolve_rule(module, target, false, || (resolve(), None))
+    }
+    /// Like `get_or_resolve()` with the hit counter of the rule deciding the threshold,
+    /// incremented with `hit`.
+    pub(crate) fn get_or_resolve_rule(&self, module: &str, target: &str, hit: bool, resolve: impl FnOnce() -> (LevelFilter, Option<Hits>)) -> LevelFilter {
+        let mut hasher = DefaultHasher::new();
+        module.hash(&mut hasher);
+        target.hash(&mut hasher);
+        let hash = hasher.finish();
+        let shard = &self.shards[hash as usize % SHARDS];
+        if let Some(entry) = shard.read().unwrap().get(&hash) {
+            if &*entry.module == module && &*entry.target == target {
+                if let Some(hits) = entry.hits.as_ref().filter(|_| hit) {
+                    hits.fetch_add(1, Ordering::Relaxed);
+                }
+                return entry.threshold;
+            }
+        }
+        let (threshold, hits) = resolve();
+        if let Some(hits) = hits.as_ref().filter(|_| hit) {
+            hits.fetch_add(1, Ordering::Relaxed);
+        }
+        let mut shard = shard.write().unwrap();
+        if shard.len() >= SHARD_CAPACITY {
+            shard.clear();
+        }
+        shard.insert(hash, Entry { module: module.into(), target: target.into(), threshold, hits });
+        threshold
+    }
+    /// Drops all cached thresholds, the rules have changed.
+    pub(crate) fn clear(&self) {
+        for shard in &self.shards {
+            shard.write().unwrap().clear();
+        }
+    }
+}
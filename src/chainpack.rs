@@ -0,0 +1,198 @@
+use chrono::{DateTime, Offset, TimeZone};
+
+/// SHV DateTime epoch 2018-02-02T00:00:00Z in milliseconds since the Unix epoch.
+const SHV_EPOCH_MSEC: i64 = 1_517_529_600_000;
+
+const CP_NULL: u8 = 128;
+const CP_UINT: u8 = 129;
+const CP_INT: u8 = 130;
+const CP_STRING: u8 = 134;
+const CP_LIST: u8 = 136;
+const CP_MAP: u8 = 137;
+const CP_DATE_TIME: u8 = 141;
+const CP_TERM: u8 = 255;
+
+/// Quoted CPON string.
+pub(crate) fn cpon_string(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+/// CPON DateTime, e.g. `d"2024-03-01T12:00:00.123+01"`.
+pub(crate) fn cpon_date_time<Tz: TimeZone>(time: &DateTime<Tz>) -> String {
+    let offset_min = time.offset().fix().local_minus_utc() / 60;
+    let offset = match offset_min {
+        0 => "Z".to_string(),
+        _ if offset_min % 60 == 0 => format!("{}{:02}", if offset_min < 0 { '-' } else { '+' }, offset_min.abs() / 60),
+        _ => format!("{}{:02}{:02}", if offset_min < 0 { '-' } else { '+' }, offset_min.abs() / 60, offset_min.abs() % 60),
+    };
+    let msec = time.timestamp_subsec_millis();
+    let fraction = if msec == 0 { String::new() } else { format!(".{:03}", msec) };
+    format!("d\"{}{}{}\"", time.naive_local().format("%Y-%m-%dT%H:%M:%S"), fraction, offset)
+}
+
+/// Minimal ChainPack encoder for the log records.
+#[derive(Default)]
+pub(crate) struct ChainPackWriter(pub(crate) Vec<u8>);
+impl ChainPackWriter {
+    pub(crate) fn null(&mut self) {
+        self.0.push(CP_NULL);
+    }
+    pub(crate) fn uint(&mut self, value: u64) {
+        if value < 64 {
+            self.0.push(value as u8);
+        } else {
+            self.0.push(CP_UINT);
+            self.uint_data(u128::from(value), significant_bits(value));
+        }
+    }
+    pub(crate) fn int(&mut self, value: i64) {
+        if (0..64).contains(&value) {
+            self.0.push(64 + value as u8);
+        } else {
+            self.0.push(CP_INT);
+            self.int_data(value);
+        }
+    }
+    pub(crate) fn string(&mut self, value: &str) {
+        self.0.push(CP_STRING);
+        self.uint_data(value.len() as u128, significant_bits(value.len() as u64));
+        self.0.extend_from_slice(value.as_bytes());
+    }
+    pub(crate) fn list_begin(&mut self) {
+        self.0.push(CP_LIST);
+    }
+    pub(crate) fn map_begin(&mut self) {
+        self.0.push(CP_MAP);
+    }
+    pub(crate) fn container_end(&mut self) {
+        self.0.push(CP_TERM);
+    }
+    /// Milliseconds since the SHV epoch with the UTC offset in quarter hours, the lowest bits
+    /// flag the offset and whole seconds.
+    pub(crate) fn date_time<Tz: TimeZone>(&mut self, time: &DateTime<Tz>) {
+        let mut msecs = time.timestamp_millis() - SHV_EPOCH_MSEC;
+        let offset = i64::from(time.offset().fix().local_minus_utc() / 60 / 15) & 0x7f;
+        let whole_seconds = msecs % 1000 == 0;
+        if whole_seconds {
+            msecs /= 1000;
+        }
+        if offset != 0 {
+            msecs = (msecs << 7) | offset;
+        }
+        msecs <<= 2;
+        if offset != 0 {
+            msecs |= 1;
+        }
+        if whole_seconds {
+            msecs |= 2;
+        }
+        self.0.push(CP_DATE_TIME);
+        self.int_data(msecs);
+    }
+    fn int_data(&mut self, value: i64) {
+        let abs = value.unsigned_abs();
+        // the sign is the most significant bit of the data, beyond 64 bits for i64::MIN
+        let bit_len = significant_bits(abs) + 1;
+        let mut num = u128::from(abs);
+        if value < 0 {
+            num |= 1 << data_bits(bit_len);
+        }
+        self.uint_data(num, bit_len);
+    }
+    /// 1 to 4 bytes with the length in the leading one bits, longer numbers have
+    /// `0xf0 | (bytes - 4)` head followed by the bytes.
+    fn uint_data(&mut self, num: u128, bit_len: u32) {
+        let byte_count = byte_count(bit_len);
+        let mut bytes = vec![0u8; byte_count];
+        let mut rest = num;
+        for byte in bytes.iter_mut().rev() {
+            *byte = rest as u8;
+            rest >>= 8;
+        }
+        if bit_len <= 28 {
+            let mask = (0xf0u32 << (4 - byte_count)) as u8;
+            bytes[0] = (bytes[0] & !mask) | (u32::from(mask) << 1) as u8;
+        } else {
+            bytes[0] = 0xf0 | (byte_count - 5) as u8;
+        }
+        self.0.extend_from_slice(&bytes);
+    }
+}
+
+fn significant_bits(num: u64) -> u32 {
+    (64 - num.leading_zeros()).max(1)
+}
+
+fn byte_count(bit_len: u32) -> usize {
+    if bit_len <= 28 { (bit_len as usize - 1) / 7 + 1 } else { (bit_len as usize - 1) / 8 + 2 }
+}
+
+/// Index of the highest data bit of a number encoded in `byte_count(bit_len)` bytes.
+fn data_bits(bit_len: u32) -> u32 {
+    let bytes = byte_count(bit_len) as u32;
+    if bit_len <= 28 { bytes * 7 - 1 } else { (bytes - 1) * 8 - 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uint(value: u64) -> Vec<u8> {
+        let mut writer = ChainPackWriter::default();
+        writer.uint(value);
+        writer.0
+    }
+
+    fn int(value: i64) -> Vec<u8> {
+        let mut writer = ChainPackWriter::default();
+        writer.int(value);
+        writer.0
+    }
+
+    #[test]
+    fn uint_limits() {
+        // the 1 to 4 byte forms hold 7, 14, 21 and 28 bits
+        assert_eq!(uint(63), [0x3f]);
+        assert_eq!(uint(127), [CP_UINT, 0x7f]);
+        assert_eq!(uint(128), [CP_UINT, 0x80, 0x80]);
+        assert_eq!(uint((1 << 14) - 1), [CP_UINT, 0xbf, 0xff]);
+        assert_eq!(uint(1 << 14), [CP_UINT, 0xc0, 0x40, 0x00]);
+        assert_eq!(uint((1 << 21) - 1), [CP_UINT, 0xdf, 0xff, 0xff]);
+        assert_eq!(uint(1 << 21), [CP_UINT, 0xe0, 0x20, 0x00, 0x00]);
+        assert_eq!(uint((1 << 28) - 1), [CP_UINT, 0xef, 0xff, 0xff, 0xff]);
+        assert_eq!(uint(1 << 28), [CP_UINT, 0xf0, 0x10, 0x00, 0x00, 0x00]);
+        assert_eq!(uint(u64::from(u32::MAX)), [CP_UINT, 0xf0, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(uint(1 << 32), [CP_UINT, 0xf1, 0x01, 0x00, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn int_limits() {
+        // the sign takes the most significant data bit
+        assert_eq!(int(63), [0x7f]);
+        assert_eq!(int(64), [CP_INT, 0x80, 0x40]);
+        assert_eq!(int(-1), [CP_INT, 0x41]);
+        assert_eq!(int(-63), [CP_INT, 0x7f]);
+        assert_eq!(int(-64), [CP_INT, 0xa0, 0x40]);
+        assert_eq!(int((1 << 13) - 1), [CP_INT, 0x9f, 0xff]);
+        assert_eq!(int(1 << 13), [CP_INT, 0xc0, 0x20, 0x00]);
+        assert_eq!(int(-(1 << 13)), [CP_INT, 0xd0, 0x20, 0x00]);
+        assert_eq!(int((1 << 27) - 1), [CP_INT, 0xe7, 0xff, 0xff, 0xff]);
+        assert_eq!(int(-((1 << 27) - 1)), [CP_INT, 0xef, 0xff, 0xff, 0xff]);
+        assert_eq!(int(1 << 27), [CP_INT, 0xf0, 0x08, 0x00, 0x00, 0x00]);
+        assert_eq!(int(-(1 << 27)), [CP_INT, 0xf0, 0x88, 0x00, 0x00, 0x00]);
+        assert_eq!(int(i64::MIN), [CP_INT, 0xf5, 0x80, 0x80, 0, 0, 0, 0, 0, 0, 0]);
+    }
+}
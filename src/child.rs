@@ -0,0 +1,26 @@
+use std::io::{BufRead, BufReader};
+use std::process::Child;
+use std::thread::{self, JoinHandle};
+
+/// Re-logs the stderr lines of `child` with `target` and `level`, so that the output of helper
+/// processes goes through the filtering and to the configured outputs. The child must be
+/// spawned with `Stdio::piped()` stderr, which is taken from it.
+///
+/// The lines are read by a "shvlog-child" thread, which ends when the child closes its stderr.
+pub fn pipe_child_stderr(child: &mut Child, target: &str, level: log::Level) -> std::io::Result<JoinHandle<()>> {
+    let stderr = child.stderr.take()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "child stderr is not piped"))?;
+    let target = target.to_string();
+    thread::Builder::new().name("shvlog-child".into()).spawn(move || {
+        let mut reader = BufReader::new(stderr);
+        let mut line = Vec::new();
+        while let Ok(len) = reader.read_until(b'\n', &mut line) {
+            if len == 0 {
+                break;
+            }
+            let text = String::from_utf8_lossy(&line);
+            log::log!(target: &target, level, "{}", text.trim_end_matches(['\n', '\r']));
+            line.clear();
+        }
+    })
+}
@@ -0,0 +1,93 @@
+//! Standard `-d`/`-v` command line options, flatten `LogArgs` into the application arguments:
+//!
+//! ```ignore
+//! #[derive(clap::Parser)]
+//! struct Args {
+//!     #[command(flatten)]
+//!     log: shvlog::cli::LogArgs,
+//! }
+//! ```
+//!
+//! An application handles `--explain` before starting the logger:
+//!
+//! ```ignore
+//! if let Some(explanation) = args.log.explain() {
+//!     println!("{}", explanation);
+//!     return;
+//! }
+//! ```
+
+use crate::{parse, Explanation, LogConfig, ParseError};
+
+/// Logging options of an application.
+#[derive(Clone, Debug, Default, clap::Args)]
+pub struct LogArgs {
+    /// Module thresholds, e.g. `-d shvbroker:D,:I`, can be repeated
+    #[arg(short = 'd', long = "debug", value_name = "MODULE:LEVEL", value_parser = check_tresholds)]
+    pub debug: Vec<String>,
+    /// Target thresholds, e.g. `-v RpcMsg:T`, can be repeated
+    #[arg(short = 'v', long = "verbose", value_name = "TARGET:LEVEL", value_parser = check_tresholds)]
+    pub verbose: Vec<String>,
+    /// Tells which threshold decides a record and exits, e.g. `--explain shvrpc::client,RpcMsg,debug`,
+    /// the target defaults to the module when empty
+    #[arg(long = "explain", value_name = "MODULE,TARGET,LEVEL", value_parser = parse_explain)]
+    pub explain: Option<(String, String, log::Level)>,
+}
+impl LogArgs {
+    pub fn to_config(&self) -> LogConfig {
+        LogConfig::new(&self.debug, &self.verbose)
+    }
+    /// Explanation of the `--explain` record by the `-d` and `-v` thresholds, see
+    /// `LogConfig::explain()`.
+    pub fn explain(&self) -> Option<Explanation> {
+        let (module, target, level) = self.explain.as_ref()?;
+        Some(self.to_config().explain(module, target, *level))
+    }
+}
+
+/// Rejects invalid thresholds already when parsing the command line.
+fn check_tresholds(tresholds: &str) -> Result<String, ParseError> {
+    let tresholds = tresholds.to_string();
+    match parse::parse_level_strings(std::slice::from_ref(&tresholds)).1.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(tresholds),
+    }
+}
+
+/// Parses `module,target,level` of `--explain`.
+fn parse_explain(record: &str) -> Result<(String, String, log::Level), String> {
+    let mut parts = record.splitn(3, ',');
+    let (Some(module), Some(target), Some(level)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(format!("expected MODULE,TARGET,LEVEL, got '{}'", record));
+    };
+    let level = parse::parse_level(level.trim())
+        .and_then(|level| level.to_level())
+        .ok_or_else(|| format!("invalid level '{}'", level))?;
+    let target = if target.is_empty() { module } else { target };
+    Ok((module.into(), target.into(), level))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Args {
+        #[command(flatten)]
+        log: LogArgs,
+    }
+
+    #[test]
+    fn explain() {
+        let args = Args::try_parse_from(["app", "-d", "rpc:D,:I", "--explain", "rpc::client,,debug"]).unwrap();
+        let explanation = args.log.explain().unwrap();
+        assert_eq!((explanation.target.as_str(), explanation.passes), ("rpc::client", true));
+        let args = Args::try_parse_from(["app", "-v", "RpcMsg:W", "--explain", "app,RpcMsg,I"]).unwrap();
+        assert!(!args.log.explain().unwrap().passes);
+        assert!(Args::try_parse_from(["app"]).unwrap().log.explain().is_none());
+        assert!(Args::try_parse_from(["app", "--explain", "app,info"]).is_err());
+        assert!(Args::try_parse_from(["app", "--explain", "app,,loud"]).is_err());
+    }
+}
@@ -0,0 +1,570 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::{palette, parse, FlexiLoggerError, ColorMode, Facility, FileLog, Fsync, LogConfig, LogFormat, MatchMode, ModuleDisplay, Multiline, Palette, ParseError, RemoteLog, RotationAge, ShvJournal, Stream, Syslog, SyslogSeverity, TimestampMode, TimestampPrecision, Tz};
+
+/// Error of loading a config file, see `LogConfig::from_toml_file()`.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Toml(PathBuf, Box<toml::de::Error>),
+    /// Invalid threshold in the `module` or `target` table.
+    Treshold(PathBuf, ParseError),
+    /// Invalid value of a setting.
+    Invalid(PathBuf, String),
+    /// The logger cannot be started, see `init_with_reload()`.
+    Init(FlexiLoggerError),
+}
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "Cannot read {}: {}", path.display(), err),
+            ConfigError::Toml(path, err) => write!(f, "Invalid config {}: {}", path.display(), err),
+            ConfigError::Treshold(path, err) => write!(f, "Invalid config {}: {}", path.display(), err),
+            ConfigError::Invalid(path, msg) => write!(f, "Invalid config {}: {}", path.display(), msg),
+            ConfigError::Init(err) => write!(f, "Cannot start logger: {}", err),
+        }
+    }
+}
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, err) => Some(err),
+            ConfigError::Toml(_, err) => Some(err.as_ref()),
+            ConfigError::Treshold(_, err) => Some(err),
+            ConfigError::Invalid(..) => None,
+            ConfigError::Init(err) => Some(err),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    module: BTreeMap<String, String>,
+    #[serde(default)]
+    target: BTreeMap<String, String>,
+    message_filter: Option<String>,
+    match_mode: Option<MatchModeName>,
+    format: Option<FormatName>,
+    json_keys: Option<BTreeMap<String, String>>,
+    template: Option<String>,
+    timestamp: Option<String>,
+    timestamp_precision: Option<u8>,
+    timezone: Option<String>,
+    color: Option<ColorName>,
+    output: Option<StreamName>,
+    always_flush: Option<bool>,
+    module_colors: Option<bool>,
+    #[serde(default)]
+    show_hostname: bool,
+    #[serde(default)]
+    show_pid: bool,
+    palette: Option<BTreeMap<String, String>>,
+    app_name: Option<String>,
+    instance_id: Option<String>,
+    verbosity_state: Option<PathBuf>,
+    stderr_level: Option<String>,
+    max_line_len: Option<usize>,
+    module_display: Option<ModuleDisplayName>,
+    multiline: Option<MultilineName>,
+    show_line: Option<bool>,
+    show_file: Option<bool>,
+    file_path_prefix: Option<String>,
+    journald: Option<bool>,
+    file: Option<FileSection>,
+    syslog: Option<SyslogSection>,
+    gelf: Option<GelfSection>,
+    remote: Option<RemoteSection>,
+    shvjournal: Option<ShvJournalSection>,
+    otel: Option<OtelSection>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MatchModeName {
+    Substring,
+    ModulePath,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FormatName {
+    Text,
+    Json,
+    Systemd,
+    Ecs,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ModuleDisplayName {
+    Full,
+    LastSegment,
+    Hidden,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MultilineName {
+    AsIs,
+    Indent,
+    Prefix,
+}
+
+fn log_format(name: FormatName) -> LogFormat {
+    match name {
+        FormatName::Text => LogFormat::Text,
+        FormatName::Json => LogFormat::Json,
+        FormatName::Systemd => LogFormat::Systemd,
+        FormatName::Ecs => LogFormat::Ecs,
+    }
+}
+
+fn module_display(name: ModuleDisplayName) -> ModuleDisplay {
+    match name {
+        ModuleDisplayName::Full => ModuleDisplay::Full,
+        ModuleDisplayName::LastSegment => ModuleDisplay::LastSegment,
+        ModuleDisplayName::Hidden => ModuleDisplay::Hidden,
+    }
+}
+fn multiline(name: MultilineName) -> Multiline {
+    match name {
+        MultilineName::AsIs => Multiline::AsIs,
+        MultilineName::Indent => Multiline::Indent,
+        MultilineName::Prefix => Multiline::Prefix,
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum StreamName {
+    Stderr,
+    Stdout,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ColorName {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileSection {
+    path: PathBuf,
+    level: Option<String>,
+    rotate_size: Option<u64>,
+    rotate_age: Option<RotationAgeName>,
+    #[serde(default)]
+    keep_files: usize,
+    max_line_len: Option<usize>,
+    module_display: Option<ModuleDisplayName>,
+    multiline: Option<MultilineName>,
+    format: Option<FormatName>,
+    show_line: Option<bool>,
+    show_file: Option<bool>,
+    #[cfg(feature = "compress")]
+    #[serde(default)]
+    compress: bool,
+    #[cfg(unix)]
+    mode: Option<u32>,
+    #[cfg(unix)]
+    dir_mode: Option<u32>,
+    #[cfg(unix)]
+    group: Option<u32>,
+    fsync: Option<FsyncName>,
+    #[serde(default)]
+    windows_line_endings: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FsyncName {
+    Never,
+    Always,
+    OnError,
+    Records(u32),
+    Interval(f64),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RotationAgeName {
+    Hour,
+    Day,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SyslogSection {
+    udp: Option<String>,
+    tcp: Option<String>,
+    #[cfg(unix)]
+    unix: Option<PathBuf>,
+    facility: Option<String>,
+    #[serde(default)]
+    target_facilities: BTreeMap<String, String>,
+    #[serde(default)]
+    severities: BTreeMap<String, String>,
+    app_name: Option<String>,
+    level: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(not(feature = "gelf"), allow(dead_code))]
+struct GelfSection {
+    udp: Option<String>,
+    tcp: Option<String>,
+    chunk_size: Option<usize>,
+    level: Option<String>,
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RemoteSection {
+    tcp: Option<String>,
+    #[cfg(unix)]
+    unix: Option<PathBuf>,
+    format: Option<FormatName>,
+    buffer: Option<usize>,
+    level: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShvJournalSection {
+    dir: PathBuf,
+    file_size: Option<u64>,
+    journal_size: Option<u64>,
+    level: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(not(feature = "otel"), allow(dead_code))]
+struct OtelSection {
+    endpoint: String,
+    level: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+fn syslog_severity(name: &str) -> Option<SyslogSeverity> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "emergency" => SyslogSeverity::Emergency,
+        "alert" => SyslogSeverity::Alert,
+        "critical" => SyslogSeverity::Critical,
+        "error" => SyslogSeverity::Error,
+        "warning" => SyslogSeverity::Warning,
+        "notice" => SyslogSeverity::Notice,
+        "informational" => SyslogSeverity::Informational,
+        "debug" => SyslogSeverity::Debug,
+        _ => return None,
+    })
+}
+
+fn facility(name: &str) -> Option<Facility> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "kern" => Facility::Kern,
+        "user" => Facility::User,
+        "mail" => Facility::Mail,
+        "daemon" => Facility::Daemon,
+        "auth" => Facility::Auth,
+        "syslog" => Facility::Syslog,
+        "lpr" => Facility::Lpr,
+        "news" => Facility::News,
+        "uucp" => Facility::Uucp,
+        "cron" => Facility::Cron,
+        "authpriv" => Facility::Authpriv,
+        "ftp" => Facility::Ftp,
+        "local0" => Facility::Local0,
+        "local1" => Facility::Local1,
+        "local2" => Facility::Local2,
+        "local3" => Facility::Local3,
+        "local4" => Facility::Local4,
+        "local5" => Facility::Local5,
+        "local6" => Facility::Local6,
+        "local7" => Facility::Local7,
+        _ => return None,
+    })
+}
+
+/// `pattern = "level"` table as threshold strings.
+fn tresholds(table: BTreeMap<String, String>) -> Vec<String> {
+    table.into_iter().map(|(pattern, level)| format!("{}:{}", pattern, level)).collect()
+}
+
+pub(crate) fn load(path: &Path) -> Result<LogConfig, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(|err| ConfigError::Io(path.into(), err))?;
+    let file: ConfigFile = toml::from_str(&text).map_err(|err| ConfigError::Toml(path.into(), Box::new(err)))?;
+    let invalid = |msg: String| ConfigError::Invalid(path.into(), msg);
+    // `level` of an output section
+    let output_level = |level: Option<String>, section: &str| match level {
+        Some(level) => parse::parse_level(&level)
+            .and_then(|level| level.to_level())
+            .map(Some)
+            .ok_or_else(|| invalid(format!("invalid level '{}' in [{}]", level, section))),
+        None => Ok(None),
+    };
+    let mut config = LogConfig::try_new(&tresholds(file.module), &tresholds(file.target))
+        .map_err(|err| ConfigError::Treshold(path.into(), err))?;
+    config.message_filter = file.message_filter;
+    if let Some(match_mode) = file.match_mode {
+        config.match_mode = match match_mode {
+            MatchModeName::Substring => MatchMode::Substring,
+            MatchModeName::ModulePath => MatchMode::ModulePath,
+        };
+    }
+    if let Some(format) = file.format {
+        config.format = log_format(format);
+    }
+    config.format_template = file.template;
+    config.max_line_len = file.max_line_len;
+    if let Some(display) = file.module_display {
+        config.module_display = module_display(display);
+    }
+    if let Some(name) = file.multiline {
+        config.multiline = multiline(name);
+    }
+    if let Some(show) = file.show_line {
+        config.show_line = show;
+    }
+    if let Some(show) = file.show_file {
+        config.show_file = show;
+    }
+    config.file_path_prefix = file.file_path_prefix;
+    if let Some(timestamp) = file.timestamp {
+        config.timestamp = match timestamp.as_str() {
+            "local" => TimestampMode::LocalRfc3339,
+            "utc" => TimestampMode::Utc,
+            "elapsed" => TimestampMode::Elapsed,
+            "none" => TimestampMode::None,
+            _ => TimestampMode::Custom(timestamp),
+        };
+        config.timestamp.validate().map_err(invalid)?;
+    }
+    if let Some(digits) = file.timestamp_precision {
+        config.timestamp_precision = match digits {
+            3 => TimestampPrecision::Millis,
+            6 => TimestampPrecision::Micros,
+            9 => TimestampPrecision::Nanos,
+            _ => return Err(invalid(format!("invalid timestamp_precision {}, expected 3, 6 or 9", digits))),
+        };
+    }
+    if let Some(timezone) = file.timezone {
+        config.timezone = Tz::parse(&timezone).map_err(invalid)?;
+    }
+    if let Some(color) = file.color {
+        config.color_mode = match color {
+            ColorName::Auto => ColorMode::Auto,
+            ColorName::Always => ColorMode::Always,
+            ColorName::Never => ColorMode::Never,
+        };
+    }
+    if let Some(output) = file.output {
+        config.output = match output {
+            StreamName::Stderr => Stream::Stderr,
+            StreamName::Stdout => Stream::Stdout,
+        };
+    }
+    if let Some(enabled) = file.always_flush {
+        config.always_flush = enabled;
+    }
+    if let Some(enabled) = file.module_colors {
+        config.module_colors = enabled;
+    }
+    if let Some(section) = file.palette {
+        let mut palette = Palette::default();
+        for (key, value) in section {
+            let style = palette::parse_style(&value).ok_or_else(|| invalid(format!("invalid palette color '{}'", value)))?;
+            palette = match key.as_str() {
+                "timestamp" => palette.timestamp(style),
+                "module" => palette.module(style),
+                "target" => palette.target(style),
+                _ => {
+                    let level = parse::parse_level(&key)
+                        .and_then(|level| level.to_level())
+                        .ok_or_else(|| invalid(format!("unknown palette key '{}'", key)))?;
+                    palette.level(level, style)
+                }
+            };
+        }
+        config.palette = palette;
+    }
+    for (key, name) in file.json_keys.unwrap_or_default() {
+        config = config.json_key(&key, &name);
+    }
+    config.show_hostname = file.show_hostname;
+    config.show_pid = file.show_pid;
+    config.app_name = file.app_name;
+    if let Some(path) = file.verbosity_state {
+        config = config.persist_verbosity(path);
+    }
+    config.instance_id = file.instance_id;
+    if let Some(level) = file.stderr_level {
+        let level = parse::parse_level(&level)
+            .and_then(|level| level.to_level())
+            .ok_or_else(|| invalid(format!("invalid stderr_level '{}'", level)))?;
+        config.stderr_level = Some(level);
+    }
+    if file.journald == Some(true) {
+        #[cfg(all(unix, feature = "journald"))]
+        {
+            config.journald = true;
+        }
+        #[cfg(not(all(unix, feature = "journald")))]
+        return Err(invalid("journald output requires the journald feature".into()));
+    }
+    if let Some(section) = file.file {
+        let mut file_log = FileLog::new(section.path).keep_files(section.keep_files);
+        file_log.max_line_len = section.max_line_len;
+        file_log.level = output_level(section.level, "file")?;
+        if let Some(display) = section.module_display {
+            file_log.module_display = module_display(display);
+        }
+        if let Some(name) = section.multiline {
+            file_log.multiline = multiline(name);
+        }
+        file_log.format = section.format.map(log_format);
+        if let Some(show) = section.show_line {
+            file_log.show_line = show;
+        }
+        if let Some(show) = section.show_file {
+            file_log.show_file = show;
+        }
+        if let Some(bytes) = section.rotate_size {
+            file_log = file_log.rotate_size(bytes);
+        }
+        if section.windows_line_endings {
+            file_log = file_log.windows_line_endings();
+        }
+        if let Some(fsync) = section.fsync {
+            file_log = file_log.fsync(match fsync {
+                FsyncName::Never => Fsync::Never,
+                FsyncName::Always => Fsync::Always,
+                FsyncName::OnError => Fsync::OnError,
+                FsyncName::Records(count) => Fsync::Records(count),
+                FsyncName::Interval(secs) => Fsync::Interval(Duration::try_from_secs_f64(secs)
+                    .map_err(|_| invalid(format!("invalid fsync interval {}", secs)))?),
+            });
+        }
+        #[cfg(unix)]
+        {
+            file_log.ownership = crate::file::Ownership { mode: section.mode, dir_mode: section.dir_mode, group: section.group };
+        }
+        if let Some(age) = section.rotate_age {
+            file_log = file_log.rotate_age(match age {
+                RotationAgeName::Hour => RotationAge::Hour,
+                RotationAgeName::Day => RotationAge::Day,
+            });
+        }
+        #[cfg(feature = "compress")]
+        {
+            file_log = file_log.compress(section.compress);
+        }
+        config.file_log = Some(file_log);
+    }
+    if let Some(section) = file.syslog {
+        #[cfg(unix)]
+        let unix = section.unix.map(Syslog::unix);
+        #[cfg(not(unix))]
+        let unix = None;
+        let tcp = section.tcp.as_deref().map(Syslog::tcp);
+        let mut syslog = section.udp.as_deref().map(Syslog::udp)
+            .or(tcp)
+            .or(unix)
+            .ok_or_else(|| invalid("syslog needs one of udp, tcp or unix".into()))?;
+        if let Some(name) = section.facility {
+            syslog = syslog.facility(facility(&name).ok_or_else(|| invalid(format!("unknown syslog facility '{}'", name)))?);
+        }
+        for (target, name) in section.target_facilities {
+            syslog = syslog.target_facility(&target, facility(&name).ok_or_else(|| invalid(format!("unknown syslog facility '{}'", name)))?);
+        }
+        for (level, name) in section.severities {
+            let level = output_level(Some(level), "syslog")?.unwrap();
+            syslog = syslog.severity(level, syslog_severity(&name).ok_or_else(|| invalid(format!("unknown syslog severity '{}'", name)))?);
+        }
+        if let Some(app_name) = section.app_name {
+            syslog = syslog.app_name(&app_name);
+        }
+        syslog.level = output_level(section.level, "syslog")?;
+        config.syslog = Some(syslog);
+    }
+    if let Some(section) = file.remote {
+        #[cfg(unix)]
+        let unix = section.unix.map(RemoteLog::unix);
+        #[cfg(not(unix))]
+        let unix = None;
+        let mut remote = section.tcp.as_deref().map(RemoteLog::tcp)
+            .or(unix)
+            .ok_or_else(|| invalid("remote needs one of tcp or unix".into()))?;
+        if let Some(format) = section.format {
+            remote = remote.format(log_format(format));
+        }
+        if let Some(records) = section.buffer {
+            remote = remote.buffer(records);
+        }
+        remote.level = output_level(section.level, "remote")?;
+        config.remote = Some(remote);
+    }
+    if let Some(section) = file.shvjournal {
+        let mut journal = ShvJournal::new(section.dir);
+        if let Some(bytes) = section.file_size {
+            journal = journal.file_size(bytes);
+        }
+        if let Some(bytes) = section.journal_size {
+            journal = journal.journal_size(bytes);
+        }
+        journal.level = output_level(section.level, "shvjournal")?;
+        config.shvjournal = Some(journal);
+    }
+    if let Some(section) = file.otel {
+        #[cfg(feature = "otel")]
+        {
+            let mut otel = crate::Otel::http(&section.endpoint);
+            for (name, value) in &section.headers {
+                otel = otel.header(name, value);
+            }
+            otel.level = output_level(section.level, "otel")?;
+            config.otel = Some(otel);
+        }
+        #[cfg(not(feature = "otel"))]
+        {
+            let _ = section;
+            return Err(invalid("otel output requires the otel feature".into()));
+        }
+    }
+    if let Some(section) = file.gelf {
+        #[cfg(feature = "gelf")]
+        {
+            let tcp = section.tcp.as_deref().map(crate::Gelf::tcp);
+            let mut gelf = section.udp.as_deref().map(crate::Gelf::udp)
+                .or(tcp)
+                .ok_or_else(|| invalid("gelf needs one of udp or tcp".into()))?;
+            if let Some(bytes) = section.chunk_size {
+                gelf = gelf.chunk_size(bytes);
+            }
+            for (name, value) in &section.fields {
+                gelf = gelf.field(name, value);
+            }
+            gelf.level = output_level(section.level, "gelf")?;
+            config.gelf = Some(gelf);
+        }
+        #[cfg(not(feature = "gelf"))]
+        {
+            let _ = section;
+            return Err(invalid("gelf output requires the gelf feature".into()));
+        }
+    }
+    Ok(config)
+}
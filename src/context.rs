@@ -0,0 +1,50 @@
+//! Per-thread diagnostic context appended to every record logged from the thread.
+//!
+//! ```ignore
+//! shvlog::context::push("client_id", "42");
+//! log::info!("login");  // ... |I|login client_id=42
+//! shvlog::context::pop("client_id");
+//! ```
+
+use std::cell::RefCell;
+
+thread_local! {
+    static CONTEXT: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Adds `key=value` to the context of the current thread, shadowing a previous value of `key`
+/// until it is popped.
+pub fn push(key: &str, value: impl ToString) {
+    CONTEXT.with(|context| context.borrow_mut().push((key.into(), value.to_string())));
+}
+
+/// Removes the most recent value of `key` and returns it.
+pub fn pop(key: &str) -> Option<String> {
+    CONTEXT.with(|context| {
+        let mut context = context.borrow_mut();
+        let ix = context.iter().rposition(|(name, _)| name == key)?;
+        Some(context.remove(ix).1)
+    })
+}
+
+/// Current value of `key`.
+pub fn get(key: &str) -> Option<String> {
+    CONTEXT.with(|context| context.borrow().iter().rev().find(|(name, _)| name == key).map(|(_, value)| value.clone()))
+}
+
+/// Removes all values from the context of the current thread.
+pub fn clear() {
+    CONTEXT.with(|context| context.borrow_mut().clear());
+}
+
+/// Current values in the order they were pushed, shadowed values left out.
+pub(crate) fn current() -> Vec<(String, String)> {
+    CONTEXT.with(|context| {
+        let context = context.borrow();
+        context.iter()
+            .enumerate()
+            .filter(|(ix, (key, _))| !context[ix + 1..].iter().any(|(name, _)| name == key))
+            .map(|(_, field)| field.clone())
+            .collect()
+    })
+}
@@ -0,0 +1,256 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Once, RwLock, Weak};
+
+use chrono::Local;
+use flexi_logger::DeferredNow;
+use lazy_static::lazy_static;
+
+use crate::memory::MemoryBuffer;
+use crate::timezone;
+use crate::LogRecord;
+
+/// Recent records written to a crash file on a panic or a fatal signal, see
+/// `LogConfig::with_crash_dump()`.
+pub(crate) struct CrashDump {
+    dir: PathBuf,
+    pub(crate) level: log::Level,
+    records: MemoryBuffer,
+}
+impl CrashDump {
+    pub(crate) fn new(dir: PathBuf, capacity: usize, level: log::Level) -> CrashDump {
+        CrashDump { dir, level, records: MemoryBuffer::new(capacity) }
+    }
+    /// Keeps `record` up to the dump level, regardless of the thresholds.
+    pub(crate) fn keep(&self, now: &mut DeferredNow, record: &log::Record) {
+        if record.level() <= self.level {
+            let record = LogRecord::from_record(now, record);
+            #[cfg(all(unix, feature = "signals"))]
+            fatal_signal::append(format!("{}\n", record).as_bytes());
+            self.records.push(record);
+        }
+    }
+    /// Writes `reason` and the kept records to a new `crash-<timestamp>.log` file.
+    fn write(&self, reason: &str) -> std::io::Result<PathBuf> {
+        // a crash while a record is being kept leaves the buffer locked
+        let records = self.records.try_snapshot().unwrap_or_default();
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("crash-{}.log", timezone::localize(&Local::now()).format("%Y%m%dT%H%M%S%.3f")));
+        let mut file = File::create(&path)?;
+        writeln!(file, "{}", reason)?;
+        writeln!(file, "--- last {} records ---", records.len())?;
+        for record in &records {
+            writeln!(file, "{}", record)?;
+        }
+        file.sync_all()?;
+        Ok(path)
+    }
+}
+
+lazy_static! {
+    static ref ACTIVE: RwLock<Weak<CrashDump>> = RwLock::new(Weak::new());
+}
+static INSTALL: Once = Once::new();
+
+/// Makes `dump` the one written on a crash, the handlers are installed with the first one.
+pub(crate) fn install(dump: Option<&Arc<CrashDump>>) {
+    *ACTIVE.write().unwrap() = dump.map_or_else(Weak::new, Arc::downgrade);
+    #[cfg(all(unix, feature = "signals"))]
+    fatal_signal::set_dir(dump.map(|dump| dump.dir.as_path()));
+    if dump.is_none() {
+        return;
+    }
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let location = info.location().map_or("".into(), |location| format!(" at {}:{}", location.file(), location.line()));
+            write_dump(&format!("panic{}: {}", location, crate::panic::panic_text(info)));
+            previous(info);
+        }));
+        #[cfg(all(unix, feature = "signals"))]
+        for signal in [signal_hook::consts::SIGSEGV, signal_hook::consts::SIGABRT] {
+            let action = move || {
+                fatal_signal::write_dump(signal);
+                let _ = signal_hook::low_level::emulate_default_handler(signal);
+            };
+            // SAFETY: the action calls only async-signal-safe functions, a crash in malloc or
+            // with a lock held cannot deadlock it
+            let _ = unsafe { signal_hook_registry::register_unchecked(signal, move |_| action()) };
+        }
+    });
+}
+
+fn write_dump(reason: &str) {
+    let Some(dump) = ACTIVE.try_read().ok().and_then(|active| active.upgrade()) else {
+        return;
+    };
+    match dump.write(reason) {
+        Ok(path) => eprintln!("shvlog: crash dump written to {}", path.display()),
+        Err(err) => eprintln!("shvlog: cannot write crash dump to {}: {}", dump.dir.display(), err),
+    }
+}
+
+/// Crash file written by the SIGSEGV and SIGABRT handler. The handler may only call
+/// async-signal-safe functions, so the kept records are appended as text to a static
+/// buffer as they come and the directory is opened when the dump is installed.
+#[cfg(all(unix, feature = "signals"))]
+mod fatal_signal {
+    use std::os::unix::io::IntoRawFd;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicI32, AtomicU8, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// The most recent text of the kept records written on a fatal signal.
+    const TAIL_SIZE: usize = 64 * 1024;
+    static TAIL: [AtomicU8; TAIL_SIZE] = [const { AtomicU8::new(0) }; TAIL_SIZE];
+    /// Bytes appended to `TAIL` since the dump was installed.
+    static TAIL_END: AtomicUsize = AtomicUsize::new(0);
+    static APPENDING: Mutex<()> = Mutex::new(());
+    /// Directory of the crash files, -1 without an active crash dump.
+    static DIR_FD: AtomicI32 = AtomicI32::new(-1);
+
+    pub(super) fn set_dir(dir: Option<&Path>) {
+        let fd = dir
+            .and_then(|dir| std::fs::create_dir_all(dir).and_then(|_| std::fs::File::open(dir)).ok())
+            .map_or(-1, IntoRawFd::into_raw_fd);
+        let _appending = APPENDING.lock().unwrap();
+        TAIL_END.store(0, Ordering::Release);
+        let previous = DIR_FD.swap(fd, Ordering::AcqRel);
+        if previous >= 0 {
+            // SAFETY: the descriptor was opened by set_dir() and is not used elsewhere
+            unsafe { libc::close(previous) };
+        }
+    }
+
+    pub(super) fn append(line: &[u8]) {
+        let _appending = APPENDING.lock().unwrap();
+        let end = TAIL_END.load(Ordering::Relaxed);
+        for (ix, byte) in line.iter().enumerate() {
+            TAIL[(end + ix) % TAIL_SIZE].store(*byte, Ordering::Relaxed);
+        }
+        TAIL_END.store(end + line.len(), Ordering::Release);
+    }
+
+    fn write_all(fd: i32, mut bytes: *const u8, mut len: usize) {
+        while len > 0 {
+            // SAFETY: `bytes` points to `len` readable bytes
+            let written = unsafe { libc::write(fd, bytes.cast(), len) };
+            if written <= 0 {
+                return;
+            }
+            // SAFETY: `written` bytes of the range were consumed
+            bytes = unsafe { bytes.add(written as usize) };
+            len -= written as usize;
+        }
+    }
+
+    fn write_str(fd: i32, text: &[u8]) {
+        write_all(fd, text.as_ptr(), text.len());
+    }
+
+    /// Writes `TAIL[start..end]`, positions counted since the install.
+    fn write_tail(fd: i32, start: usize, end: usize) {
+        if start == end {
+            return;
+        }
+        let (from, to) = (start % TAIL_SIZE, end % TAIL_SIZE);
+        // AtomicU8 has the layout of u8
+        let tail = TAIL.as_ptr().cast::<u8>();
+        // SAFETY: the offsets are within TAIL
+        unsafe {
+            if from < to {
+                write_all(fd, tail.add(from), to - from);
+            } else {
+                write_all(fd, tail.add(from), TAIL_SIZE - from);
+                write_all(fd, tail, to);
+            }
+        }
+    }
+
+    /// `crash-<UTC timestamp>Z.log` with a terminating NUL, formatted without allocating.
+    // time_t and c_long are 32-bit on some targets
+    #[allow(clippy::useless_conversion)]
+    fn file_name(name: &mut [u8; 32]) {
+        let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        // SAFETY: `time` is a valid timespec
+        unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut time) };
+        let (year, month, day) = super::civil_from_days(i64::from(time.tv_sec).div_euclid(86_400));
+        let seconds = i64::from(time.tv_sec).rem_euclid(86_400);
+        let mut len = 0;
+        let mut push = |text: &[u8]| {
+            name[len..len + text.len()].copy_from_slice(text);
+            len += text.len();
+        };
+        let digits = |value: i64, width: usize| {
+            let mut digits = [b'0'; 4];
+            for ix in 0..width {
+                digits[width - 1 - ix] = b'0' + (value / 10_i64.pow(ix as u32) % 10) as u8;
+            }
+            digits
+        };
+        push(b"crash-");
+        push(&digits(year, 4));
+        push(&digits(month, 2)[..2]);
+        push(&digits(day, 2)[..2]);
+        push(b"T");
+        push(&digits(seconds / 3600, 2)[..2]);
+        push(&digits(seconds / 60 % 60, 2)[..2]);
+        push(&digits(seconds % 60, 2)[..2]);
+        push(b".");
+        push(&digits(i64::from(time.tv_nsec) / 1_000_000, 3)[..3]);
+        push(b"Z.log\0");
+    }
+
+    /// Writes the signal name and the tail of the kept records to a new crash file.
+    pub(super) fn write_dump(signal: i32) {
+        let dir = DIR_FD.load(Ordering::Acquire);
+        if dir < 0 {
+            return;
+        }
+        let mut name = [0; 32];
+        file_name(&mut name);
+        // SAFETY: `name` is NUL terminated
+        let fd = unsafe { libc::openat(dir, name.as_ptr().cast(), libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC, 0o644) };
+        if fd < 0 {
+            write_str(2, b"shvlog: cannot write crash dump\n");
+            return;
+        }
+        write_str(fd, b"fatal signal ");
+        write_str(fd, signal_hook::low_level::signal_name(signal).unwrap_or("").as_bytes());
+        write_str(fd, b"\n--- last records ---\n");
+        let end = TAIL_END.load(Ordering::Acquire);
+        let mut start = end.saturating_sub(TAIL_SIZE);
+        if start > 0 {
+            // the oldest line was partly overwritten
+            while start < end && TAIL[start % TAIL_SIZE].load(Ordering::Relaxed) != b'\n' {
+                start += 1;
+            }
+            start = (start + 1).min(end);
+        }
+        write_tail(fd, start, end);
+        // SAFETY: `fd` was opened above
+        unsafe {
+            libc::fsync(fd);
+            libc::close(fd);
+        }
+        write_str(2, b"shvlog: crash dump written to ");
+        write_str(2, name.split(|byte| *byte == 0).next().unwrap_or(&[]));
+        write_str(2, b"\n");
+    }
+}
+
+/// Year, month and day of the days since 1970-01-01, without chrono for the signal handler.
+#[cfg(all(unix, feature = "signals"))]
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
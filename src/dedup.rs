@@ -0,0 +1,133 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use flexi_logger::filter::LogLineWriter;
+use flexi_logger::DeferredNow;
+
+/// Last admitted record of a deduplicated level and its suppressed repetitions.
+struct LastRecord {
+    level: log::Level,
+    module_path: Option<String>,
+    target: String,
+    line: Option<u32>,
+    message: String,
+    repeated: usize,
+    reported: Instant,
+}
+
+/// Collapses consecutive identical records into periodic "repeated N times" notices.
+#[derive(Default)]
+pub(crate) struct Dedup {
+    intervals: Vec<(log::Level, Duration)>,
+    last: Mutex<Option<LastRecord>>,
+}
+impl Dedup {
+    pub(crate) fn set_interval(&mut self, level: log::Level, interval: Duration) {
+        self.intervals.retain(|(l, _)| *l != level);
+        self.intervals.push((level, interval));
+    }
+    fn interval(&self, level: log::Level) -> Option<Duration> {
+        self.intervals.iter().find(|(l, _)| *l == level).map(|(_, interval)| *interval)
+    }
+    /// Returns whether `record` differs from the previous one and is to be written.
+    /// A pending repeat notice is written before a different record, and while the
+    /// repetitions continue once per interval.
+    pub(crate) fn admit(&self, now: &mut DeferredNow, record: &log::Record, log_line_writer: &dyn LogLineWriter) -> std::io::Result<bool> {
+        if self.intervals.is_empty() {
+            return Ok(true);
+        }
+        let message = record.args().to_string();
+        let mut last = self.last.lock().unwrap();
+        if let Some(last) = last.as_mut() {
+            let same = last.level == record.level()
+                && last.module_path.as_deref() == record.module_path()
+                && last.target == record.target()
+                && last.message == message;
+            if same {
+                last.repeated += 1;
+                if self.interval(last.level).is_some_and(|interval| last.reported.elapsed() >= interval) {
+                    write_notice(now, last, log_line_writer)?;
+                    last.repeated = 0;
+                    last.reported = Instant::now();
+                }
+                return Ok(false);
+            }
+            if last.repeated > 0 {
+                write_notice(now, last, log_line_writer)?;
+            }
+        }
+        *last = self.interval(record.level()).map(|_| LastRecord {
+            level: record.level(),
+            module_path: record.module_path().map(String::from),
+            target: record.target().into(),
+            line: record.line(),
+            message,
+            repeated: 0,
+            reported: Instant::now(),
+        });
+        Ok(true)
+    }
+}
+
+fn write_notice(now: &mut DeferredNow, last: &LastRecord, log_line_writer: &dyn LogLineWriter) -> std::io::Result<()> {
+    let notice = format!("last message repeated {} times", last.repeated);
+    log_line_writer.write(now, &log::Record::builder()
+        .args(format_args!("{}", notice))
+        .level(last.level)
+        .target(&last.target)
+        .module_path(last.module_path.as_deref())
+        .line(last.line)
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Collect(Mutex<Vec<String>>);
+    impl LogLineWriter for Collect {
+        fn write(&self, _now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+            self.0.lock().unwrap().push(record.args().to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn repeated_records() {
+        let mut dedup = Dedup::default();
+        dedup.set_interval(log::Level::Warn, Duration::from_secs(3600));
+        let output = Collect(Mutex::new(Vec::new()));
+        let log = |record: &log::Record| {
+            if dedup.admit(&mut DeferredNow::new(), record, &output).unwrap() {
+                output.write(&mut DeferredNow::new(), record).unwrap();
+            }
+        };
+        for (level, message) in [
+            (log::Level::Warn, "timeout"),
+            (log::Level::Warn, "timeout"),
+            (log::Level::Warn, "timeout"),
+            (log::Level::Warn, "refused"),
+            (log::Level::Info, "connected"),
+            (log::Level::Info, "connected"),
+        ] {
+            log(&log::Record::builder().level(level).target("app").args(format_args!("{}", message)).build());
+        }
+        assert_eq!(*output.0.lock().unwrap(), ["timeout", "last message repeated 2 times", "refused", "connected", "connected"]);
+    }
+
+    #[test]
+    fn notice_per_interval() {
+        let mut dedup = Dedup::default();
+        dedup.set_interval(log::Level::Warn, Duration::ZERO);
+        let output = Collect(Mutex::new(Vec::new()));
+        let log = |record: &log::Record| {
+            if dedup.admit(&mut DeferredNow::new(), record, &output).unwrap() {
+                output.write(&mut DeferredNow::new(), record).unwrap();
+            }
+        };
+        for _ in 0..3 {
+            log(&log::Record::builder().level(log::Level::Warn).target("app").args(format_args!("timeout")).build());
+        }
+        assert_eq!(*output.0.lock().unwrap(), ["timeout", "last message repeated 1 times", "last message repeated 1 times"]);
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+/// Why a record passing the thresholds was not written, see `LogStats::dropped`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DropReason {
+    /// `LogConfig::sample()`
+    Sampling,
+    /// `LogConfig::throttle()`
+    Throttling,
+    /// `LogConfig::quota()`
+    Quota,
+    /// `LogConfig::burst()`
+    Burst,
+    /// `LogConfig::adapt_to_load()`
+    Load,
+    /// Full queue of `LogConfig::with_async()`.
+    AsyncOverflow,
+}
+impl DropReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            DropReason::Sampling => "sampling",
+            DropReason::Throttling => "throttling",
+            DropReason::Quota => "quota",
+            DropReason::Burst => "burst muting",
+            DropReason::Load => "load shedding",
+            DropReason::AsyncOverflow => "async queue overflow",
+        }
+    }
+}
+
+struct Drops {
+    totals: BTreeMap<DropReason, u64>,
+    /// Drops since the last summary by reason, level and target. The async writer only
+    /// knows the formatted lines, its drops have no level and target.
+    pending: HashMap<(DropReason, Option<log::Level>, String), u64>,
+    summarized: Instant,
+}
+
+lazy_static! {
+    static ref DROPS: Mutex<Drops> = Mutex::new(Drops { totals: BTreeMap::new(), pending: HashMap::new(), summarized: Instant::now() });
+}
+/// Set when `pending` is not empty, spares the lock on every record.
+static PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Counts a dropped record.
+pub(crate) fn count(reason: DropReason, record: Option<&log::Record>) {
+    let mut drops = DROPS.lock().unwrap();
+    *drops.totals.entry(reason).or_default() += 1;
+    let key = (reason, record.map(|record| record.level()), record.map_or("", |record| record.target()).to_string());
+    *drops.pending.entry(key).or_default() += 1;
+    PENDING.store(true, Ordering::Relaxed);
+}
+
+/// Records dropped since `init()` per reason.
+pub(crate) fn totals() -> BTreeMap<DropReason, u64> {
+    DROPS.lock().unwrap().totals.clone()
+}
+
+/// Notice about the records dropped since the previous one, once `interval` has passed,
+/// e.g. "dropped 1523 RpcData trace records by throttling in the last 60s".
+pub(crate) fn summary(interval: Duration) -> Option<String> {
+    if !PENDING.load(Ordering::Relaxed) {
+        return None;
+    }
+    let mut drops = DROPS.lock().unwrap();
+    let elapsed = drops.summarized.elapsed();
+    if elapsed < interval {
+        return None;
+    }
+    let mut pending: Vec<_> = drops.pending.drain().collect();
+    drops.summarized = Instant::now();
+    PENDING.store(false, Ordering::Relaxed);
+    drop(drops);
+    // the most frequent first
+    pending.sort_by(|(a_key, a_count), (b_key, b_count)| b_count.cmp(a_count).then_with(|| a_key.cmp(b_key)));
+    let parts: Vec<_> = pending.iter()
+        .map(|((reason, level, target), count)| {
+            let mut part = count.to_string();
+            if !target.is_empty() {
+                part = format!("{} {}", part, target);
+            }
+            if let Some(level) = level {
+                part = format!("{} {}", part, level.as_str().to_ascii_lowercase());
+            }
+            format!("{} records by {}", part, reason.as_str())
+        })
+        .collect();
+    let elapsed = if elapsed.as_secs() == 0 { format!("{}ms", elapsed.as_millis()) } else { format!("{}s", elapsed.as_secs()) };
+    Some(format!("dropped {} in the last {}", parts.join(", "), elapsed))
+}
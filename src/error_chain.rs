@@ -0,0 +1,19 @@
+use std::error::Error;
+use std::fmt;
+
+/// Error followed by its causes, one per indented `caused by:` line, see `log_error_chain!`.
+///
+/// Works with any `&dyn Error`, pass `err.as_ref()` for `anyhow::Error` or `Box<dyn Error>`.
+/// The chain is rendered when the record is formatted, after the filtering.
+pub struct ErrorChain<'a>(pub &'a (dyn Error + 'a));
+impl fmt::Display for ErrorChain<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)?;
+        let mut source = self.0.source();
+        while let Some(cause) = source {
+            write!(f, "\n  caused by: {}", cause)?;
+            source = cause.source();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use flexi_logger::filter::LogLineWriter;
+use flexi_logger::DeferredNow;
+
+/// Rule emitting a synthesized Error when a target logs too many warnings in a time window.
+pub(crate) struct EscalationRule {
+    target: String,
+    max_count: usize,
+    window: Duration,
+    hits: Mutex<VecDeque<Instant>>,
+}
+impl EscalationRule {
+    pub(crate) fn new(target: &str, max_count: usize, window: Duration) -> EscalationRule {
+        EscalationRule {
+            target: target.into(),
+            max_count,
+            window,
+            hits: Mutex::new(VecDeque::new()),
+        }
+    }
+    pub(crate) fn check(&self, now: &mut DeferredNow, record: &log::Record, log_line_writer: &dyn LogLineWriter) -> std::io::Result<()> {
+        if record.level() != log::Level::Warn || !record.target().contains(&self.target) {
+            return Ok(());
+        }
+        let count = {
+            let mut hits = self.hits.lock().unwrap();
+            let instant = Instant::now();
+            while let Some(first) = hits.front() {
+                if instant.duration_since(*first) > self.window {
+                    hits.pop_front();
+                } else {
+                    break;
+                }
+            }
+            hits.push_back(instant);
+            if hits.len() <= self.max_count {
+                return Ok(());
+            }
+            let count = hits.len();
+            hits.clear();
+            count
+        };
+        let summary = format!(
+            "{} warnings from target '{}' within {}s, last one: {}",
+            count,
+            self.target,
+            self.window.as_secs(),
+            record.args()
+        );
+        log_line_writer.write(now, &log::Record::builder()
+            .args(format_args!("{}", summary))
+            .level(log::Level::Error)
+            .target(record.target())
+            .module_path(record.module_path())
+            .file(record.file())
+            .line(record.line())
+            .build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Collect(Mutex<Vec<String>>);
+    impl LogLineWriter for Collect {
+        fn write(&self, _now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+            self.0.lock().unwrap().push(format!("{} {}", record.level(), record.args()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn warnings_within_window() {
+        let rule = EscalationRule::new("modbus", 2, Duration::from_secs(1));
+        let output = Collect(Mutex::new(Vec::new()));
+        let log = |record: &log::Record| rule.check(&mut DeferredNow::new(), record, &output).unwrap();
+        for (level, target, message) in [
+            (log::Level::Warn, "modbus", "a"),
+            (log::Level::Info, "modbus", "info"),
+            (log::Level::Warn, "rpc", "other"),
+            (log::Level::Warn, "modbus::rtu", "b"),
+            (log::Level::Warn, "modbus", "c"),
+            (log::Level::Warn, "modbus", "d"),
+            (log::Level::Warn, "modbus", "e"),
+            (log::Level::Warn, "modbus", "f"),
+        ] {
+            if message == "f" {
+                // the hits of "d" and "e" are outside of the window
+                rule.hits.lock().unwrap().iter_mut().for_each(|hit| *hit -= Duration::from_secs(2));
+            }
+            log(&log::Record::builder().level(level).target(target).args(format_args!("{}", message)).build());
+        }
+        assert_eq!(*output.0.lock().unwrap(), ["ERROR 3 warnings from target 'modbus' within 1s, last one: c"]);
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+
+/// Rule deciding the threshold of a record, see `LogConfig::explain()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MatchedRule {
+    /// Key of the matching `-d` rule.
+    Module(String),
+    /// Key of the matching `-v` rule.
+    Target(String),
+    /// Key of the matching `module@target` rule.
+    ModuleTarget(String),
+    /// No rule matched, the default threshold applies.
+    Default,
+    /// Audit records are never filtered.
+    Audit,
+}
+
+/// Result of a dry-run rule matching.
+///
+/// Only the static module and target rules are considered, key-value field rules,
+/// triggers, quotas and load adaptation depend on the actual record and runtime state.
+#[derive(Clone, Debug)]
+pub struct Explanation {
+    pub module: String,
+    pub target: String,
+    pub level: log::Level,
+    pub threshold: log::LevelFilter,
+    pub rule: MatchedRule,
+    pub passes: bool,
+}
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} record from module '{}', target '{}': ", self.level, self.module, self.target)?;
+        match &self.rule {
+            MatchedRule::Module(key) => write!(f, "module rule '{}:{}' matched", key, self.threshold)?,
+            MatchedRule::Target(key) => write!(f, "target rule '{}:{}' matched", key, self.threshold)?,
+            MatchedRule::ModuleTarget(key) => write!(f, "module@target rule '{}:{}' matched", key, self.threshold)?,
+            MatchedRule::Default if self.module == self.target => write!(f, "no module rule matched, default threshold {}", self.threshold)?,
+            MatchedRule::Default => write!(f, "no target rule matched, default threshold {}", self.threshold)?,
+            MatchedRule::Audit => return write!(f, "audit record, always written"),
+        }
+        if self.passes {
+            write!(f, ", written")
+        } else {
+            write!(f, ", dropped")
+        }
+    }
+}
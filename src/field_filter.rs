@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::AtomicU64;
+
+use log::kv::Key;
+
+enum FieldMatch {
+    Equals(String),
+    Contains(String),
+    StartsWith(String),
+}
+
+/// Threshold applied to records carrying a matching key-value field.
+///
+/// Rule keys have the form `key=value` (exact match) or `key~value`
+/// (substring match, a leading `^` anchors it to the start of the value).
+pub(crate) struct FieldRule {
+    key: String,
+    pattern: FieldMatch,
+    pub(crate) level: log::LevelFilter,
+    /// Records whose threshold the rule decided.
+    pub(crate) hits: AtomicU64,
+}
+impl FieldRule {
+    pub(crate) fn is_field_rule(key: &str) -> bool {
+        key.contains('=') || key.contains('~')
+    }
+    fn parse(rule: &str, level: log::LevelFilter) -> Option<FieldRule> {
+        let (key, pattern) = if let Some((key, value)) = rule.split_once('=') {
+            (key, FieldMatch::Equals(value.into()))
+        } else {
+            let (key, value) = rule.split_once('~')?;
+            match value.strip_prefix('^') {
+                Some(prefix) => (key, FieldMatch::StartsWith(prefix.into())),
+                None => (key, FieldMatch::Contains(value.into())),
+            }
+        };
+        Some(FieldRule { key: key.into(), pattern, level, hits: AtomicU64::new(0) })
+    }
+    /// Moves the field rules out of parsed `levels`, ordered by rule string.
+    pub(crate) fn extract(levels: &mut HashMap<String, log::LevelFilter>) -> Vec<FieldRule> {
+        let mut keys: Vec<String> = levels.keys().filter(|key| FieldRule::is_field_rule(key)).cloned().collect();
+        keys.sort();
+        keys.into_iter()
+            .filter_map(|key| {
+                let level = levels.remove(&key)?;
+                FieldRule::parse(&key, level)
+            })
+            .collect()
+    }
+    /// Level of the rule `rule` among `rules`.
+    pub(crate) fn get(rules: &[FieldRule], rule: &str) -> Option<log::LevelFilter> {
+        let rule = FieldRule::parse(rule, log::LevelFilter::Off)?.to_string();
+        rules.iter().find(|other| other.to_string() == rule).map(|other| other.level)
+    }
+    /// Adds the rule `rule` to `rules` ordered like `extract()`, replacing the level of an
+    /// equal rule.
+    pub(crate) fn insert(rules: &mut Vec<FieldRule>, rule: &str, level: log::LevelFilter) {
+        let Some(rule) = FieldRule::parse(rule, level) else {
+            return;
+        };
+        let key = rule.to_string();
+        match rules.binary_search_by(|other| other.to_string().cmp(&key)) {
+            Ok(ix) => rules[ix].level = level,
+            Err(ix) => rules.insert(ix, rule),
+        }
+    }
+    pub(crate) fn remove(rules: &mut Vec<FieldRule>, rule: &str) {
+        if let Some(rule) = FieldRule::parse(rule, log::LevelFilter::Off).map(|rule| rule.to_string()) {
+            rules.retain(|other| other.to_string() != rule);
+        }
+    }
+    pub(crate) fn matches(&self, record: &log::Record) -> bool {
+        let Some(value) = record.key_values().get(Key::from_str(&self.key)) else {
+            return false;
+        };
+        let value = value.to_string();
+        match &self.pattern {
+            FieldMatch::Equals(expected) => value == *expected,
+            FieldMatch::Contains(part) => value.contains(part.as_str()),
+            FieldMatch::StartsWith(prefix) => value.starts_with(prefix.as_str()),
+        }
+    }
+}
+impl fmt::Display for FieldRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.pattern {
+            FieldMatch::Equals(value) => write!(f, "{}={}", self.key, value),
+            FieldMatch::Contains(value) => write!(f, "{}~{}", self.key, value),
+            FieldMatch::StartsWith(value) => write!(f, "{}~^{}", self.key, value),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use log::kv::{Error, Key, Value, VisitSource};
+
+use crate::{FieldProvider, ERROR_CODE_KEY};
+
+lazy_static! {
+    /// Fields added to every record by `LogConfig::with_field()` and `with_field_fn()`.
+    static ref ENRICHMENT: RwLock<Vec<(String, FieldProvider)>> = RwLock::new(Vec::new());
+}
+
+struct Collect<'kvs>(Vec<(Key<'kvs>, Value<'kvs>)>);
+impl<'kvs> VisitSource<'kvs> for Collect<'kvs> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        if key.as_str() != ERROR_CODE_KEY {
+            self.0.push((key, value));
+        }
+        Ok(())
+    }
+}
+
+/// Key-value fields of the record except the error code, which is rendered separately.
+pub(crate) fn record_fields<'a>(record: &'a log::Record) -> Vec<(Key<'a>, Value<'a>)> {
+    let mut fields = Collect(Vec::new());
+    let _ = record.key_values().visit(&mut fields);
+    fields.0
+}
+
+pub(crate) fn set_enrichment(fields: Vec<(String, FieldProvider)>) {
+    *ENRICHMENT.write().unwrap() = fields;
+}
+
+/// Names and current values of the fields added to every record.
+pub(crate) fn enrichment() -> Vec<(String, String)> {
+    ENRICHMENT.read().unwrap().iter().map(|(name, provider)| (name.clone(), provider())).collect()
+}
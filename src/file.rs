@@ -0,0 +1,463 @@
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use flexi_logger::writers::{FileLogWriter, LogWriter};
+use flexi_logger::{Age, Cleanup, Criterion, DeferredNow, FileSpec, FlexiLoggerError, FormatFunction, Naming, WriteMode};
+use lazy_static::lazy_static;
+
+use crate::{LogFormat, ModuleDisplay, Multiline};
+
+/// Time based rotation interval of a log file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RotationAge {
+    Hour,
+    Day,
+}
+
+/// When the file output makes the written records durable with fsync, see `FileLog::fsync()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fsync {
+    /// Left to the OS, the default.
+    Never,
+    /// After every record.
+    Always,
+    /// After every `n` records.
+    Records(u32),
+    /// At the first record or periodic flush once the interval elapsed since the last sync.
+    Interval(Duration),
+    /// After every Error record, the records before it included.
+    OnError,
+}
+
+/// File output with optional rotation, see `LogConfig::with_file()`.
+///
+/// Without rotation records go to the configured path. With rotation the current
+/// file is `app_rCURRENT.log` and rotated files are numbered `app_r00001.log`, ...
+#[derive(Clone, Debug)]
+pub struct FileLog {
+    path: PathBuf,
+    max_size: Option<u64>,
+    max_age: Option<RotationAge>,
+    keep_files: usize,
+    pub(crate) max_line_len: Option<usize>,
+    pub(crate) module_display: ModuleDisplay,
+    pub(crate) show_line: bool,
+    pub(crate) show_file: bool,
+    pub(crate) multiline: Multiline,
+    pub(crate) format: Option<LogFormat>,
+    pub(crate) level: Option<log::Level>,
+    #[cfg(feature = "compress")]
+    compress: bool,
+    #[cfg(unix)]
+    pub(crate) ownership: Ownership,
+    fsync: Fsync,
+    crlf: bool,
+}
+impl FileLog {
+    pub fn new(path: impl Into<PathBuf>) -> FileLog {
+        FileLog {
+            path: path.into(),
+            max_size: None,
+            max_age: None,
+            keep_files: 0,
+            max_line_len: None,
+            module_display: ModuleDisplay::Full,
+            show_line: true,
+            show_file: false,
+            multiline: Multiline::AsIs,
+            format: None,
+            level: None,
+            #[cfg(feature = "compress")]
+            compress: false,
+            #[cfg(unix)]
+            ownership: Ownership::default(),
+            fsync: Fsync::Never,
+            crlf: false,
+        }
+    }
+    /// Rotates the file when it grows over `bytes`.
+    pub fn rotate_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+    /// Rotates the file every hour or day.
+    pub fn rotate_age(mut self, age: RotationAge) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+    /// Number of rotated files to keep, older ones are deleted. All are kept when 0.
+    pub fn keep_files(mut self, count: usize) -> Self {
+        self.keep_files = count;
+        self
+    }
+    /// Skips records less severe than `level`, see `Syslog::level()`.
+    pub fn level(mut self, level: log::Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+    /// Truncates messages longer than `max_len` bytes in the file, see `LogConfig::max_line_len()`.
+    pub fn max_line_len(mut self, max_len: usize) -> Self {
+        self.max_line_len = Some(max_len);
+        self
+    }
+    /// Module path in the file, see `LogConfig::module_display()`.
+    pub fn module_display(mut self, display: ModuleDisplay) -> Self {
+        self.module_display = display;
+        self
+    }
+    /// Shows the line number next to the module path in the file, on by default.
+    pub fn show_line(mut self, show: bool) -> Self {
+        self.show_line = show;
+        self
+    }
+    /// Shows the source file path in the file, see `LogConfig::show_file()`.
+    pub fn show_file(mut self, show: bool) -> Self {
+        self.show_file = show;
+        self
+    }
+    /// Layout of multi-line messages in the file, see `LogConfig::multiline()`.
+    pub fn multiline(mut self, multiline: Multiline) -> Self {
+        self.multiline = multiline;
+        self
+    }
+    /// Format of the records in the file, e.g. `LogFormat::Json` while stderr shows colored
+    /// text. The format set by `LogConfig::with_log_format()` by default.
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+    /// Gzips rotated files.
+    #[cfg(feature = "compress")]
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+    /// Mode bits of the log files, e.g. `0o640`, regardless of the umask. Also set on existing
+    /// files and on the files created by rotation, on the first record written to them.
+    #[cfg(unix)]
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.ownership.mode = Some(mode);
+        self
+    }
+    /// Mode bits of the directory of the log files if it is created, e.g. `0o750`.
+    #[cfg(unix)]
+    pub fn dir_mode(mut self, mode: u32) -> Self {
+        self.ownership.dir_mode = Some(mode);
+        self
+    }
+    /// Group owning the log files and the created directory, the process must be a member of
+    /// it or privileged.
+    #[cfg(unix)]
+    pub fn group(mut self, gid: u32) -> Self {
+        self.ownership.group = Some(gid);
+        self
+    }
+    /// Syncs the log file to disk by `policy`, e.g. `Fsync::OnError` so that the last records
+    /// before a power loss are on disk, at the cost of throughput. The buffered records are
+    /// written to the file first.
+    pub fn fsync(mut self, policy: Fsync) -> Self {
+        self.fsync = policy;
+        self
+    }
+    /// Ends the records with `\r\n` instead of `\n`, e.g. for logs read on Windows.
+    pub fn windows_line_endings(mut self) -> Self {
+        self.crlf = true;
+        self
+    }
+    fn cleanup(&self) -> Cleanup {
+        #[cfg(feature = "compress")]
+        if self.compress {
+            return Cleanup::KeepCompressedFiles(if self.keep_files == 0 { usize::MAX } else { self.keep_files });
+        }
+        if self.keep_files == 0 { Cleanup::Never } else { Cleanup::KeepLogFiles(self.keep_files) }
+    }
+    fn rotates(&self) -> bool {
+        self.max_age.is_some() || self.max_size.is_some()
+    }
+    /// Writer of the file output with records rendered by `format`, `reopen()` then reopens it.
+    pub(crate) fn writer(&self, format: FormatFunction, write_mode: WriteMode) -> Result<FileWriter, FlexiLoggerError> {
+        let output = Arc::new(self.output(format, write_mode)?);
+        *CURRENT.lock().unwrap() = Arc::downgrade(&output);
+        Ok(FileWriter(output))
+    }
+    fn output(&self, format: FormatFunction, write_mode: WriteMode) -> Result<FileOutput, FlexiLoggerError> {
+        let file_spec = FileSpec::try_from(&self.path)?.suppress_timestamp();
+        let output = FileOutput {
+            path: file_spec.as_pathbuf(self.rotates().then_some("_rCURRENT")),
+            #[cfg(unix)]
+            rotates: self.rotates(),
+            fsync: self.fsync,
+            sync_state: Mutex::new(SyncState { unsynced: 0, last_sync: Instant::now() }),
+            #[cfg(unix)]
+            ownership: self.ownership,
+            #[cfg(unix)]
+            inode: AtomicU64::new(0),
+            writer: self.file_writer(file_spec, format, write_mode)?,
+        };
+        #[cfg(unix)]
+        output.apply_ownership()?;
+        Ok(output)
+    }
+    fn file_writer(&self, file_spec: FileSpec, format: FormatFunction, write_mode: WriteMode) -> Result<FileLogWriter, FlexiLoggerError> {
+        #[cfg(unix)]
+        self.ownership.create_dir(&file_spec.as_pathbuf(None))?;
+        #[cfg(unix)]
+        self.ownership.create_file(&file_spec.as_pathbuf(self.rotates().then_some("_rCURRENT")))?;
+        let mut builder = FileLogWriter::builder(file_spec).format(format).write_mode(write_mode).append();
+        if self.crlf {
+            builder = builder.use_windows_line_ending();
+        }
+        let age = self.max_age.map(|age| match age {
+            RotationAge::Hour => Age::Hour,
+            RotationAge::Day => Age::Day,
+        });
+        let criterion = match (age, self.max_size) {
+            (Some(age), Some(size)) => Criterion::AgeOrSize(age, size),
+            (Some(age), None) => Criterion::Age(age),
+            (None, Some(size)) => Criterion::Size(size),
+            (None, None) => return builder.try_build(),
+        };
+        builder.rotate(criterion, Naming::Numbers, self.cleanup()).try_build()
+    }
+}
+
+/// Mode bits and group of the log files, see `FileLog::mode()`.
+#[cfg(unix)]
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Ownership {
+    pub(crate) mode: Option<u32>,
+    pub(crate) dir_mode: Option<u32>,
+    pub(crate) group: Option<u32>,
+}
+#[cfg(unix)]
+impl Ownership {
+    fn is_set(&self) -> bool {
+        self.mode.is_some() || self.group.is_some()
+    }
+    /// Creates the directory of `path` with the mode and group if it does not exist.
+    fn create_dir(&self, path: &Path) -> std::io::Result<()> {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+        let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty() && !dir.exists()) else {
+            return Ok(());
+        };
+        std::fs::DirBuilder::new().recursive(true).mode(self.dir_mode.unwrap_or(0o777)).create(dir)?;
+        if let Some(mode) = self.dir_mode {
+            std::fs::set_permissions(dir, std::fs::Permissions::from_mode(mode))?;
+        }
+        if self.group.is_some() {
+            std::os::unix::fs::chown(dir, None, self.group)?;
+        }
+        Ok(())
+    }
+    /// Creates the log file with the mode, so that it is never readable by others meanwhile.
+    fn create_file(&self, path: &Path) -> std::io::Result<()> {
+        use std::os::unix::fs::OpenOptionsExt;
+        if let Some(mode) = self.mode {
+            std::fs::OpenOptions::new().create(true).append(true).mode(mode).open(path)?;
+        }
+        Ok(())
+    }
+    fn apply(&self, path: &Path) -> std::io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        if let Some(mode) = self.mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+        if self.group.is_some() {
+            std::os::unix::fs::chown(path, None, self.group)?;
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    /// File output of the running logger.
+    static ref CURRENT: Mutex<Weak<FileOutput>> = Mutex::new(Weak::new());
+}
+
+/// Reopens the log file of the file output, e.g. after logrotate moved it away. Does nothing
+/// without file output.
+pub(crate) fn reopen() -> std::io::Result<()> {
+    let Some(output) = CURRENT.lock().unwrap().upgrade() else {
+        return Ok(());
+    };
+    #[cfg(unix)]
+    output.ownership.create_file(&output.path)?;
+    output.writer.reopen_outputfile().map_err(std::io::Error::other)?;
+    #[cfg(unix)]
+    output.apply_ownership()?;
+    Ok(())
+}
+
+struct FileOutput {
+    writer: FileLogWriter,
+    /// The file written to, `app_rCURRENT.log` with rotation.
+    path: PathBuf,
+    #[cfg(unix)]
+    rotates: bool,
+    #[cfg(unix)]
+    ownership: Ownership,
+    /// Inode of `path` when the ownership was applied, a rotation creates a new one.
+    #[cfg(unix)]
+    inode: AtomicU64,
+    fsync: Fsync,
+    sync_state: Mutex<SyncState>,
+}
+struct SyncState {
+    /// Records written since the last sync.
+    unsynced: u32,
+    last_sync: Instant,
+}
+impl FileOutput {
+    /// Writes out the buffered records and syncs the file, the current one after a rotation.
+    fn sync(&self, state: &mut SyncState) -> std::io::Result<()> {
+        self.writer.flush()?;
+        std::fs::OpenOptions::new().append(true).open(&self.path)?.sync_data()?;
+        state.unsynced = 0;
+        state.last_sync = Instant::now();
+        Ok(())
+    }
+    #[cfg(unix)]
+    fn apply_ownership(&self) -> std::io::Result<()> {
+        use std::os::unix::fs::MetadataExt;
+        if !self.ownership.is_set() {
+            return Ok(());
+        }
+        let inode = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata.ino(),
+            // created on the first record
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        if self.inode.swap(inode, Ordering::Relaxed) != inode {
+            self.ownership.apply(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The file output, shared with `reopen()`.
+pub(crate) struct FileWriter(Arc<FileOutput>);
+impl LogWriter for FileWriter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        self.0.writer.write(now, record)?;
+        #[cfg(unix)]
+        if self.0.rotates {
+            self.0.apply_ownership()?;
+        }
+        if self.0.fsync == Fsync::Never {
+            return Ok(());
+        }
+        let mut state = self.0.sync_state.lock().unwrap();
+        state.unsynced += 1;
+        let sync = match self.0.fsync {
+            Fsync::Never => false,
+            Fsync::Always => true,
+            Fsync::Records(count) => state.unsynced >= count,
+            Fsync::Interval(interval) => state.last_sync.elapsed() >= interval,
+            Fsync::OnError => record.level() == log::Level::Error,
+        };
+        if sync {
+            self.0.sync(&mut state)?;
+        }
+        Ok(())
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        if let Fsync::Interval(interval) = self.0.fsync {
+            let mut state = self.0.sync_state.lock().unwrap();
+            if state.unsynced > 0 && state.last_sync.elapsed() >= interval {
+                return self.0.sync(&mut state);
+            }
+        }
+        self.0.writer.flush()
+    }
+    fn shutdown(&self) {
+        self.0.writer.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(writer: &FileWriter, message: &str) {
+        writer.write(&mut DeferredNow::new(), &log::Record::builder().args(format_args!("{}", message)).build()).unwrap();
+        writer.flush().unwrap();
+    }
+
+    #[test]
+    fn reopen_after_move() {
+        let dir = std::env::temp_dir().join(format!("shvlog-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        let writer = FileLog::new(&path).writer(flexi_logger::default_format, WriteMode::Direct).unwrap();
+        write(&writer, "before");
+        std::fs::rename(&path, dir.join("app.log.1")).unwrap();
+        write(&writer, "moved");
+        reopen().unwrap();
+        write(&writer, "after");
+        assert!(std::fs::read_to_string(dir.join("app.log.1")).unwrap().contains("moved"));
+        let current = std::fs::read_to_string(&path).unwrap();
+        assert!(!current.contains("moved") && current.contains("after"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fsync() {
+        let dir = std::env::temp_dir().join(format!("shvlog-fsync-{}", std::process::id()));
+        for (ix, &(policy, synced)) in [(Fsync::Always, 0), (Fsync::Records(2), 1), (Fsync::OnError, 3), (Fsync::Never, 0)].iter().enumerate() {
+            let path = dir.join(format!("app{}.log", ix));
+            let output = FileLog::new(&path).fsync(policy)
+                .output(flexi_logger::default_format, WriteMode::BufferDontFlushWith(1000))
+                .unwrap();
+            let writer = FileWriter(Arc::new(output));
+            for level in [log::Level::Info, log::Level::Info, log::Level::Warn] {
+                writer.write(&mut DeferredNow::new(), &log::Record::builder().level(level).args(format_args!("")).build()).unwrap();
+            }
+            assert_eq!(writer.0.sync_state.lock().unwrap().unsynced, synced, "{:?}", policy);
+            writer.write(&mut DeferredNow::new(), &log::Record::builder().level(log::Level::Error).args(format_args!("")).build()).unwrap();
+            if policy == Fsync::OnError {
+                // the buffered records were written out before the sync
+                assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 4);
+                assert_eq!(writer.0.sync_state.lock().unwrap().unsynced, 0);
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn windows_line_endings() {
+        let dir = std::env::temp_dir().join(format!("shvlog-crlf-{}", std::process::id()));
+        let path = dir.join("app.log");
+        let writer = FileWriter(Arc::new(FileLog::new(&path).windows_line_endings().output(flexi_logger::default_format, WriteMode::Direct).unwrap()));
+        write(&writer, "one");
+        write(&writer, "two");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "INFO [<unnamed>] one\r\nINFO [<unnamed>] two\r\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("shvlog-mode-{}", std::process::id()));
+        let path = dir.join("logs").join("app.log");
+        let output = FileLog::new(&path).rotate_size(10).mode(0o640).dir_mode(0o750)
+            .output(flexi_logger::default_format, WriteMode::Direct)
+            .unwrap();
+        let writer = FileWriter(Arc::new(output));
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir.join("logs")), 0o750);
+        let current = dir.join("logs").join("app_rCURRENT.log");
+        assert_eq!(mode(&current), 0o640);
+        write(&writer, "over ten bytes");
+        write(&writer, "rotated");
+        assert_eq!(mode(&dir.join("logs").join("app_r00000.log")), 0o640);
+        assert_eq!(mode(&current), 0o640);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
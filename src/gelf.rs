@@ -0,0 +1,255 @@
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use flexi_logger::writers::LogWriter;
+use flexi_logger::DeferredNow;
+
+use crate::json::{JsonStr, JsonValue};
+use crate::{context, fields, scope, ShvSeverity, ERROR_CODE_KEY};
+
+/// Chunks are prefixed with the magic bytes, message ID, sequence number and count.
+const CHUNK_HEADER_LEN: usize = 12;
+/// Graylog discards messages split into more chunks.
+const MAX_CHUNKS: usize = 128;
+
+#[derive(Clone, Debug)]
+enum Transport {
+    Udp(String),
+    Tcp(String),
+}
+
+/// GELF 1.1 output for Graylog, see `LogConfig::with_gelf()`.
+///
+/// The module, target, line, file, application name, process ID, device ID and the
+/// key-value fields of the record are sent as additional fields with the `_` prefix.
+#[derive(Clone, Debug)]
+pub struct Gelf {
+    transport: Transport,
+    fields: Vec<(String, String)>,
+    chunk_size: usize,
+    pub(crate) level: Option<log::Level>,
+}
+impl Gelf {
+    /// Sends records as UDP datagrams to `addr`, e.g. `"graylog:12201"`, chunked when longer
+    /// than `chunk_size()`.
+    pub fn udp(addr: &str) -> Gelf {
+        Gelf::new(Transport::Udp(addr.into()))
+    }
+    /// Streams null byte delimited records over TCP to `addr`.
+    pub fn tcp(addr: &str) -> Gelf {
+        Gelf::new(Transport::Tcp(addr.into()))
+    }
+    fn new(transport: Transport) -> Gelf {
+        Gelf { transport, fields: Vec::new(), chunk_size: 8192, level: None }
+    }
+    /// Skips records less severe than `level`, see `Syslog::level()`.
+    pub fn level(mut self, level: log::Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+    /// Static additional field sent with every record, e.g. `field("site", "brno")`.
+    pub fn field(mut self, name: &str, value: &str) -> Self {
+        self.fields.push((field_name(name), value.into()));
+        self
+    }
+    /// Maximal UDP datagram size, 8192 bytes by default, use about 1420 across a WAN.
+    pub fn chunk_size(mut self, bytes: usize) -> Self {
+        self.chunk_size = bytes.max(CHUNK_HEADER_LEN + 1);
+        self
+    }
+    pub(crate) fn writer(&self, app_name: Option<&str>, hostname: &str, device_id: Option<&str>) -> std::io::Result<GelfWriter> {
+        let mut fields = String::new();
+        if let Some(app_name) = app_name {
+            fields.push_str(&format!(",\"_app\":{}", JsonStr(app_name)));
+        }
+        fields.push_str(&format!(",\"_pid\":{}", std::process::id()));
+        if let Some(device_id) = device_id {
+            fields.push_str(&format!(",\"_device_id\":{}", JsonStr(device_id)));
+        }
+        for (name, value) in &self.fields {
+            fields.push_str(&format!(",{}:{}", JsonStr(name), JsonStr(value)));
+        }
+        Ok(GelfWriter {
+            connection: Mutex::new(Connection::open(&self.transport)?),
+            transport: self.transport.clone(),
+            chunk_size: self.chunk_size,
+            host: JsonStr(hostname).to_string(),
+            fields,
+            message_id: AtomicU64::new(0),
+        })
+    }
+}
+
+/// Additional field name, `_` prefixed and limited to the characters allowed by GELF.
+fn field_name(name: &str) -> String {
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' }).collect();
+    // `_id` is reserved
+    match name.trim_start_matches('_') {
+        "id" => "__id".into(),
+        name => format!("_{}", name),
+    }
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+impl Connection {
+    fn open(transport: &Transport) -> std::io::Result<Connection> {
+        Ok(match transport {
+            Transport::Udp(addr) => {
+                let addr = addr.to_socket_addrs()?.next()
+                    .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("cannot resolve {}", addr)))?;
+                let socket = UdpSocket::bind(if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+                socket.connect(addr)?;
+                Connection::Udp(socket)
+            }
+            Transport::Tcp(addr) => Connection::Tcp(TcpStream::connect(addr)?),
+        })
+    }
+}
+
+pub(crate) struct GelfWriter {
+    connection: Mutex<Connection>,
+    transport: Transport,
+    chunk_size: usize,
+    /// JSON string of the hostname.
+    host: String,
+    /// Static additional fields as JSON members, each with the leading comma.
+    fields: String,
+    message_id: AtomicU64,
+}
+impl GelfWriter {
+    fn message(&self, now: &mut DeferredNow, record: &log::Record) -> String {
+        let text = format!("{}{}", scope::prefix(), record.args());
+        let (short_message, full_message) = match text.split_once('\n') {
+            Some((first_line, _)) => (first_line, Some(text.as_str())),
+            None => (text.as_str(), None),
+        };
+        let millis = now.now().timestamp_millis();
+        let mut message = format!(
+            "{{\"version\":\"1.1\",\"host\":{},\"short_message\":{},\"timestamp\":{}.{:03},\"level\":{}",
+            self.host,
+            JsonStr(short_message),
+            millis.div_euclid(1000),
+            millis.rem_euclid(1000),
+            ShvSeverity::of(record.level()).syslog_severity(),
+        );
+        if let Some(full_message) = full_message {
+            message.push_str(&format!(",\"full_message\":{}", JsonStr(full_message)));
+        }
+        message.push_str(&format!(
+            ",\"_module\":{},\"_target\":{}",
+            JsonStr(record.module_path().unwrap_or("")),
+            JsonStr(record.target()),
+        ));
+        if let Some(file) = record.file() {
+            message.push_str(&format!(",\"_file\":{}", JsonStr(file)));
+        }
+        if let Some(line) = record.line() {
+            message.push_str(&format!(",\"_line\":{}", line));
+        }
+        message.push_str(&self.fields);
+        if let Some(code) = record.key_values().get(log::kv::Key::from_str(ERROR_CODE_KEY)) {
+            message.push_str(&format!(",\"_{}\":{}", ERROR_CODE_KEY, JsonStr(&code.to_string())));
+        }
+        for (key, value) in fields::record_fields(record) {
+            message.push_str(&format!(",{}:{}", JsonStr(&field_name(key.as_str())), JsonValue(&value)));
+        }
+        for (key, value) in context::current().into_iter().chain(fields::enrichment()) {
+            message.push_str(&format!(",{}:{}", JsonStr(&field_name(&key)), JsonStr(&value)));
+        }
+        message.push('}');
+        message
+    }
+    fn send(&self, connection: &mut Connection, message: &[u8]) -> std::io::Result<()> {
+        match connection {
+            Connection::Udp(socket) if message.len() <= self.chunk_size => socket.send(message).map(|_| ()),
+            Connection::Udp(socket) => {
+                let payload_len = self.chunk_size - CHUNK_HEADER_LEN;
+                let count = message.len().div_ceil(payload_len);
+                if count > MAX_CHUNKS {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("GELF message of {} bytes needs more than {} chunks", message.len(), MAX_CHUNKS)));
+                }
+                let id = self.message_id().to_be_bytes();
+                for (seq, payload) in message.chunks(payload_len).enumerate() {
+                    let mut chunk = Vec::with_capacity(CHUNK_HEADER_LEN + payload.len());
+                    chunk.extend_from_slice(&[0x1e, 0x0f]);
+                    chunk.extend_from_slice(&id);
+                    chunk.extend_from_slice(&[seq as u8, count as u8]);
+                    chunk.extend_from_slice(payload);
+                    socket.send(&chunk)?;
+                }
+                Ok(())
+            }
+            Connection::Tcp(stream) => {
+                stream.write_all(message)?;
+                stream.write_all(b"\0")
+            }
+        }
+    }
+    /// Chunked message ID, unique per process and distinct across processes sending at once.
+    fn message_id(&self) -> u64 {
+        let counter = self.message_id.fetch_add(1, Ordering::Relaxed);
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|time| time.as_nanos() as u64).unwrap_or(0);
+        (nanos ^ (u64::from(std::process::id()) << 32)).wrapping_add(counter.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+}
+impl LogWriter for GelfWriter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        let message = self.message(now, record);
+        let mut connection = self.connection.lock().unwrap();
+        if let Err(err) = self.send(&mut connection, message.as_bytes()) {
+            if err.kind() == std::io::ErrorKind::InvalidInput {
+                return Err(err);
+            }
+            // Graylog may have been restarted, reconnect once
+            *connection = Connection::open(&self.transport)?;
+            self.send(&mut connection, message.as_bytes())?;
+        }
+        Ok(())
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        if let Connection::Tcp(stream) = &mut *self.connection.lock().unwrap() {
+            stream.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Datagrams received for `message` sent with chunk size 100, 88 payload bytes per chunk.
+    fn datagrams(message: &[u8]) -> std::io::Result<Vec<Vec<u8>>> {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(std::time::Duration::from_millis(200))).unwrap();
+        let writer = Gelf::udp(&receiver.local_addr().unwrap().to_string()).chunk_size(100).writer(None, "host", None).unwrap();
+        writer.send(&mut writer.connection.lock().unwrap(), message)?;
+        let mut datagrams = Vec::new();
+        let mut buf = [0; 200];
+        while let Ok(len) = receiver.recv(&mut buf) {
+            datagrams.push(buf[..len].to_vec());
+        }
+        Ok(datagrams)
+    }
+
+    #[test]
+    fn chunking() {
+        assert_eq!(datagrams(&[b'x'; 100]).unwrap(), [vec![b'x'; 100]]);
+        let chunks = datagrams(&[b'x'; 101]).unwrap();
+        assert_eq!(chunks.iter().map(Vec::len).collect::<Vec<_>>(), [100, 25]);
+        for (seq, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk[..2], [0x1e, 0x0f]);
+            assert_eq!(chunk[2..10], chunks[0][2..10]);
+            assert_eq!(chunk[10..12], [seq as u8, 2]);
+        }
+        let chunks = datagrams(&[b'x'; 88 * MAX_CHUNKS]).unwrap();
+        assert_eq!(chunks.len(), MAX_CHUNKS);
+        assert_eq!(chunks[MAX_CHUNKS - 1][10..12], [127, 128]);
+        assert_eq!(datagrams(&[b'x'; 88 * MAX_CHUNKS + 1]).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
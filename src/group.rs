@@ -0,0 +1,64 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::time::Instant;
+
+thread_local! {
+    static GROUP_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Nesting depth of log groups on the current thread.
+pub(crate) fn depth() -> usize {
+    GROUP_DEPTH.with(|depth| depth.get())
+}
+
+/// Guard of a log group created by `group!`, records logged from the same
+/// thread while it lives are indented. The end marker is emitted on drop.
+#[must_use = "the group ends when the guard is dropped"]
+pub struct LogGroup {
+    name: String,
+    target: &'static str,
+    module_path: &'static str,
+    file: &'static str,
+    line: u32,
+    start: Instant,
+    // the depth lives in a thread-local counter, the guard must stay on its thread
+    _not_send: PhantomData<*const ()>,
+}
+impl LogGroup {
+    #[doc(hidden)]
+    pub fn begin(name: String, target: &'static str, module_path: &'static str, file: &'static str, line: u32) -> LogGroup {
+        let group = LogGroup { name, target, module_path, file, line, start: Instant::now(), _not_send: PhantomData };
+        group.emit(format_args!("begin {}", group.name));
+        GROUP_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        group
+    }
+    fn emit(&self, args: std::fmt::Arguments) {
+        if log::Level::Info <= log::max_level() {
+            log::logger().log(&log::Record::builder()
+                .args(args)
+                .level(log::Level::Info)
+                .target(self.target)
+                .module_path_static(Some(self.module_path))
+                .file_static(Some(self.file))
+                .line(Some(self.line))
+                .build());
+        }
+    }
+}
+impl Drop for LogGroup {
+    fn drop(&mut self) {
+        GROUP_DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+        self.emit(format_args!("end {} ({:?})", self.name, self.start.elapsed()));
+    }
+}
+
+/// Opens a log group, e.g. `let _g = shvlog::group!("loading config");`.
+///
+/// Begin and end marker records are logged at Info and records logged in between
+/// from the same thread are indented in the text output and carry the nesting depth as
+/// `group_depth` in the JSON output.
+#[macro_export]
+macro_rules! group {
+    (target: $target:expr, $($arg:tt)+) => ($crate::LogGroup::begin(format!($($arg)+), $target, module_path!(), file!(), line!()));
+    ($($arg:tt)+) => ($crate::LogGroup::begin(format!($($arg)+), module_path!(), module_path!(), file!(), line!()));
+}
@@ -0,0 +1,348 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+
+use flexi_logger::filter::{LogLineFilter, LogLineWriter};
+use flexi_logger::{DeferredNow, LoggerHandle};
+
+use crate::rules::Rules;
+use crate::{GetLogParams, LogConfig, LogRecord, LogStats, ParseError, RecordReceiver, Reconfiguration, RuleInfo, SinkStats, Subscription, INSTANCE_IDENTITY};
+
+/// Filter installed into flexi_logger, delegating to the currently active config.
+pub(crate) struct SharedFilter(pub(crate) Arc<RwLock<LogConfig>>);
+impl LogLineFilter for SharedFilter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record, log_line_writer: &dyn LogLineWriter) -> std::io::Result<()> {
+        let result = self.0.read().unwrap().write(now, record, log_line_writer);
+        crate::subscribe::deliver();
+        result
+    }
+}
+
+/// The `log` logger, answers `enabled()` with the rules of the active config and passes the
+/// records to flexi_logger.
+pub(crate) struct ShvLogger {
+    pub(crate) inner: Box<dyn log::Log>,
+    pub(crate) config: Arc<RwLock<LogConfig>>,
+    /// Records at this level or more severe flush the buffered output, see `LogConfig::flush_on()`.
+    pub(crate) flush_level: log::LevelFilter,
+}
+impl log::Log for ShvLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level() && self.config.read().unwrap().enabled(metadata)
+    }
+    fn log(&self, record: &log::Record) {
+        self.inner.log(record);
+        if record.level() <= self.flush_level {
+            self.inner.flush();
+        }
+    }
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+/// The `log` logger of shvlog, installed before anything is started so that an init failing
+/// on another installed logger has no side effects, it forwards the records to the started
+/// `ShvLogger`.
+struct LoggerSlot;
+static SLOT: LoggerSlot = LoggerSlot;
+static STARTED: OnceLock<ShvLogger> = OnceLock::new();
+/// The slot is installed, possibly by an init that failed afterwards.
+static RESERVED: AtomicBool = AtomicBool::new(false);
+impl log::Log for LoggerSlot {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        STARTED.get().is_some_and(|logger| logger.enabled(metadata))
+    }
+    fn log(&self, record: &log::Record) {
+        if let Some(logger) = STARTED.get() {
+            logger.log(record);
+        }
+    }
+    fn flush(&self) {
+        if let Some(logger) = STARTED.get() {
+            logger.flush();
+        }
+    }
+}
+
+/// Installs the logger slot, fails when shvlog was started before or another logger is set.
+/// Called with the global handle locked.
+pub(crate) fn reserve_logger() -> Result<(), log::SetLoggerError> {
+    if RESERVED.load(Ordering::Acquire) && STARTED.get().is_none() {
+        return Ok(());
+    }
+    log::set_logger(&SLOT)?;
+    RESERVED.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Passes the records to `logger` from now on, the slot is reserved.
+pub(crate) fn start_logger(logger: ShvLogger) {
+    let _ = STARTED.set(logger);
+}
+
+/// Handle of the running logger returned by `init()`, allows changing verbosity at runtime.
+#[derive(Clone)]
+pub struct ShvLogHandle {
+    logger_handle: LoggerHandle,
+    pub(crate) config: Arc<RwLock<LogConfig>>,
+    dropped: Option<Arc<AtomicU64>>,
+}
+impl ShvLogHandle {
+    pub(crate) fn new(logger_handle: LoggerHandle, config: Arc<RwLock<LogConfig>>, dropped: Option<Arc<AtomicU64>>) -> ShvLogHandle {
+        ShvLogHandle { logger_handle, config, dropped }
+    }
+    /// Sets threshold of modules whose path contains `module`, like `-d module:L`, also of a
+    /// `module@target` or `key=value` pattern.
+    pub fn set_module_level(&self, module: &str, level: log::LevelFilter) {
+        let mut config = self.config.write().unwrap();
+        config.set_rule_level(module, level, false);
+        config.update_max_level();
+    }
+    /// Sets threshold of records with target containing `target`, like `-v target:L`, also of
+    /// a `module@target` or `key=value` pattern.
+    pub fn set_target_level(&self, target: &str, level: log::LevelFilter) {
+        let mut config = self.config.write().unwrap();
+        config.set_rule_level(target, level, true);
+        config.update_max_level();
+    }
+    /// Replaces module, target, module@target and field thresholds from `-d`/`-v` strings, other settings are kept.
+    pub fn set_levels(&self, module_tresholds: &[String], target_tresholds: &[String]) -> Result<(), ParseError> {
+        let levels = LogConfig::try_new(module_tresholds, target_tresholds)?;
+        let mut config = self.config.write().unwrap();
+        config.module_levels = levels.module_levels;
+        config.target_levels = levels.target_levels;
+        config.compound_levels = levels.compound_levels;
+        config.field_levels = levels.field_levels;
+        config.update_max_level();
+        Ok(())
+    }
+    /// Applies several changes at once, e.g.
+    /// `handle.reconfigure(|cfg| { cfg.set_module_level("rpc", LevelFilter::Debug); cfg.throttle("rpc", 100.0, 100); })`.
+    /// The changes are collected first and applied while records wait, so that no record is
+    /// filtered by a part of them. Nothing is changed when a threshold is invalid.
+    pub fn reconfigure(&self, changes: impl FnOnce(&mut Reconfiguration)) -> Result<(), ParseError> {
+        let mut reconfiguration = Reconfiguration::default();
+        changes(&mut reconfiguration);
+        reconfiguration.apply(&mut self.config.write().unwrap())
+    }
+    /// Applies `changes` like `reconfigure()` for `duration`, then reverts the thresholds and
+    /// the message filter to the previous ones and logs it with target "shvlog". Handy when
+    /// raising a device to Trace remotely, it cannot be forgotten on.
+    ///
+    /// Only what `changes` set is reverted, a threshold or the message filter changed again
+    /// meanwhile, e.g. by `reconfigure()` or a signal, is kept. Throttles added by `changes`
+    /// are kept. Overrides made meanwhile postpone the revert. Invalid changes fail with
+    /// `InvalidInput` wrapping the `ParseError`, nothing is applied then.
+    pub fn set_level_for(&self, duration: std::time::Duration, changes: impl FnOnce(&mut Reconfiguration)) -> std::io::Result<()> {
+        let mut reconfiguration = Reconfiguration::default();
+        changes(&mut reconfiguration);
+        crate::reconfigure::apply_for(&self.config, reconfiguration, duration)
+    }
+    /// Sets or clears the message filter, see `LogConfig::with_message_filter()`.
+    pub fn set_message_filter(&self, pattern: Option<&str>) {
+        self.config.write().unwrap().message_filter = pattern.map(String::from);
+    }
+    /// Replaces the whole active config. The outputs stay as set up by `init()`, the file
+    /// output also keeps its format and display options.
+    pub fn reset(&self, config: LogConfig) -> std::io::Result<()> {
+        replace_config(&self.config, config)
+    }
+    /// Reopens the log file of the file output, e.g. from a logrotate `postrotate` hook
+    /// instead of `copytruncate`, which loses the records written between the copy and the
+    /// truncation. Does nothing without file output.
+    pub fn reopen_file(&self) -> std::io::Result<()> {
+        crate::file::reopen()
+    }
+    /// Handles SIGUSR1 by raising the default threshold one step toward Trace, wrapping back
+    /// to Info, and SIGHUP by reopening the log file like `reopen_file()`, as logrotate
+    /// expects, and reloading `config_file` (see `LogConfig::from_toml_file()`) if given.
+    ///
+    /// The default threshold is the `:level` module rule if there is one, and the threshold
+    /// of records matched by no rule.
+    #[cfg(all(unix, feature = "signals"))]
+    pub fn handle_signals(&self, config_file: Option<std::path::PathBuf>) -> std::io::Result<()> {
+        crate::signals::spawn_handler(Arc::downgrade(&self.config), config_file)
+    }
+    /// Removes the state file of `LogConfig::persist_verbosity()` and returns to the thresholds
+    /// and message filter of the config, the factory defaults. Does nothing without it.
+    pub fn reset_verbosity(&self) -> std::io::Result<()> {
+        let mut config = self.config.write().unwrap();
+        let Some(persisted) = &config.persisted_verbosity else {
+            return Ok(());
+        };
+        let factory = persisted.factory.clone();
+        persisted.forget()?;
+        config.set_verbosity(&factory).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()))?;
+        config.update_max_level();
+        Ok(())
+    }
+    /// `-d`/`-v` representation of the active thresholds.
+    pub fn verbosity_string(&self) -> String {
+        self.config.read().unwrap().verbosity_string()
+    }
+    /// Active module thresholds as `(pattern, level)` pairs, sorted by pattern.
+    pub fn module_levels(&self) -> Vec<(String, log::LevelFilter)> {
+        sorted_levels(&self.config.read().unwrap().module_levels)
+    }
+    /// Active target thresholds as `(pattern, level)` pairs, sorted by pattern.
+    pub fn target_levels(&self) -> Vec<(String, log::LevelFilter)> {
+        sorted_levels(&self.config.read().unwrap().target_levels)
+    }
+    /// Active threshold rules with the number of records each one decided, e.g. for
+    /// a diagnostics command. Rules of each source are listed in the order they are tried,
+    /// the default threshold is the last one.
+    pub fn rules(&self) -> Vec<RuleInfo> {
+        self.config.read().unwrap().rule_infos()
+    }
+    /// Copy of the records kept by `LogConfig::with_memory_buffer()`, oldest first.
+    /// Empty when the active config has no memory buffer.
+    pub fn snapshot(&self) -> Vec<LogRecord> {
+        match &self.config.read().unwrap().memory_buffer {
+            Some(buffer) => buffer.snapshot(),
+            None => Vec::new(),
+        }
+    }
+    /// Records of the memory buffer selected by `params`, like SHV `getLog`, e.g. to be
+    /// returned by an RPC method as `LogRecord::list_to_cpon()`.
+    pub fn get_log(&self, params: GetLogParams) -> Vec<LogRecord> {
+        match &self.config.read().unwrap().memory_buffer {
+            Some(buffer) => buffer.query(&params),
+            None => Vec::new(),
+        }
+    }
+    /// Number of records dropped by the async writer because its queue was full,
+    /// see `LogConfig::with_async()`.
+    pub fn dropped_records(&self) -> u64 {
+        self.dropped.as_ref().map_or(0, |dropped| dropped.load(Ordering::Relaxed))
+    }
+    /// Writes out buffered records, waits for the async writer to empty its queue.
+    pub fn flush(&self) {
+        self.logger_handle.flush();
+    }
+    /// Flushes and stops the writers, including the async writer thread. Records logged
+    /// afterwards are written directly to stderr by the async writer, other writers may lose them.
+    pub fn shutdown(&self) {
+        self.logger_handle.shutdown();
+    }
+    /// Guard flushing the output when dropped, e.g. held in `main` so that all records are
+    /// written before the process exits.
+    pub fn flush_guard(&self) -> FlushGuard {
+        FlushGuard(self.logger_handle.clone())
+    }
+    /// Calls `callback` for every record passing the filtering, e.g. to forward errors to an
+    /// SHV alarm node. Records logged from the callback are written, but not passed to the
+    /// subscribers again. The subscription outlives `reset()` until `unsubscribe()`.
+    pub fn subscribe(&self, callback: impl Fn(&LogRecord) + Send + Sync + 'static) -> Subscription {
+        crate::subscribe::subscribe(None, Arc::new(callback))
+    }
+    /// Calls `callback` like `subscribe()`, but for the records passing the thresholds and
+    /// message filter of `verbosity`, a `-d ... -v ... -g ...` string, instead of the active
+    /// ones, e.g. to stream Debug records of one module to a remote client without changing the
+    /// local output. The sampling, throttling and other filtering of the active config do not
+    /// apply to these records.
+    pub fn subscribe_with_verbosity(&self, verbosity: &str, callback: impl Fn(&LogRecord) + Send + Sync + 'static) -> Result<Subscription, ParseError> {
+        let overlay = LogConfig::from_verbosity_string(verbosity)?;
+        let subscription = crate::subscribe::subscribe(Some(overlay), Arc::new(callback));
+        self.config.read().unwrap().update_max_level();
+        Ok(subscription)
+    }
+    /// Sends the records passing the filtering to the returned channel, e.g. for a GUI log
+    /// pane, or those passing the thresholds of `verbosity` like `subscribe_with_verbosity()`
+    /// unless it is empty. At most `capacity` records are queued, more are dropped until the
+    /// consumer catches up. Unsubscribes when the receiver is dropped.
+    pub fn subscribe_channel(&self, verbosity: &str, capacity: usize) -> Result<RecordReceiver, ParseError> {
+        let overlay = if verbosity.trim().is_empty() { None } else { Some(LogConfig::from_verbosity_string(verbosity)?) };
+        let receiver = crate::subscribe::subscribe_channel(overlay, capacity, &self.config);
+        self.config.read().unwrap().update_max_level();
+        Ok(receiver)
+    }
+    /// Removes a subscription created by `subscribe()` or `subscribe_with_verbosity()`.
+    pub fn unsubscribe(&self, subscription: Subscription) {
+        crate::subscribe::unsubscribe(subscription);
+        self.config.read().unwrap().update_max_level();
+    }
+    /// Streams the records passing the filtering as JSON (see `LogRecord::to_json()`) to
+    /// WebSocket clients connecting to `addr`, e.g. for a browser based live log viewer, and
+    /// returns the bound address. A client selects the least severe level it receives with
+    /// the `level` query parameter, e.g. `ws://device:9001/?level=warn`, all by default, and
+    /// may replace the active thresholds with a `verbosity` one, e.g. `?verbosity=-d+rpc:D`,
+    /// see `subscribe_with_verbosity()`.
+    ///
+    /// The server runs until the process exits. Records are dropped for a client that
+    /// cannot keep up. Pings are answered, a client is unsubscribed when it closes the
+    /// connection or disconnects. At most 16 clients are served at a time, more are rejected
+    /// with `503 Service Unavailable`.
+    #[cfg(feature = "ws")]
+    pub fn serve_websocket(&self, addr: impl std::net::ToSocketAddrs) -> std::io::Result<std::net::SocketAddr> {
+        crate::ws::serve(addr, Arc::downgrade(&self.config))
+    }
+    /// Counts of the records written since `init()` per level and per target, also kept
+    /// across `reset()`. Records suppressed by the filtering are not counted.
+    pub fn stats(&self) -> LogStats {
+        crate::stats::stats()
+    }
+    /// Metrics of the sinks added by `LogConfig::add_sink()`, in the order they were added, e.g.
+    /// to watch the lag of a sink with a queue.
+    pub fn sink_stats(&self) -> Vec<SinkStats> {
+        crate::sink::sink_stats()
+    }
+    /// Distinct targets of the records that reached the filter, plus the ones added by
+    /// `register_targets()`, sorted, e.g. for a `--list-log-domains` option. Records above the
+    /// global maximum level are discarded by the `log` macros and never seen.
+    pub fn seen_targets(&self) -> Vec<String> {
+        crate::seen::targets()
+    }
+    /// Distinct module paths of the records that reached the filter, sorted.
+    pub fn seen_modules(&self) -> Vec<String> {
+        crate::seen::modules()
+    }
+    /// Logs the standard Info banner of a starting daemon with its name, version, git hash,
+    /// PID, hostname and the effective verbosity, e.g.
+    /// `handle.log_startup_info("shvbroker", env!("CARGO_PKG_VERSION"), option_env!("GIT_HASH"))`.
+    pub fn log_startup_info(&self, app_name: &str, version: &str, git_hash: Option<&str>) {
+        let git = git_hash.map(|hash| format!(" git {}", hash)).unwrap_or_default();
+        let host = match INSTANCE_IDENTITY.read().unwrap().as_ref() {
+            Some(identity) => format!("PID {} on {}", identity.pid, identity.hostname),
+            None => format!("PID {}", std::process::id()),
+        };
+        let verbosity = self.verbosity_string();
+        log::info!(
+            target: "shvlog",
+            "{} {}{} started, {}, verbosity {}",
+            app_name,
+            version,
+            git,
+            host,
+            if verbosity.is_empty() { "default" } else { &verbosity },
+        );
+    }
+    /// The wrapped flexi_logger handle.
+    pub fn logger_handle(&self) -> &LoggerHandle {
+        &self.logger_handle
+    }
+}
+
+/// Flushes the log output when dropped, see `ShvLogHandle::flush_guard()`.
+#[must_use = "the output is flushed when the guard is dropped"]
+pub struct FlushGuard(LoggerHandle);
+impl Drop for FlushGuard {
+    fn drop(&mut self) {
+        self.0.flush();
+    }
+}
+
+/// Activates `config` and makes it the active one.
+pub(crate) fn replace_config(active: &RwLock<LogConfig>, mut config: LogConfig) -> std::io::Result<()> {
+    if let Some(identity) = INSTANCE_IDENTITY.read().unwrap().as_ref() {
+        config.activate(identity)?;
+    }
+    config.update_max_level();
+    *active.write().unwrap() = config;
+    Ok(())
+}
+
+fn sorted_levels(levels: &Rules) -> Vec<(String, log::LevelFilter)> {
+    let mut levels: Vec<_> = levels.iter().map(|(key, level)| (key.to_string(), level)).collect();
+    levels.sort();
+    levels
+}
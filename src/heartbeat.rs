@@ -0,0 +1,52 @@
+use std::sync::Weak;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+
+/// Periodic Info record with the uptime and record counts, see `LogConfig::heartbeat()`.
+pub(crate) struct Heartbeat {
+    interval: Duration,
+}
+impl Heartbeat {
+    pub(crate) fn new(interval: Duration) -> Heartbeat {
+        Heartbeat { interval }
+    }
+}
+
+fn emit(start_time: DateTime<Local>) {
+    let uptime = (Local::now() - start_time).num_seconds().max(0);
+    let stats = crate::stats::stats();
+    let levels = stats.levels;
+    let dropped: u64 = stats.dropped.values().sum();
+    log::info!(
+        target: "shvlog",
+        "heartbeat, uptime {}d {:02}:{:02}:{:02}, records E:{} W:{} I:{} D:{} T:{}, dropped {}",
+        uptime / 86400,
+        uptime / 3600 % 24,
+        uptime / 60 % 60,
+        uptime % 60,
+        levels.error,
+        levels.warn,
+        levels.info,
+        levels.debug,
+        levels.trace,
+        dropped,
+    );
+}
+
+/// Spawns the heartbeat thread, it ends once the heartbeat is dropped with its config.
+pub(crate) fn spawn_heartbeat(heartbeat: Weak<Heartbeat>, start_time: DateTime<Local>) -> std::io::Result<()> {
+    thread::Builder::new()
+        .name("shvlog-heartbeat".into())
+        .spawn(move || {
+            while let Some(interval) = heartbeat.upgrade().map(|heartbeat| heartbeat.interval) {
+                thread::sleep(interval);
+                if heartbeat.strong_count() == 0 {
+                    break;
+                }
+                emit(start_time);
+            }
+        })?;
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use std::fmt;
+
+/// Classic hex dump of binary data, 16 bytes per line with offset and ASCII column, see `hexdump!`.
+///
+/// The dump is rendered when the record is formatted, after the filtering.
+pub struct HexDump<'a>(pub &'a [u8]);
+impl fmt::Display for HexDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes", self.0.len())?;
+        for (ix, chunk) in self.0.chunks(16).enumerate() {
+            write!(f, "\n{:08x} ", ix * 16)?;
+            for col in 0..16 {
+                if col == 8 {
+                    f.write_str(" ")?;
+                }
+                match chunk.get(col) {
+                    Some(byte) => write!(f, " {:02x}", byte)?,
+                    None => f.write_str("   ")?,
+                }
+            }
+            f.write_str("  |")?;
+            for byte in chunk {
+                let c = if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' };
+                write!(f, "{}", c)?;
+            }
+            f.write_str("|")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use chrono::{DateTime, Local};
+
+/// Identity of the running process, detected once at `init()`.
+#[derive(Clone, Debug)]
+pub struct InstanceIdentity {
+    pub hostname: String,
+    pub pid: u32,
+    pub start_time: DateTime<Local>,
+    /// SHV device ID as returned by the callback passed to `LogConfig::with_device_id()`.
+    pub device_id: Option<String>,
+}
+impl InstanceIdentity {
+    pub(crate) fn detect(device_id: Option<fn() -> Option<String>>) -> InstanceIdentity {
+        InstanceIdentity {
+            hostname: detect_hostname(),
+            pid: std::process::id(),
+            start_time: Local::now(),
+            device_id: device_id.and_then(|f| f()),
+        }
+    }
+}
+
+fn detect_hostname() -> String {
+    for path in ["/proc/sys/kernel/hostname", "/etc/hostname"] {
+        if let Ok(name) = std::fs::read_to_string(path) {
+            let name = name.trim();
+            if !name.is_empty() {
+                return name.into();
+            }
+        }
+    }
+    for var in ["HOSTNAME", "COMPUTERNAME"] {
+        if let Ok(name) = std::env::var(var) {
+            if !name.is_empty() {
+                return name;
+            }
+        }
+    }
+    "localhost".into()
+}
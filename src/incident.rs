@@ -0,0 +1,119 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use flexi_logger::filter::LogLineWriter;
+use flexi_logger::DeferredNow;
+
+use crate::memory::MemoryBuffer;
+use crate::timezone;
+use crate::LogRecord;
+
+/// Incident file still collecting the records after its Error.
+struct OpenIncident {
+    file: File,
+    remaining: usize,
+}
+
+/// Files with the records around each Error, see `LogConfig::with_incident_dumps()`.
+pub(crate) struct IncidentDumps {
+    dir: PathBuf,
+    pub(crate) level: log::Level,
+    after: usize,
+    records: MemoryBuffer,
+    open: Mutex<Option<OpenIncident>>,
+    count: AtomicU64,
+}
+impl IncidentDumps {
+    pub(crate) fn new(dir: PathBuf, before: usize, after: usize, level: log::Level) -> IncidentDumps {
+        IncidentDumps { dir, level, after, records: MemoryBuffer::new(before + 1), open: Mutex::new(None), count: AtomicU64::new(0) }
+    }
+    /// Keeps `record` up to the dump level, regardless of the thresholds, and appends it to
+    /// the incident collecting the records after its Error.
+    pub(crate) fn keep(&self, now: &mut DeferredNow, record: &log::Record) {
+        if record.level() > self.level {
+            return;
+        }
+        let record = LogRecord::from_record(now, record);
+        let mut open = self.open.lock().unwrap();
+        if let Some(incident) = open.as_mut() {
+            incident.remaining -= 1;
+            if writeln!(incident.file, "{}", record).is_err() || incident.remaining == 0 {
+                *open = None;
+            }
+        }
+        drop(open);
+        self.records.push(record);
+    }
+    /// Writes the kept records to a new `incident-<id>.log` file on an Error record outside
+    /// of an open incident, and a record referencing it to the outputs.
+    pub(crate) fn check(&self, now: &mut DeferredNow, record: &log::Record, log_line_writer: &dyn LogLineWriter) -> std::io::Result<()> {
+        if record.level() != log::Level::Error || self.open.lock().unwrap().is_some() {
+            return Ok(());
+        }
+        let id = format!("{}-{}", timezone::localize(now.now()).format("%Y%m%dT%H%M%S"), self.count.fetch_add(1, Ordering::Relaxed) + 1);
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("incident-{}.log", id));
+        let mut file = File::create(&path)?;
+        writeln!(file, "incident {}: {}", id, record.args())?;
+        for record in self.records.snapshot() {
+            writeln!(file, "{}", record)?;
+        }
+        if self.after > 0 {
+            *self.open.lock().unwrap() = Some(OpenIncident { file, remaining: self.after });
+        }
+        log_line_writer.write(now, &log::Record::builder()
+            .args(format_args!("Incident {} written to {}", id, path.display()))
+            .level(log::Level::Warn)
+            .target("shvlog")
+            .module_path_static(Some("shvlog::incident"))
+            .key_values(&[("incident", id.as_str())])
+            .build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Collect(Mutex<Vec<String>>);
+    impl LogLineWriter for Collect {
+        fn write(&self, _now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+            self.0.lock().unwrap().push(record.args().to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn records_around_the_error() {
+        let dir = std::env::temp_dir().join(format!("shvlog-incident-{}", std::process::id()));
+        let dumps = IncidentDumps::new(dir.clone(), 2, 2, log::Level::Debug);
+        let output = Collect(Mutex::new(Vec::new()));
+        let log = |record: &log::Record| {
+            dumps.keep(&mut DeferredNow::new(), record);
+            dumps.check(&mut DeferredNow::new(), record, &output).unwrap();
+        };
+        for (level, message) in [
+            (log::Level::Trace, "skipped"),
+            (log::Level::Debug, "one"),
+            (log::Level::Info, "two"),
+            (log::Level::Info, "three"),
+            (log::Level::Error, "failed"),
+            (log::Level::Error, "failed again"),
+            (log::Level::Debug, "four"),
+            (log::Level::Debug, "five"),
+        ] {
+            log(&log::Record::builder().level(level).target("app").args(format_args!("{}", message)).build());
+        }
+        let references = output.0.lock().unwrap();
+        assert_eq!(references.len(), 1);
+        let path = references[0].split(" written to ").nth(1).unwrap();
+        let dump = std::fs::read_to_string(path).unwrap();
+        let messages: Vec<_> = dump.lines().skip(1).map(|line| line.rsplit('|').next().unwrap()).collect();
+        assert!(dump.starts_with("incident ") && dump.lines().next().unwrap().ends_with(": failed"), "{}", dump);
+        assert_eq!(messages, ["two", "three", "failed", "failed again", "four"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+use std::os::unix::net::UnixDatagram;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::{AsRawFd, FromRawFd};
+
+use flexi_logger::writers::LogWriter;
+use flexi_logger::DeferredNow;
+
+use crate::{fields, ShvSeverity, ERROR_CODE_KEY};
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Writer submitting records to journald using its native protocol.
+pub(crate) struct JournaldWriter {
+    socket: UnixDatagram,
+    identifier: String,
+    message_ids: Vec<(String, u128)>,
+}
+impl JournaldWriter {
+    pub(crate) fn new(identifier: Option<&str>, message_ids: Vec<(String, u128)>) -> std::io::Result<JournaldWriter> {
+        let identifier = match identifier {
+            Some(identifier) => identifier.into(),
+            None => std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.file_name().map(|name| name.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| "shvlog".into()),
+        };
+        Ok(JournaldWriter { socket: UnixDatagram::unbound()?, identifier, message_ids })
+    }
+}
+
+/// Fields with a meaning to journald or written by `JournaldWriter` itself.
+const RESERVED_FIELDS: &[&str] = &[
+    "MESSAGE", "MESSAGE_ID", "PRIORITY", "CODE_FILE", "CODE_LINE", "CODE_FUNC", "CODE_MODULE", "ERRNO", "INVOCATION_ID",
+    "USER_INVOCATION_ID", "SYSLOG_FACILITY", "SYSLOG_IDENTIFIER", "SYSLOG_PID", "SYSLOG_TIMESTAMP", "SYSLOG_RAW",
+    "DOCUMENTATION", "TID", "UNIT", "USER_UNIT", "TARGET",
+];
+/// Journal field names are limited to 64 characters.
+const MAX_FIELD_NAME_LEN: usize = 64;
+
+/// Journal field name of a key-value or enrichment field, uppercase letters, digits and `_`
+/// not leading. A name starting with a digit, reserved by journald or written by the writer
+/// itself is prefixed with `SHV_`, e.g. `message` is sent as `SHV_MESSAGE`.
+fn field_name(name: &str) -> String {
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect();
+    let mut name = match name.trim_start_matches('_') {
+        "" => "FIELD".to_string(),
+        name if name.starts_with(|c: char| c.is_ascii_digit()) || name.starts_with("SHV_") || RESERVED_FIELDS.contains(&name) => format!("SHV_{}", name),
+        name => name.to_string(),
+    };
+    name.truncate(MAX_FIELD_NAME_LEN);
+    name
+}
+
+/// Appends a field, values containing a newline use the length prefixed binary form.
+fn add_field(buf: &mut Vec<u8>, name: &str, value: &str) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+/// Passes an entry too large for a datagram in a sealed memfd, as the journald native
+/// protocol allows.
+#[cfg(target_os = "linux")]
+fn send_memfd(entry: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    // SAFETY: the name is NUL terminated
+    let fd = unsafe { libc::memfd_create(b"shvlog-journal\0".as_ptr().cast(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor was just returned by memfd_create, it is now owned by `file`
+    let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+    file.write_all(entry)?;
+    // SAFETY: plain fcntl on the owned descriptor
+    if unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(JOURNALD_SOCKET)?;
+    let fd_len = std::mem::size_of::<libc::c_int>() as u32;
+    // SAFETY: the control buffer is large and aligned enough for one descriptor, the header
+    // fields are set before sendmsg reads them
+    unsafe {
+        let space = libc::CMSG_SPACE(fd_len) as usize;
+        let mut control = vec![0u64; space.div_ceil(8)];
+        let mut message: libc::msghdr = std::mem::zeroed();
+        message.msg_control = control.as_mut_ptr().cast();
+        message.msg_controllen = space as _;
+        let header = libc::CMSG_FIRSTHDR(&message);
+        (*header).cmsg_level = libc::SOL_SOCKET;
+        (*header).cmsg_type = libc::SCM_RIGHTS;
+        (*header).cmsg_len = libc::CMSG_LEN(fd_len) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(header).cast::<libc::c_int>(), fd);
+        if libc::sendmsg(socket.as_raw_fd(), &message, 0) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_memfd(_entry: &[u8]) -> std::io::Result<()> {
+    Err(std::io::Error::from_raw_os_error(libc::EMSGSIZE))
+}
+
+impl JournaldWriter {
+    /// Journal entry of the record in the native protocol.
+    fn entry(&self, record: &log::Record) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(256);
+        add_field(&mut buf, "PRIORITY", &ShvSeverity::of(record.level()).syslog_severity().to_string());
+        add_field(&mut buf, "MESSAGE", &record.args().to_string());
+        add_field(&mut buf, "SYSLOG_IDENTIFIER", &self.identifier);
+        if let Some(module) = record.module_path() {
+            add_field(&mut buf, "CODE_MODULE", module);
+        }
+        if let Some(file) = record.file() {
+            add_field(&mut buf, "CODE_FILE", file);
+        }
+        if let Some(line) = record.line() {
+            add_field(&mut buf, "CODE_LINE", &line.to_string());
+        }
+        add_field(&mut buf, "TARGET", record.target());
+        if let Some((_, id)) = self.message_ids.iter().find(|(target, _)| target == record.target()) {
+            add_field(&mut buf, "MESSAGE_ID", &format!("{:032x}", id));
+        }
+        if let Some(code) = record.key_values().get(log::kv::Key::from_str(ERROR_CODE_KEY)) {
+            add_field(&mut buf, "SHV_ERROR_CODE", &code.to_string());
+        }
+        for (key, value) in fields::record_fields(record) {
+            add_field(&mut buf, &field_name(key.as_str()), &value.to_string());
+        }
+        for (name, value) in fields::enrichment() {
+            add_field(&mut buf, &field_name(&name), &value);
+        }
+        buf
+    }
+}
+
+impl LogWriter for JournaldWriter {
+    fn write(&self, _now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        let buf = self.entry(record);
+        match self.socket.send_to(&buf, JOURNALD_SOCKET) {
+            Err(err) if err.raw_os_error() == Some(libc::EMSGSIZE) => send_memfd(&buf),
+            result => result.map(drop),
+        }
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_names() {
+        assert_eq!(field_name("peer_id"), "PEER_ID");
+        assert_eq!(field_name("device.serial"), "DEVICE_SERIAL");
+        assert_eq!(field_name("_private"), "PRIVATE");
+        assert_eq!(field_name("__"), "FIELD");
+        for (name, field) in [("message", "SHV_MESSAGE"), ("priority", "SHV_PRIORITY"), ("code_line", "SHV_CODE_LINE"), ("1x", "SHV_1X"), ("target", "SHV_TARGET"), ("shv_error_code", "SHV_SHV_ERROR_CODE")] {
+            assert_eq!(field_name(name), field);
+        }
+        assert_eq!(field_name(&"x".repeat(100)).len(), MAX_FIELD_NAME_LEN);
+    }
+
+    #[test]
+    fn binary_field() {
+        let mut buf = Vec::new();
+        add_field(&mut buf, "MESSAGE", "a");
+        add_field(&mut buf, "MESSAGE", "a\nb");
+        assert_eq!(buf, b"MESSAGE=a\nMESSAGE\n\x03\0\0\0\0\0\0\0a\nb\n");
+    }
+
+    #[test]
+    fn entry() {
+        let writer = JournaldWriter::new(Some("app"), vec![("Access".into(), 0xfc2e22bc6ee647b6b90729ab34a250b1)]).unwrap();
+        let kvs = [("message", "kv"), ("peer_id", "7")];
+        let record = log::Record::builder()
+            .level(log::Level::Warn)
+            .target("Access")
+            .module_path(Some("app::rpc"))
+            .file(Some("src/rpc.rs"))
+            .line(Some(42))
+            .args(format_args!("denied"))
+            .key_values(&kvs)
+            .build();
+        let entry = String::from_utf8(writer.entry(&record)).unwrap();
+        for field in [
+            "MESSAGE=denied", "SYSLOG_IDENTIFIER=app", "CODE_MODULE=app::rpc", "CODE_FILE=src/rpc.rs", "CODE_LINE=42",
+            "TARGET=Access", "MESSAGE_ID=fc2e22bc6ee647b6b90729ab34a250b1", "SHV_MESSAGE=kv", "PEER_ID=7",
+        ] {
+            assert!(entry.lines().any(|line| line == field), "{} missing in {}", field, entry);
+        }
+        let record = log::Record::builder().target("Other").args(format_args!("x")).build();
+        assert!(!String::from_utf8(writer.entry(&record)).unwrap().contains("MESSAGE_ID"));
+    }
+}
@@ -0,0 +1,278 @@
+use std::fmt;
+
+use flexi_logger::DeferredNow;
+
+use crate::{context, fields, group, scope, FormatOptions, ERROR_CODE_KEY};
+
+/// Version of the JSON record layout written as `schema_version`, see `json_schema()`.
+///
+/// Within a version members are only added. Removing or renaming a member or changing its
+/// type bumps the version, so a consumer accepting unknown members is compatible with all
+/// records of the version it was written for.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// JSON Schema (draft 2020-12) of the records of `LogFormat::Json` in version
+/// `JSON_SCHEMA_VERSION`, for validating records downstream. It has the default member
+/// names, not those renamed by `LogConfig::json_key()`.
+pub fn json_schema() -> &'static str {
+    r#"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "title": "shvlog record",
+  "type": "object",
+  "required": ["schema_version", "level", "module", "target", "line", "message"],
+  "properties": {
+    "schema_version": { "const": 1 },
+    "timestamp": { "type": "string", "description": "RFC 3339, a custom format or the time since init()" },
+    "delta": { "type": "number", "description": "seconds since the previous record" },
+    "level": { "enum": ["ERROR", "WARN", "INFO", "DEBUG", "TRACE"] },
+    "module": { "type": "string" },
+    "target": { "type": "string" },
+    "line": { "type": ["integer", "null"], "minimum": 0 },
+    "thread": { "type": "string" },
+    "hostname": { "type": "string" },
+    "pid": { "type": "integer", "minimum": 0 },
+    "message": { "type": "string" },
+    "group_depth": { "type": "integer", "minimum": 1 },
+    "app": { "type": "string" },
+    "instance": { "type": "string" },
+    "error_code": { "type": "string" },
+    "fields": { "type": "object", "description": "key-value, context and with_field() fields" },
+    "backtrace": { "type": "string" }
+  },
+  "additionalProperties": true
+}"#
+}
+
+/// Displays a string as JSON string literal.
+pub(crate) struct JsonStr<'a>(pub(crate) &'a str);
+impl fmt::Display for JsonStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+/// Displays a key-value field as JSON bool, number or string.
+pub(crate) struct JsonValue<'a>(pub(crate) &'a log::kv::Value<'a>);
+impl fmt::Display for JsonValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.0;
+        if let Some(value) = value.to_bool() {
+            write!(f, "{}", value)
+        } else if let Some(value) = value.to_i64() {
+            write!(f, "{}", value)
+        } else if let Some(value) = value.to_u64() {
+            write!(f, "{}", value)
+        } else if let Some(value) = value.to_f64().filter(|value| value.is_finite()) {
+            write!(f, "{}", value)
+        } else {
+            write!(f, "{}", JsonStr(&value.to_string()))
+        }
+    }
+}
+
+/// Writes the record as single line JSON object.
+pub(crate) fn json_format(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &log::Record, options: &FormatOptions, max_len: Option<usize>) -> std::io::Result<()> {
+    let key = |name| JsonStr(options.json_key(name));
+    write!(w, "{{{}:{},", key("schema_version"), JSON_SCHEMA_VERSION)?;
+    if let Some(timestamp) = options.timestamp(now) {
+        write!(w, "{}:{},", key("timestamp"), JsonStr(&timestamp))?;
+    }
+    if let Some(delta) = options.delta(now) {
+        write!(w, "{}:{},", key("delta"), options.seconds(delta))?;
+    }
+    write!(
+        w,
+        "{}:\"{}\",{}:{},{}:{},{}:",
+        key("level"),
+        record.level(),
+        key("module"),
+        JsonStr(record.module_path().unwrap_or("")),
+        key("target"),
+        JsonStr(record.target()),
+        key("line"),
+    )?;
+    match record.line() {
+        Some(line) => write!(w, "{}", line)?,
+        None => write!(w, "null")?,
+    }
+    if options.show_thread {
+        write!(w, ",{}:{}", key("thread"), JsonStr(&crate::thread_name()))?;
+    }
+    if options.show_hostname {
+        write!(w, ",{}:{}", key("hostname"), JsonStr(&options.hostname))?;
+    }
+    if options.show_pid {
+        write!(w, ",{}:{}", key("pid"), options.pid)?;
+    }
+    write!(w, ",{}:{}", key("message"), JsonStr(&crate::truncate(format!("{}{}", scope::prefix(), record.args()), max_len)))?;
+    let group_depth = group::depth();
+    if group_depth > 0 {
+        write!(w, ",{}:{}", key("group_depth"), group_depth)?;
+    }
+    if let Some(app_name) = &options.app_name {
+        write!(w, ",{}:{}", key("app"), JsonStr(app_name))?;
+    }
+    if let Some(instance_id) = &options.instance_id {
+        write!(w, ",{}:{}", key("instance"), JsonStr(instance_id))?;
+    }
+    if let Some(code) = record.key_values().get(log::kv::Key::from_str(ERROR_CODE_KEY)) {
+        write!(w, ",{}:{}", key(ERROR_CODE_KEY), JsonStr(&code.to_string()))?;
+    }
+    // nested, so that a field named like a member of the record cannot shadow it
+    let members = field_members(record);
+    if !members.is_empty() {
+        write!(w, ",{}:{{{}}}", key("fields"), members.join(","))?;
+    }
+    if let Some(backtrace) = crate::backtrace(record, options) {
+        write!(w, ",{}:{}", key("backtrace"), JsonStr(&backtrace))?;
+    }
+    write!(w, "}}")
+}
+
+/// ECS version of the records of `LogFormat::Ecs`.
+const ECS_VERSION: &str = "8.11.0";
+
+/// Writes the record as single line JSON object with the Elastic Common Schema fields.
+pub(crate) fn ecs_format(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &log::Record, options: &FormatOptions, max_len: Option<usize>) -> std::io::Result<()> {
+    write!(
+        w,
+        "{{\"@timestamp\":\"{}\",\"log.level\":\"{}\",\"message\":{},\"ecs.version\":\"{}\",\"log.logger\":{}",
+        now.now_utc_owned().format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+        record.level().as_str().to_ascii_lowercase(),
+        JsonStr(&crate::truncate(format!("{}{}", scope::prefix(), record.args()), max_len)),
+        ECS_VERSION,
+        JsonStr(record.target()),
+    )?;
+    if let Some(file) = record.file() {
+        write!(w, ",\"log.origin.file.name\":{}", JsonStr(file))?;
+    }
+    if let Some(line) = record.line() {
+        write!(w, ",\"log.origin.file.line\":{}", line)?;
+    }
+    write!(
+        w,
+        ",\"host.name\":{},\"process.pid\":{},\"process.thread.name\":{}",
+        JsonStr(&options.hostname),
+        options.pid,
+        JsonStr(&crate::thread_name()),
+    )?;
+    if let Some(app_name) = &options.app_name {
+        write!(w, ",\"service.name\":{}", JsonStr(app_name))?;
+    }
+    if let Some(instance_id) = &options.instance_id {
+        write!(w, ",\"service.node.name\":{}", JsonStr(instance_id))?;
+    }
+    if let Some(code) = record.key_values().get(log::kv::Key::from_str(ERROR_CODE_KEY)) {
+        write!(w, ",\"error.code\":{}", JsonStr(&code.to_string()))?;
+    }
+    if let Some(backtrace) = crate::backtrace(record, options) {
+        write!(w, ",\"error.stack_trace\":{}", JsonStr(&backtrace))?;
+    }
+    // ECS has no module, it is a custom field when it differs from the logger
+    let module = record.module_path().unwrap_or("");
+    if module != record.target() {
+        write!(w, ",\"shvlog.module\":{}", JsonStr(module))?;
+    }
+    let members = field_members(record);
+    if !members.is_empty() {
+        write!(w, ",\"labels\":{{{}}}", members.join(","))?;
+    }
+    write!(w, "}}")
+}
+
+/// Key-value, context and enrichment fields of the record as JSON members.
+fn field_members(record: &log::Record) -> Vec<String> {
+    fields::record_fields(record).into_iter()
+        .map(|(key, value)| format!("{}:{}", JsonStr(key.as_str()), JsonValue(&value)))
+        .chain(context::current().into_iter().chain(fields::enrichment()).map(|(key, value)| format!("{}:{}", JsonStr(&key), JsonStr(&value))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(record: &log::Record) -> String {
+        let mut line = Vec::new();
+        let options = FormatOptions { timestamp: crate::TimestampMode::None, ..FormatOptions::default() };
+        json_format(&mut line, &mut DeferredNow::new(), record, &options, None).unwrap();
+        String::from_utf8(line).unwrap()
+    }
+
+    #[test]
+    fn fields_are_nested() {
+        let fields: &[(&str, &dyn log::kv::ToValue)] = &[("message", &"shadow"), ("peer", &42)];
+        let record = log::Record::builder()
+            .args(format_args!("connected \"a\""))
+            .level(log::Level::Info)
+            .target("rpc")
+            .module_path_static(Some("shvapp::rpc"))
+            .line(Some(7))
+            .key_values(&fields)
+            .build();
+        assert_eq!(json(&record), concat!(
+            r#"{"schema_version":1,"level":"INFO","module":"shvapp::rpc","target":"rpc","line":7,"message":"connected \"a\"","#,
+            r#""fields":{"message":"shadow","peer":42}}"#,
+        ));
+        let record = log::Record::builder().args(format_args!("plain")).level(log::Level::Warn).build();
+        assert_eq!(json(&record), r#"{"schema_version":1,"level":"WARN","module":"","target":"","line":null,"message":"plain"}"#);
+    }
+
+    #[test]
+    fn renamed_keys() {
+        let options = FormatOptions {
+            timestamp: crate::TimestampMode::None,
+            json_keys: vec![("message".into(), "msg".into()), ("level".into(), "severity".into())],
+            ..FormatOptions::default()
+        };
+        let record = log::Record::builder().args(format_args!("renamed")).level(log::Level::Error).build();
+        let mut line = Vec::new();
+        json_format(&mut line, &mut DeferredNow::new(), &record, &options, None).unwrap();
+        assert_eq!(String::from_utf8(line).unwrap(), r#"{"schema_version":1,"severity":"ERROR","module":"","target":"","line":null,"msg":"renamed"}"#);
+    }
+
+    #[test]
+    fn ecs() {
+        let fields: &[(&str, &dyn log::kv::ToValue)] = &[("peer", &42)];
+        let record = log::Record::builder()
+            .args(format_args!("refused"))
+            .level(log::Level::Warn)
+            .target("RpcMsg")
+            .module_path_static(Some("shvapp::rpc"))
+            .file_static(Some("src/rpc.rs"))
+            .line(Some(12))
+            .key_values(&fields)
+            .build();
+        let options = FormatOptions { hostname: "device".into(), pid: 7, app_name: Some("shvapp".into()), ..FormatOptions::default() };
+        let mut line = Vec::new();
+        ecs_format(&mut line, &mut DeferredNow::new(), &record, &options, None).unwrap();
+        let line = String::from_utf8(line).unwrap();
+        assert!(line.starts_with(r#"{"@timestamp":""#), "{}", line);
+        let thread = JsonStr(&crate::thread_name()).to_string();
+        assert!(line.ends_with(&format!(concat!(
+            r#"Z","log.level":"warn","message":"refused","ecs.version":"8.11.0","log.logger":"RpcMsg","#,
+            r#""log.origin.file.name":"src/rpc.rs","log.origin.file.line":12,"host.name":"device","process.pid":7,"#,
+            r#""process.thread.name":{},"service.name":"shvapp","shvlog.module":"shvapp::rpc","labels":{{"peer":42}}}}"#,
+        ), thread)), "{}", line);
+    }
+
+    #[test]
+    fn schema_lists_the_members() {
+        assert!(json_schema().contains(&format!(r#""schema_version": {{ "const": {} }}"#, JSON_SCHEMA_VERSION)));
+        for member in ["timestamp", "delta", "thread", "hostname", "pid", "group_depth", "app", "instance", ERROR_CODE_KEY, "fields", "backtrace"] {
+            assert!(json_schema().contains(&format!(r#""{}": {{"#, member)), "{} is missing", member);
+        }
+    }
+}
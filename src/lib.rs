@@ -1,133 +1,2284 @@
 use std::collections::HashMap;
-use ansi_term::Color;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
 
-use flexi_logger::{DeferredNow, FlexiLoggerError, Level, Logger, LoggerHandle, Record};
+use flexi_logger::{Duplicate, Level, Logger, Record, WriteMode};
+use log::LevelFilter;
+pub use flexi_logger::{DeferredNow, FlexiLoggerError, FormatFunction};
 use flexi_logger::filter::{LogLineFilter, LogLineWriter};
+use flexi_logger::writers::LogWriter;
+use lazy_static::lazy_static;
+
+mod async_writer;
+mod builder;
+mod burst;
+mod cache;
+mod chainpack;
+mod child;
+#[cfg(feature = "clap")]
+pub mod cli;
+#[cfg(feature = "toml")]
+mod config;
+mod crash;
+pub mod context;
+mod dedup;
+mod drops;
+mod error_chain;
+mod escalation;
+mod explain;
+mod field_filter;
+mod fields;
+mod file;
+#[cfg(feature = "gelf")]
+mod gelf;
+mod group;
+mod heartbeat;
+mod handle;
+mod hexdump;
+mod identity;
+mod incident;
+#[cfg(all(unix, feature = "journald"))]
+mod journald;
+mod json;
+mod load;
+mod memory;
+mod once;
+#[cfg(feature = "otel")]
+mod otel;
+mod palette;
+mod panic;
+mod parse;
+mod persist;
+mod query;
+mod quota;
+mod record;
+mod reconfigure;
+mod redact;
+#[cfg(feature = "toml")]
+mod reload;
+mod remote;
+mod route;
+mod rules;
+mod sample;
+mod scope;
+mod seen;
+mod severity;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+mod shvjournal;
+#[cfg(all(unix, feature = "signals"))]
+mod signals;
+mod silence;
+mod sink;
+mod stats;
+mod subscribe;
+mod syslog;
+#[cfg(all(unix, feature = "systemd"))]
+mod systemd;
+mod template;
+pub mod test;
+mod throttle;
+mod timezone;
+mod timing;
+#[cfg(feature = "tracing")]
+mod tracing_layer;
+mod trigger;
+mod writers;
+#[cfg(feature = "ws")]
+mod ws;
+pub use async_writer::OverflowPolicy;
+use async_writer::AsyncWriter;
+pub use builder::LogConfigBuilder;
+use burst::Burst;
+use cache::ThresholdCache;
+pub use child::pipe_child_stderr;
+#[cfg(feature = "toml")]
+pub use config::ConfigError;
+use crash::CrashDump;
+use dedup::Dedup;
+pub use drops::DropReason;
+pub use error_chain::ErrorChain;
+use escalation::EscalationRule;
+pub use explain::{Explanation, MatchedRule};
+use field_filter::FieldRule;
+pub use file::{FileLog, Fsync, RotationAge};
+#[cfg(feature = "gelf")]
+pub use gelf::Gelf;
+pub use group::LogGroup;
+pub use handle::{FlushGuard, ShvLogHandle};
+use heartbeat::Heartbeat;
+pub use hexdump::HexDump;
+use handle::{SharedFilter, ShvLogger};
+pub use identity::InstanceIdentity;
+use incident::IncidentDumps;
+pub use json::{json_schema, JSON_SCHEMA_VERSION};
+use load::LoadMonitor;
+use memory::MemoryBuffer;
+#[doc(hidden)]
+pub use once::{occurrence, Occurrence};
+#[cfg(feature = "otel")]
+pub use otel::Otel;
+pub use palette::Palette;
+pub use panic::{install_panic_hook, PanicHook, PANIC_TARGET};
+pub use parse::{format_rules, parse_rules, FilterRule, ParseError, PatternKind};
+use persist::PersistedVerbosity;
+pub use query::GetLogParams;
+use quota::Quota;
+pub use reconfigure::Reconfiguration;
+pub use record::LogRecord;
+pub use remote::RemoteLog;
+pub use route::{Destination, Route};
+use route::ConsoleWriter;
+pub use rules::{MatchMode, RuleInfo, RuleSource};
+use redact::{MaskedFields, Redaction};
+use rules::{CompoundRules, Hits, Rules};
+use sample::Sample;
+pub use scope::{scope, LogScope};
+pub use seen::register_targets;
+pub use severity::ShvSeverity;
+pub use shvjournal::ShvJournal;
+use silence::SilenceRule;
+pub use sink::{FormattedRecord, LogSink, SinkStats};
+use sink::SinkWriter;
+pub use stats::{LevelCounts, LogStats};
+pub use subscribe::{RecordReceiver, Subscription};
+pub use syslog::{Facility, Syslog, SyslogSeverity};
+use template::Template;
+use throttle::Throttle;
+pub use timezone::Tz;
+#[cfg(feature = "tz")]
+pub use timezone::zoneinfo::ZoneInfo;
+pub use timing::TimedBlock;
+#[cfg(feature = "tracing")]
+pub use tracing_layer::ShvLogLayer;
+use trigger::Trigger;
+
+#[doc(hidden)]
+pub use log;
+pub use ansi_term::{Color, Style};
+
+/// Most verbose level compiled in, set by the `max_level_*` and `release_max_level_*` features,
+/// e.g. `release_max_level_info` strips Debug and Trace records from release builds.
+///
+/// Applications can check the build matches their expectations, e.g.
+/// `assert!(shvlog::STATIC_MAX_LEVEL <= log::LevelFilter::Info)`. Thresholds above it have no effect.
+pub const STATIC_MAX_LEVEL: LevelFilter = log::STATIC_MAX_LEVEL;
+
+/// Target of the audit channel, records logged with it bypass level filtering.
+pub const AUDIT_TARGET: &str = "Audit";
+
+/// Key of the structured field carrying an error code, see `error_code!`.
+pub const ERROR_CODE_KEY: &str = "error_code";
+
+/// Logs an Error record tagged with an error code, e.g. `error_code!(E1203, "mount failed: {}", path)`.
+///
+/// The code is stored in the `error_code` key-value field and rendered in front of the message.
+#[macro_export]
+macro_rules! error_code {
+    (target: $target:expr, $code:ident, $($arg:tt)+) => ($crate::log::error!(target: $target, error_code = stringify!($code); $($arg)+));
+    (target: $target:expr, $code:literal, $($arg:tt)+) => ($crate::log::error!(target: $target, error_code = $code; $($arg)+));
+    ($code:ident, $($arg:tt)+) => ($crate::log::error!(error_code = stringify!($code); $($arg)+));
+    ($code:literal, $($arg:tt)+) => ($crate::log::error!(error_code = $code; $($arg)+));
+}
+
+/// Logs a security relevant event to the audit channel.
+///
+/// Audit records are always written, regardless of the configured thresholds,
+/// and are additionally appended to the file set by `LogConfig::with_audit_file()`.
+#[macro_export]
+macro_rules! audit {
+    ($($arg:tt)+) => ($crate::log::info!(target: $crate::AUDIT_TARGET, $($arg)+))
+}
+
+/// Target of SHV RPC message traces, enabled with `-v RpcMsg`.
+pub const RPCMSG_TARGET: &str = "RpcMsg";
+
+/// Target of SHV RPC raw data traces, enabled with `-v RpcData`.
+pub const RPCDATA_TARGET: &str = "RpcData";
+
+/// Logs with level Error to the `RPCMSG_TARGET` target, e.g. `rpcmsg_error!("<== {}", msg)`.
+#[macro_export]
+macro_rules! rpcmsg_error {
+    ($($arg:tt)+) => ($crate::log::error!(target: $crate::RPCMSG_TARGET, $($arg)+))
+}
+
+/// Logs with level Warn to the `RPCMSG_TARGET` target, e.g. `rpcmsg_warn!("<== {}", msg)`.
+#[macro_export]
+macro_rules! rpcmsg_warn {
+    ($($arg:tt)+) => ($crate::log::warn!(target: $crate::RPCMSG_TARGET, $($arg)+))
+}
+
+/// Logs with level Info to the `RPCMSG_TARGET` target, e.g. `rpcmsg_info!("<== {}", msg)`.
+#[macro_export]
+macro_rules! rpcmsg_info {
+    ($($arg:tt)+) => ($crate::log::info!(target: $crate::RPCMSG_TARGET, $($arg)+))
+}
+
+/// Logs with level Debug to the `RPCMSG_TARGET` target, e.g. `rpcmsg_debug!("<== {}", msg)`.
+#[macro_export]
+macro_rules! rpcmsg_debug {
+    ($($arg:tt)+) => ($crate::log::debug!(target: $crate::RPCMSG_TARGET, $($arg)+))
+}
+
+/// Logs with level Trace to the `RPCMSG_TARGET` target, e.g. `rpcmsg_trace!("<== {}", msg)`.
+#[macro_export]
+macro_rules! rpcmsg_trace {
+    ($($arg:tt)+) => ($crate::log::trace!(target: $crate::RPCMSG_TARGET, $($arg)+))
+}
+
+/// Logs with level Error to the `RPCDATA_TARGET` target, e.g. `rpcdata_error!("{} bytes received", len)`.
+#[macro_export]
+macro_rules! rpcdata_error {
+    ($($arg:tt)+) => ($crate::log::error!(target: $crate::RPCDATA_TARGET, $($arg)+))
+}
+
+/// Logs with level Warn to the `RPCDATA_TARGET` target, e.g. `rpcdata_warn!("{} bytes received", len)`.
+#[macro_export]
+macro_rules! rpcdata_warn {
+    ($($arg:tt)+) => ($crate::log::warn!(target: $crate::RPCDATA_TARGET, $($arg)+))
+}
+
+/// Logs with level Info to the `RPCDATA_TARGET` target, e.g. `rpcdata_info!("{} bytes received", len)`.
+#[macro_export]
+macro_rules! rpcdata_info {
+    ($($arg:tt)+) => ($crate::log::info!(target: $crate::RPCDATA_TARGET, $($arg)+))
+}
+
+/// Logs with level Debug to the `RPCDATA_TARGET` target, e.g. `rpcdata_debug!("{} bytes received", len)`.
+#[macro_export]
+macro_rules! rpcdata_debug {
+    ($($arg:tt)+) => ($crate::log::debug!(target: $crate::RPCDATA_TARGET, $($arg)+))
+}
+
+/// Logs with level Trace to the `RPCDATA_TARGET` target, e.g. `rpcdata_trace!("{} bytes received", len)`.
+#[macro_export]
+macro_rules! rpcdata_trace {
+    ($($arg:tt)+) => ($crate::log::trace!(target: $crate::RPCDATA_TARGET, $($arg)+))
+}
+
+/// Logs binary data as hex dump, e.g. `hexdump!(target: "RpcData", Level::Trace, &frame, "frame from {}", peer)`.
+///
+/// The dump is only built when the record passes the filtering, see `HexDump`.
+#[macro_export]
+macro_rules! hexdump {
+    (target: $target:expr, $level:expr, $data:expr, $($arg:tt)+) => ($crate::log::log!(target: $target, $level, "{}: {}", format_args!($($arg)+), $crate::HexDump($data)));
+    (target: $target:expr, $level:expr, $data:expr) => ($crate::log::log!(target: $target, $level, "{}", $crate::HexDump($data)));
+    ($level:expr, $data:expr, $($arg:tt)+) => ($crate::log::log!($level, "{}: {}", format_args!($($arg)+), $crate::HexDump($data)));
+    ($level:expr, $data:expr) => ($crate::log::log!($level, "{}", $crate::HexDump($data)));
+}
+
+/// Logs an error with its cause chain, e.g. `log_error_chain!(Level::Error, &err, "mount {} failed", path)`.
+///
+/// Each cause goes on its own indented line, rendered by the configured format, see `ErrorChain`.
+#[macro_export]
+macro_rules! log_error_chain {
+    (target: $target:expr, $level:expr, $err:expr, $($arg:tt)+) => ($crate::log::log!(target: $target, $level, "{}: {}", format_args!($($arg)+), $crate::ErrorChain($err)));
+    (target: $target:expr, $level:expr, $err:expr) => ($crate::log::log!(target: $target, $level, "{}", $crate::ErrorChain($err)));
+    ($level:expr, $err:expr, $($arg:tt)+) => ($crate::log::log!($level, "{}: {}", format_args!($($arg)+), $crate::ErrorChain($err)));
+    ($level:expr, $err:expr) => ($crate::log::log!($level, "{}", $crate::ErrorChain($err)));
+}
+
+/// Callback contributing a field value to every emitted record.
+pub type FieldProvider = Arc<dyn Fn() -> String + Send + Sync>;
+
+/// Output format of the records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Colored human readable text, key-value fields of the record are appended as `key=value`.
+    #[default]
+    Text,
+    /// One JSON object per line, without colors, described by `json_schema()`. The key-value,
+    /// context and `with_field()` fields of the record are members of its `fields` object.
+    Json,
+    /// Text prefixed with sd-daemon priority (`<3>` for Error, ...), without timestamps
+    /// and colors, for services whose stderr is captured by journald.
+    Systemd,
+    /// One JSON object per line with the Elastic Common Schema fields (`@timestamp`,
+    /// `log.level`, `log.logger`, `host.name`, `error.stack_trace`, ...) for Elastic SIEM, the
+    /// fields of the record are in `labels`.
+    Ecs,
+}
+
+/// Console stream of the records, see `LogConfig::output()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Stream {
+    #[default]
+    Stderr,
+    /// For tools emitting their data on stderr.
+    Stdout,
+}
+impl Stream {
+    fn is_terminal(self) -> bool {
+        match self {
+            Stream::Stderr => std::io::IsTerminal::is_terminal(&std::io::stderr()),
+            Stream::Stdout => std::io::IsTerminal::is_terminal(&std::io::stdout()),
+        }
+    }
+    pub(crate) fn lock(self) -> Box<dyn Write> {
+        match self {
+            Stream::Stderr => Box::new(std::io::stderr().lock()),
+            Stream::Stdout => Box::new(std::io::stdout().lock()),
+        }
+    }
+}
+
+/// When the text output on stderr is colored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Colored when the output stream is a terminal. `NO_COLOR` disables and `CLICOLOR_FORCE` forces the colors.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+impl ColorMode {
+    fn enabled(self, stream: Stream) -> bool {
+        let enabled = self.requested(stream);
+        // the Windows console shows the escape sequences verbatim unless VT processing is on
+        #[cfg(windows)]
+        if enabled && ansi_term::enable_ansi_support().is_err() {
+            return false;
+        }
+        enabled
+    }
+    fn requested(self, stream: Stream) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                let env_set = |name| std::env::var_os(name).is_some_and(|value| !value.is_empty() && value != "0");
+                if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+                    false
+                } else {
+                    env_set("CLICOLOR_FORCE") || stream.is_terminal()
+                }
+            }
+        }
+    }
+}
+
+/// Timestamp of the records in the text and JSON output.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimestampMode {
+    /// Local time in RFC 3339 with the UTC offset, e.g. `2024-03-01T12:00:00.123+01:00`,
+    /// in the timezone of `LogConfig::timezone()`.
+    #[default]
+    LocalRfc3339,
+    /// UTC time in RFC 3339 with `Z` suffix.
+    Utc,
+    /// Local time in a `chrono` strftime format, e.g. `"%H:%M:%S%.6f"`, checked by `init()`,
+    /// in the timezone of `LogConfig::timezone()`.
+    Custom(String),
+    /// Time elapsed since `init()`, e.g. `+0.012345s`, for benchmarking startup sequences.
+    Elapsed,
+    /// No timestamp, e.g. for CLI tools.
+    None,
+}
+impl TimestampMode {
+    /// Rejects a custom format with unknown or incomplete specifiers, which chrono reports only
+    /// when formatting.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        match self {
+            TimestampMode::Custom(format) if StrftimeItems::new(format).any(|item| item == Item::Error) => {
+                Err(format!("invalid timestamp format '{}'", format))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+/// Sub-second digits of the `LocalRfc3339`, `Utc` and `Elapsed` timestamps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampPrecision {
+    #[default]
+    Millis,
+    Micros,
+    Nanos,
+}
+/// How the module path is shown in the text output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ModuleDisplay {
+    /// `shvapp::rpc::client`
+    #[default]
+    Full,
+    /// `client`
+    LastSegment,
+    Hidden,
+}
+impl ModuleDisplay {
+    fn text(self, module: &str) -> Option<&str> {
+        match self {
+            ModuleDisplay::Full => Some(module),
+            ModuleDisplay::LastSegment => Some(module.rsplit("::").next().unwrap_or(module)),
+            ModuleDisplay::Hidden => None,
+        }
+    }
+}
+
+/// How messages with embedded newlines are written in the text output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Multiline {
+    /// The lines follow the first one unchanged.
+    #[default]
+    AsIs,
+    /// Continuation lines are indented under the message column.
+    Indent,
+    /// Each line is written with the full prefix of the record.
+    Prefix,
+}
+
+/// Text options of the file output. They come from the config passed to `init()` like the file
+/// writer itself, which `ShvLogHandle::reset()` keeps.
+#[derive(Clone, Copy, Default)]
+struct FileFormatOptions {
+    format: Option<LogFormat>,
+    max_line_len: Option<usize>,
+    module_display: ModuleDisplay,
+    show_line: bool,
+    show_file: bool,
+    multiline: Multiline,
+}
+impl FileFormatOptions {
+    fn of(file_log: &FileLog) -> FileFormatOptions {
+        FileFormatOptions {
+            format: file_log.format,
+            max_line_len: file_log.max_line_len,
+            module_display: file_log.module_display,
+            show_line: file_log.show_line,
+            show_file: file_log.show_file,
+            multiline: file_log.multiline,
+        }
+    }
+}
+
+/// Settings of the active config used by the format functions.
+#[derive(Default)]
+struct FormatOptions {
+    format: LogFormat,
+    colored: bool,
+    show_thread: bool,
+    show_hostname: bool,
+    show_pid: bool,
+    hostname: String,
+    pid: u32,
+    module_colors: bool,
+    palette: Palette,
+    timestamp: TimestampMode,
+    timestamp_precision: TimestampPrecision,
+    backtrace_level: Option<Level>,
+    max_line_len: Option<usize>,
+    module_display: ModuleDisplay,
+    show_line: bool,
+    multiline: Multiline,
+    show_file: bool,
+    file: FileFormatOptions,
+    file_path_prefix: Option<String>,
+    started: DateTime<Local>,
+    show_delta: bool,
+    /// Time of the last formatted record and its delta, a record written to several outputs
+    /// keeps the delta to the previous one.
+    last_record: Mutex<Option<(DateTime<Local>, chrono::Duration)>>,
+    template: Option<Template>,
+    prefix: String,
+    app_name: Option<String>,
+    instance_id: Option<String>,
+    /// Renamed members of the JSON output.
+    json_keys: Vec<(String, String)>,
+}
+
+impl FormatOptions {
+    /// Name of JSON member `key`, see `LogConfig::json_key()`.
+    fn json_key<'a>(&'a self, key: &'a str) -> &'a str {
+        self.json_keys.iter().find(|(from, _)| from == key).map_or(key, |(_, to)| to)
+    }
+    fn timestamp(&self, now: &mut DeferredNow) -> Option<String> {
+        let mut text = Vec::new();
+        match self.write_timestamp(&mut text, now) {
+            Ok(true) => Some(String::from_utf8_lossy(&text).into_owned()),
+            Ok(false) => None,
+            // custom formats are validated by activate()
+            Err(_) => Some("<invalid timestamp format>".into()),
+        }
+    }
+    /// Writes the timestamp as configured, false when there is none.
+    fn write_timestamp(&self, w: &mut dyn std::io::Write, now: &mut DeferredNow) -> std::io::Result<bool> {
+        let (local, utc) = match self.timestamp_precision {
+            TimestampPrecision::Millis => ("%Y-%m-%dT%H:%M:%S%.3f%:z", "%Y-%m-%dT%H:%M:%S%.3fZ"),
+            TimestampPrecision::Micros => ("%Y-%m-%dT%H:%M:%S%.6f%:z", "%Y-%m-%dT%H:%M:%S%.6fZ"),
+            TimestampPrecision::Nanos => ("%Y-%m-%dT%H:%M:%S%.9f%:z", "%Y-%m-%dT%H:%M:%S%.9fZ"),
+        };
+        match &self.timestamp {
+            TimestampMode::LocalRfc3339 => write!(w, "{}", timezone::localize(now.now()).format(local))?,
+            TimestampMode::Utc => write!(w, "{}", now.now_utc_owned().format(utc))?,
+            TimestampMode::Custom(format) => write!(w, "{}", timezone::localize(now.now()).format(format))?,
+            TimestampMode::Elapsed => write!(w, "+{}s", self.seconds(*now.now() - self.started))?,
+            TimestampMode::None => return Ok(false),
+        }
+        Ok(true)
+    }
+    /// Time since the previous record, if enabled by `LogConfig::show_delta()`.
+    fn delta(&self, now: &mut DeferredNow) -> Option<chrono::Duration> {
+        if !self.show_delta {
+            return None;
+        }
+        let now = *now.now();
+        let mut last_record = self.last_record.lock().unwrap();
+        let delta = match *last_record {
+            Some((time, delta)) if time == now => delta,
+            Some((time, _)) => now - time,
+            None => now - self.started,
+        };
+        *last_record = Some((now, delta));
+        Some(delta)
+    }
+    /// Seconds with the configured precision, e.g. `0.012345`.
+    fn seconds(&self, duration: chrono::Duration) -> Seconds {
+        Seconds(duration.num_nanoseconds().unwrap_or(i64::MAX).max(0), self.timestamp_precision)
+    }
+}
+
+/// Nanoseconds rendered as seconds with the precision, see `FormatOptions::seconds()`.
+struct Seconds(i64, TimestampPrecision);
+impl std::fmt::Display for Seconds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (secs, nanos) = (self.0 / 1_000_000_000, self.0 % 1_000_000_000);
+        match self.1 {
+            TimestampPrecision::Millis => write!(f, "{}.{:03}", secs, nanos / 1_000_000),
+            TimestampPrecision::Micros => write!(f, "{}.{:06}", secs, nanos / 1_000),
+            TimestampPrecision::Nanos => write!(f, "{}.{:09}", secs, nanos),
+        }
+    }
+}
+
+lazy_static! {
+    static ref FORMAT_OPTIONS: RwLock<FormatOptions> = RwLock::new(FormatOptions::default());
+    static ref INSTANCE_IDENTITY: RwLock<Option<InstanceIdentity>> = RwLock::new(None);
+    static ref HANDLE: RwLock<Option<ShvLogHandle>> = RwLock::new(None);
+}
 
 pub struct LogConfig {
-    module_levels: HashMap<String, log::Level>,
-    target_levels: HashMap<String, log::Level>
+    module_levels: Rules,
+    target_levels: Rules,
+    compound_levels: CompoundRules,
+    field_levels: Vec<FieldRule>,
+    threshold_cache: ThresholdCache,
+    /// Thresholds of `enabled()`, keyed by the target only.
+    enabled_cache: ThresholdCache,
+    message_filter: Option<String>,
+    redaction: Redaction,
+    default_level: LevelFilter,
+    /// Records matched by no rule.
+    default_hits: Hits,
+    match_mode: MatchMode,
+    format: LogFormat,
+    json_keys: Vec<(String, String)>,
+    color_mode: ColorMode,
+    output: Stream,
+    always_flush: bool,
+    buffer_capacity: usize,
+    flush_interval: Duration,
+    flush_level: LevelFilter,
+    show_thread: bool,
+    show_hostname: bool,
+    show_pid: bool,
+    module_colors: bool,
+    palette: Palette,
+    timestamp: TimestampMode,
+    timestamp_precision: TimestampPrecision,
+    timezone: Tz,
+    show_delta: bool,
+    backtrace_level: Option<Level>,
+    max_line_len: Option<usize>,
+    module_display: ModuleDisplay,
+    show_line: bool,
+    multiline: Multiline,
+    show_file: bool,
+    file_path_prefix: Option<String>,
+    format_template: Option<String>,
+    file_log: Option<FileLog>,
+    stderr_level: Option<log::Level>,
+    syslog: Option<Syslog>,
+    #[cfg(feature = "gelf")]
+    gelf: Option<Gelf>,
+    remote: Option<RemoteLog>,
+    shvjournal: Option<ShvJournal>,
+    #[cfg(feature = "otel")]
+    otel: Option<Otel>,
+    sinks: Vec<Box<dyn LogSink>>,
+    routes: Vec<Route>,
+    async_output: Option<(usize, OverflowPolicy)>,
+    #[cfg(all(unix, feature = "journald"))]
+    journald: bool,
+    #[cfg(all(unix, feature = "journald"))]
+    journald_message_ids: Vec<(String, u128)>,
+    app_name: Option<String>,
+    instance_id: Option<String>,
+    device_id: Option<fn() -> Option<String>>,
+    audit_path: Option<PathBuf>,
+    audit_file: Option<Mutex<File>>,
+    escalations: Vec<EscalationRule>,
+    quotas: Vec<Quota>,
+    bursts: Vec<Burst>,
+    dedup: Dedup,
+    drop_summary: Option<Duration>,
+    severities: [ShvSeverity; 5],
+    throttles: Vec<Throttle>,
+    samples: Vec<Sample>,
+    triggers: Vec<Trigger>,
+    load_monitor: Option<Arc<LoadMonitor>>,
+    heartbeat: Option<Arc<Heartbeat>>,
+    silence_rules: Vec<Arc<SilenceRule>>,
+    silence_callback: Option<fn(Option<&str>, Duration)>,
+    field_providers: Vec<(String, FieldProvider)>,
+    memory_buffer: Option<MemoryBuffer>,
+    crash_dump: Option<Arc<CrashDump>>,
+    incident_dumps: Option<IncidentDumps>,
+    persisted_verbosity: Option<PersistedVerbosity>,
+    #[cfg(all(unix, feature = "systemd"))]
+    systemd_watchdog: bool,
+    #[cfg(all(unix, feature = "systemd"))]
+    systemd_notifier: Option<Arc<systemd::Notifier>>,
 }
 impl LogConfig {
+    /// Creates config from `-d` (module) and `-v` (target) threshold strings.
+    ///
+    /// Thresholds are comma separated `pattern:level`, the level is `T`, `D`, `I`, `W`, `E`,
+    /// a level name like `debug` or a number from 1 (Error) to 5 (Trace), in any case.
+    /// `O`, `off` or 0 mutes the matching records completely, as does the `!pattern` shorthand,
+    /// e.g. `-d :T,!tokio,!mio` traces everything except the two crates. A missing level means Trace.
+    ///
+    /// Patterns containing `*` are globs matching the whole module or target, e.g. `shv*::client:T`
+    /// or `*broker*:D`. With the `regex` feature, patterns wrapped in slashes are regular expressions,
+    /// e.g. `/^shvbroker::(acl|mount)/:T`. When several patterns match, the longest one decides,
+    /// equally long ones in alphabetical order.
+    ///
+    /// Keys of the form `key=value` or `key~value` in either string are rules
+    /// matching key-value fields of the record, e.g. `peer=42:T` or `shv_path~^test/:D`.
+    /// A matching field rule takes precedence over module and target thresholds.
+    ///
+    /// Keys of the form `module@target` in either string match records by both their module
+    /// and target, e.g. `shvbroker@RpcData:T`, and take precedence over module and target rules.
+    ///
+    /// Invalid levels are reported on stderr and treated as Info, thresholds with an invalid
+    /// pattern are ignored. Use `try_new()` to get the error instead, `FilterRule` describes
+    /// the grammar.
     pub fn new(module_tresholds: &[String], target_tresholds: &[String]) -> LogConfig {
-        let mut lv = LogConfig {
-            module_levels: LogConfig::parse_level_strings(module_tresholds),
-            target_levels: LogConfig::parse_level_strings(target_tresholds),
-        };
+        let (module_levels, mut errors) = parse::parse_level_strings(module_tresholds);
+        let (target_levels, target_errors) = parse::parse_level_strings(target_tresholds);
+        errors.extend(target_errors);
+        for err in &errors {
+            eprintln!("shvlog: {}", err);
+        }
+        LogConfig::with_levels(module_levels, target_levels)
+    }
+    /// Creates config like `new()` with thresholds also taken from the environment, `RUST_LOG`
+    /// in env_logger syntax (e.g. `info,shvbroker::rpc=trace`) and `SHVLOG` as verbosity string
+    /// (e.g. `-d :D -v RpcMsg:T`). `SHVLOG` thresholds take precedence over `RUST_LOG` and
+    /// `module_tresholds` and `target_tresholds`, usually from the command line, over both.
+    pub fn from_env(module_tresholds: &[String], target_tresholds: &[String]) -> LogConfig {
+        let mut options = std::env::var("RUST_LOG").map(|spec| parse::parse_rust_log(&spec)).unwrap_or_default();
+        if let Ok(verbosity) = std::env::var("SHVLOG") {
+            match parse::parse_verbosity(&verbosity) {
+                Ok(shvlog) => {
+                    options.module_tresholds.extend(shvlog.module_tresholds);
+                    options.target_tresholds.extend(shvlog.target_tresholds);
+                    options.message_filter = shvlog.message_filter.or(options.message_filter);
+                }
+                Err(err) => eprintln!("shvlog: SHVLOG: {}", err),
+            }
+        }
+        options.module_tresholds.extend_from_slice(module_tresholds);
+        options.target_tresholds.extend_from_slice(target_tresholds);
+        let mut config = LogConfig::new(&options.module_tresholds, &options.target_tresholds);
+        config.message_filter = options.message_filter;
+        config
+    }
+    /// Loads config from a TOML file, e.g.
+    ///
+    /// ```toml
+    /// format = "text"             # text, json or systemd
+    /// color = "auto"              # auto, always or never
+    /// output = "stderr"           # stderr or stdout
+    /// module_colors = true        # module field colored by a hash of its name
+    /// match_mode = "module_path"  # substring or module_path
+    /// stderr_level = "I"
+    ///
+    /// [module]
+    /// "" = "I"
+    /// shvbroker = "D"
+    ///
+    /// [target]
+    /// RpcMsg = "T"
+    ///
+    /// [palette]                   # names, 256-color numbers or #rrggbb, e.g. "bold red"
+    /// error = "bold #ff5f5f"
+    /// module = "244"
+    ///
+    /// [file]
+    /// path = "/var/log/shvbroker.log"
+    /// rotate_size = 10_000_000
+    /// keep_files = 5
+    ///
+    /// [syslog]
+    /// udp = "collector:514"
+    /// facility = "local0"
+    /// ```
+    ///
+    /// Every output section accepts `level`, the least severe level written to the output.
+    ///
+    /// Other settings are `message_filter`, `app_name`, `instance_id`, `verbosity_state` (see
+    /// `persist_verbosity()`), `show_hostname`, `show_pid`, `always_flush`, `journald = true`, `template` (see `with_format()`), `timestamp` (local,
+    /// utc, elapsed, none or a strftime format), `timestamp_precision` (3, 6 or 9 digits),
+    /// `timezone` (see `Tz::parse()`), `max_line_len`, `module_display` (full, last_segment or hidden), `show_line`, `show_file` and
+    /// `multiline` (as_is, indent or prefix, also in `[file]`), `file_path_prefix`, `rotate_age`
+    /// (hour or day), `format`, `compress`, `mode`, `dir_mode` (e.g. `0o640`), `group` (a gid) and
+    /// `fsync` (never, always, on_error, `{ records = 100 }` or `{ interval = 5.0 }`) and
+    /// `windows_line_endings` in `[file]`, `tcp`, `unix`, `app_name`, a
+    /// `target_facilities` and a `severities` table (level to severity name) in `[syslog]`, `udp`, `tcp`, `chunk_size` and a `fields` table in `[gelf]`, `tcp`, `unix`,
+    /// `format` and `buffer` in `[remote]`, `dir`, `file_size` and `journal_size` in
+    /// `[shvjournal]`, `endpoint` and a `headers` table in `[otel]`, and a `json_keys` table
+    /// renaming the JSON members (see `json_key()`).
+    #[cfg(feature = "toml")]
+    pub fn from_toml_file(path: impl AsRef<std::path::Path>) -> Result<LogConfig, ConfigError> {
+        config::load(path.as_ref())
+    }
+    /// Creates config from a `-d ... -v ... -g ...` string as returned by `verbosity_string()`,
+    /// e.g. to start a child process with the thresholds of its parent.
+    pub fn from_verbosity_string(verbosity: &str) -> Result<LogConfig, ParseError> {
+        let options = parse::parse_verbosity(verbosity)?;
+        let mut config = LogConfig::try_new(&options.module_tresholds, &options.target_tresholds)?;
+        config.message_filter = options.message_filter;
+        Ok(config)
+    }
+    /// Creates config from `-d` (module) and `-v` (target) threshold strings like `new()`,
+    /// failing on the first invalid threshold.
+    pub fn try_new(module_tresholds: &[String], target_tresholds: &[String]) -> Result<LogConfig, ParseError> {
+        let (module_levels, errors) = parse::parse_level_strings(module_tresholds);
+        let (target_levels, target_errors) = parse::parse_level_strings(target_tresholds);
+        if let Some(err) = errors.into_iter().chain(target_errors).next() {
+            return Err(err);
+        }
+        Ok(LogConfig::with_levels(module_levels, target_levels))
+    }
+    /// Creates config from typed thresholds like `try_new()` from strings, the rules are usually
+    /// from `parse_rules()` or `module_rules()` and `target_rules()` of another config.
+    pub fn from_rules(module_rules: &[FilterRule], target_rules: &[FilterRule]) -> LogConfig {
+        let levels = |rules: &[FilterRule]| rules.iter().map(|rule| (rule.pattern.clone(), rule.level)).collect();
+        LogConfig::with_levels(levels(module_rules), levels(target_rules))
+    }
+    fn with_levels(module_levels: HashMap<String, LevelFilter>, target_levels: HashMap<String, LevelFilter>) -> LogConfig {
+        let mut lv = LogConfig::from_levels(module_levels, target_levels, LevelFilter::Info);
         if lv.module_levels.is_empty() {
-            lv.module_levels.insert("".into(), Level::Info);
+            lv.module_levels.insert("", LevelFilter::Info);
         }
         lv
     }
-    fn parse_level_strings(level_strings: &[String]) -> HashMap<String, log::Level> {
-        let mut levels = HashMap::new();
-        for tresholds in level_strings {
-            for level_str in tresholds.split(',') {
-                if level_str.is_empty() {
-                    continue;
+    /// Builder setting thresholds from typed levels instead of `-d`/`-v` strings, e.g.
+    /// `LogConfig::builder().module("shvbroker", LevelFilter::Debug).target("RpcMsg", LevelFilter::Trace).build()`.
+    pub fn builder() -> LogConfigBuilder {
+        LogConfigBuilder::new()
+    }
+    fn from_levels(mut module_levels: HashMap<String, LevelFilter>, mut target_levels: HashMap<String, LevelFilter>, default_level: LevelFilter) -> LogConfig {
+        let mut field_levels = FieldRule::extract(&mut module_levels);
+        field_levels.extend(FieldRule::extract(&mut target_levels));
+        let mut compound_levels = CompoundRules::default();
+        compound_levels.extract(&mut module_levels);
+        compound_levels.extract(&mut target_levels);
+        LogConfig {
+            module_levels: Rules::new(module_levels),
+            target_levels: Rules::new(target_levels),
+            compound_levels,
+            field_levels,
+            threshold_cache: ThresholdCache::default(),
+            enabled_cache: ThresholdCache::default(),
+            message_filter: None,
+            redaction: Redaction::default(),
+            default_level,
+            default_hits: Hits::default(),
+            match_mode: MatchMode::Substring,
+            format: LogFormat::Text,
+            json_keys: Vec::new(),
+            color_mode: ColorMode::Auto,
+            output: Stream::Stderr,
+            always_flush: true,
+            buffer_capacity: flexi_logger::DEFAULT_BUFFER_CAPACITY,
+            flush_interval: flexi_logger::DEFAULT_FLUSH_INTERVAL,
+            flush_level: LevelFilter::Warn,
+            show_thread: false,
+            show_hostname: false,
+            show_pid: false,
+            module_colors: false,
+            palette: Palette::default(),
+            timestamp: TimestampMode::LocalRfc3339,
+            timestamp_precision: TimestampPrecision::Millis,
+            timezone: Tz::Local,
+            show_delta: false,
+            backtrace_level: None,
+            max_line_len: None,
+            module_display: ModuleDisplay::Full,
+            multiline: Multiline::AsIs,
+            show_line: true,
+            show_file: false,
+            file_path_prefix: None,
+            format_template: None,
+            file_log: None,
+            stderr_level: None,
+            syslog: None,
+            #[cfg(feature = "gelf")]
+            gelf: None,
+            remote: None,
+            shvjournal: None,
+            #[cfg(feature = "otel")]
+            otel: None,
+            sinks: Vec::new(),
+            routes: Vec::new(),
+            async_output: None,
+            #[cfg(all(unix, feature = "journald"))]
+            journald: false,
+            #[cfg(all(unix, feature = "journald"))]
+            journald_message_ids: Vec::new(),
+            app_name: None,
+            instance_id: None,
+            device_id: None,
+            audit_path: None,
+            audit_file: None,
+            escalations: Vec::new(),
+            quotas: Vec::new(),
+            bursts: Vec::new(),
+            dedup: Dedup::default(),
+            drop_summary: None,
+            severities: severity::default_severities(),
+            throttles: Vec::new(),
+            samples: Vec::new(),
+            triggers: Vec::new(),
+            load_monitor: None,
+            heartbeat: None,
+            silence_rules: Vec::new(),
+            silence_callback: None,
+            field_providers: Vec::new(),
+            memory_buffer: None,
+            crash_dump: None,
+            incident_dumps: None,
+            persisted_verbosity: None,
+            #[cfg(all(unix, feature = "systemd"))]
+            systemd_watchdog: false,
+            #[cfg(all(unix, feature = "systemd"))]
+            systemd_notifier: None,
+        }
+    }
+    /// Only lets through records whose message contains `pattern`, checked after the level
+    /// thresholds, e.g. `with_message_filter("device=42")`. Audit records are not filtered.
+    pub fn with_message_filter(mut self, pattern: &str) -> Self {
+        self.message_filter = Some(pattern.into());
+        self
+    }
+    /// Masks the matches of regular expression `pattern` in the messages before the records
+    /// reach any output, e.g. `redact(r"password=(\S+)")`. With a capture group only the group
+    /// is masked. The records below the thresholds are dropped before the redaction. An invalid
+    /// pattern is an error.
+    #[cfg(feature = "regex")]
+    pub fn redact(mut self, pattern: &str) -> Result<Self, ParseError> {
+        let regex = regex::Regex::new(pattern).map_err(|err| ParseError::InvalidPattern { token: pattern.into(), reason: err.to_string() })?;
+        self.redaction.add_pattern(regex);
+        Ok(self)
+    }
+    /// Masks the value of key-value field `key` before the records reach any output.
+    pub fn redact_field(mut self, key: &str) -> Self {
+        self.redaction.add_field(key);
+        self
+    }
+    /// How `-d` rules match module paths, substring by default. Target rules always match
+    /// as substrings.
+    pub fn with_match_mode(mut self, match_mode: MatchMode) -> Self {
+        self.match_mode = match_mode;
+        self
+    }
+    /// Output format of the records, colored text by default.
+    pub fn with_log_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+    /// Renames member `key` of the JSON output to `name`, e.g. `json_key("timestamp", "@timestamp")`
+    /// to match an existing ingestion mapping. The members of `fields` keep their names.
+    pub fn json_key(mut self, key: &str, name: &str) -> Self {
+        self.json_keys.retain(|(from, _)| from != key);
+        self.json_keys.push((key.into(), name.into()));
+        self
+    }
+    /// Coloring of the text output on stderr, detected from the terminal by default.
+    pub fn with_color(mut self, color_mode: ColorMode) -> Self {
+        self.color_mode = color_mode;
+        self
+    }
+    /// Includes name of the logging thread, or its id for unnamed threads, in every record.
+    pub fn show_thread(mut self, show: bool) -> Self {
+        self.show_thread = show;
+        self
+    }
+    /// Console stream of the records, stderr by default. `with_stderr_level()` duplicates to this stream.
+    pub fn output(mut self, stream: Stream) -> Self {
+        self.output = stream;
+        self
+    }
+    /// Writes every record through to the console and file immediately, on by default.
+    /// When off the output is buffered and flushed every second, on Warn and more severe
+    /// records and by `ShvLogHandle::flush()`, which is cheaper for chatty loggers but may
+    /// delay or, on abort, lose the last records.
+    pub fn always_flush(mut self, enabled: bool) -> Self {
+        self.always_flush = enabled;
+        self
+    }
+    /// Buffers up to `capacity` bytes of output like `always_flush(false)`, e.g. so that trace
+    /// logging to slow eMMC storage does not block the application.
+    pub fn buffer(mut self, capacity: usize) -> Self {
+        self.always_flush = false;
+        self.buffer_capacity = capacity;
+        self
+    }
+    /// Interval of flushing the buffered output, one second by default.
+    pub fn flush_every(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+    /// Flushes the buffered output right after records at `level` or more severe, Warn by
+    /// default, so that important records reach the disk promptly. `LevelFilter::Off` leaves
+    /// the flushing to the interval.
+    pub fn flush_on(mut self, level: LevelFilter) -> Self {
+        self.flush_level = level;
+        self
+    }
+    /// Shows the hostname detected at `init()` in the text and JSON output.
+    pub fn show_hostname(mut self, show: bool) -> Self {
+        self.show_hostname = show;
+        self
+    }
+    /// Shows the process ID in the text and JSON output.
+    pub fn show_pid(mut self, show: bool) -> Self {
+        self.show_pid = show;
+        self
+    }
+    /// Colors the module field of the text output by a hash of the module name, so that
+    /// each module gets a stable color, instead of yellow for all.
+    pub fn module_colors(mut self, enabled: bool) -> Self {
+        self.module_colors = enabled;
+        self
+    }
+    /// Colors of the levels, timestamp, module and target in the colored text output.
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+    /// Timestamp of the records in the text and JSON output, local time by default.
+    pub fn timestamp(mut self, mode: TimestampMode) -> Self {
+        self.timestamp = mode;
+        self
+    }
+    /// Timezone of all rendered timestamps regardless of the system timezone, e.g.
+    /// `Tz::utc()` or `Tz::Fixed(offset)` on devices without a configured timezone, or
+    /// `Tz::named("Europe/Prague")` with the `tz` feature. The system timezone by default.
+    pub fn timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+    /// Sub-second digits of the timestamps, milliseconds by default. Microseconds or
+    /// nanoseconds help correlating RPC message traces with packet captures.
+    pub fn timestamp_precision(mut self, precision: TimestampPrecision) -> Self {
+        self.timestamp_precision = precision;
+        self
+    }
+    /// Includes the time since the previous record, e.g. `+0.000120s`, to spot slow steps.
+    pub fn show_delta(mut self, show: bool) -> Self {
+        self.show_delta = show;
+        self
+    }
+    /// Appends a backtrace of the logging thread to records at `level` or more severe,
+    /// e.g. `capture_backtrace(Level::Error)`. Capturing is slow, this is meant for rare records.
+    pub fn capture_backtrace(mut self, level: Level) -> Self {
+        self.backtrace_level = Some(level);
+        self
+    }
+    /// Truncates messages longer than `max_len` bytes on stderr, e.g. huge RpcData traces.
+    /// The file output keeps full messages unless limited by `FileLog::max_line_len()`.
+    pub fn max_line_len(mut self, max_len: usize) -> Self {
+        self.max_line_len = Some(max_len);
+        self
+    }
+    /// Module path in the text output on stderr, the file output is set by `FileLog::module_display()`.
+    pub fn module_display(mut self, display: ModuleDisplay) -> Self {
+        self.module_display = display;
+        self
+    }
+    /// Layout of multi-line messages, e.g. pretty printed CPON, on stderr, the file output is
+    /// set by `FileLog::multiline()`.
+    pub fn multiline(mut self, multiline: Multiline) -> Self {
+        self.multiline = multiline;
+        self
+    }
+    /// Shows the line number next to the module path on stderr, on by default.
+    pub fn show_line(mut self, show: bool) -> Self {
+        self.show_line = show;
+        self
+    }
+    /// Shows the source file path, e.g. `src/rpc/client.rs:81`, after the module path on stderr.
+    /// Combined with `ModuleDisplay::Hidden` the file path replaces the module path.
+    pub fn show_file(mut self, show: bool) -> Self {
+        self.show_file = show;
+        self
+    }
+    /// Strips `prefix` from the source file paths, e.g. the workspace directory or
+    /// `$CARGO_HOME/registry/src/` to shorten the paths of dependencies.
+    pub fn file_path_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.file_path_prefix = Some(prefix.into());
+        self
+    }
+    /// Text layout of the records, e.g. `"{ts} {level} {module}:{line} {target} {msg}"`, parsed by `init()`.
+    /// Placeholders are `{ts}`, `{level}`, `{lvl}` (level letter), `{module}`, `{file}`, `{line}`, `{target}`,
+    /// `{msg}`, `{fields}`, `{prefix}` (application name and instance ID), `{host}`, `{pid}` and `{thread}`,
+    /// `{{` and `}}` escape the braces. Applies to the `Text` and `Systemd` formats, the systemd priority is still prepended.
+    pub fn with_format(mut self, template: &str) -> Self {
+        self.format_template = Some(template.into());
+        self
+    }
+    /// Writes records to a file instead of stderr. The file output is set up by
+    /// `init()` and is not changed by `ShvLogHandle::reset()`.
+    pub fn with_file(mut self, file_log: FileLog) -> Self {
+        self.file_log = Some(file_log);
+        self
+    }
+    /// With file, journald or syslog output, also writes records up to `level` to stderr, e.g. Info on
+    /// stderr for the journal while the file gets everything passing the thresholds.
+    pub fn with_stderr_level(mut self, level: Level) -> Self {
+        self.stderr_level = Some(level);
+        self
+    }
+    /// Sends records to journald with structured fields (`CODE_MODULE`, `CODE_LINE`,
+    /// `TARGET`, ...) instead of stderr, in addition to the file output if configured.
+    /// Key-value fields are sent uppercased, e.g. `peer_id` as `PEER_ID`, those named like a
+    /// journald field with `SHV_` prefix, e.g. `message` as `SHV_MESSAGE`.
+    #[cfg(all(unix, feature = "journald"))]
+    pub fn with_journald(mut self) -> Self {
+        self.journald = true;
+        self
+    }
+    /// Sends records of `target` to journald with `MESSAGE_ID` set to `id`, a message catalog
+    /// entry, e.g. `journalctl MESSAGE_ID=...` then lists all of the records of the target.
+    #[cfg(all(unix, feature = "journald"))]
+    pub fn journald_message_id(mut self, target: &str, id: u128) -> Self {
+        self.journald_message_ids.retain(|(t, _)| t != target);
+        self.journald_message_ids.push((target.into(), id));
+        self
+    }
+    /// Sends records to a syslog collector in RFC 5424 format instead of stderr,
+    /// in addition to the file and journald output if configured.
+    pub fn with_syslog(mut self, syslog: Syslog) -> Self {
+        self.syslog = Some(syslog);
+        self
+    }
+    /// Maps records of `level` to `severity` instead of `ShvSeverity::from(level)` in the RPC
+    /// streaming, journald, syslog, GELF and the sd-daemon prefix, e.g. to raise Warn records to
+    /// SHV error alarms.
+    pub fn severity(mut self, level: Level, severity: ShvSeverity) -> Self {
+        self.severities[level as usize - 1] = severity;
+        self
+    }
+    /// Sends records to Graylog in GELF format instead of stderr, in addition to the
+    /// other configured outputs.
+    #[cfg(feature = "gelf")]
+    pub fn with_gelf(mut self, gelf: Gelf) -> Self {
+        self.gelf = Some(gelf);
+        self
+    }
+    /// Streams records to a collector over TCP or a Unix socket instead of stderr, in
+    /// addition to the other configured outputs.
+    pub fn with_remote(mut self, remote: RemoteLog) -> Self {
+        self.remote = Some(remote);
+        self
+    }
+    /// Writes records to a shvjournal directory instead of stderr, in addition to the other
+    /// configured outputs.
+    pub fn with_shvjournal(mut self, journal: ShvJournal) -> Self {
+        self.shvjournal = Some(journal);
+        self
+    }
+    /// Exports records to an OpenTelemetry collector instead of stderr, in addition to the
+    /// other configured outputs.
+    #[cfg(feature = "otel")]
+    pub fn with_otel(mut self, otel: Otel) -> Self {
+        self.otel = Some(otel);
+        self
+    }
+    /// Passes the records to a custom backend instead of stderr, in addition to the other
+    /// configured outputs. Several sinks can be added, they are set up by `init()` and not
+    /// changed by `ShvLogHandle::reset()`.
+    pub fn add_sink(mut self, sink: Box<dyn LogSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+    /// Adds a rule directing records to some of the outputs, e.g. Warn and more severe records
+    /// to the GELF output or RpcData only to the file, see `Route`. The routes are set up by
+    /// `init()` and not changed by `ShvLogHandle::reset()`.
+    pub fn route(mut self, route: Route) -> Self {
+        self.routes.push(route);
+        self
+    }
+    /// Writes the console output from a dedicated thread, the logging thread only formats
+    /// the record and queues it. Up to `capacity` records are queued, `policy` decides what
+    /// happens to further records, see `ShvLogHandle::dropped_records()`.
+    ///
+    /// Applies when the console is the only output, set up by `init()` and not changed by
+    /// `ShvLogHandle::reset()`.
+    pub fn with_async(mut self, capacity: usize, policy: OverflowPolicy) -> Self {
+        self.async_output = Some((capacity, policy));
+        self
+    }
+    /// Application name prepended to every record, useful when several services share one log stream.
+    pub fn with_app_name(mut self, app_name: &str) -> Self {
+        self.app_name = Some(app_name.into());
+        self
+    }
+    /// Instance ID prepended to every record after the application name.
+    pub fn with_instance_id(mut self, instance_id: &str) -> Self {
+        self.instance_id = Some(instance_id.into());
+        self
+    }
+    /// Callback providing the SHV device ID, evaluated once at `init()`.
+    /// The device ID is used as instance ID unless one is set explicitly.
+    pub fn with_device_id(mut self, device_id: fn() -> Option<String>) -> Self {
+        self.device_id = Some(device_id);
+        self
+    }
+    /// Append-only file receiving the records of the audit channel, see `audit!`.
+    pub fn with_audit_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.audit_path = Some(path.into());
+        self
+    }
+    /// Prepares the config to become the active filter, done by `init()` and `ShvLogHandle::reset()`.
+    fn activate(&mut self, identity: &InstanceIdentity) -> std::io::Result<()> {
+        self.timestamp.validate().map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+        if let Some(mut persisted) = self.persisted_verbosity.take() {
+            let saved = persisted.load(self.verbosity_string());
+            self.persisted_verbosity = Some(persisted);
+            if let Some(saved) = saved? {
+                if let Err(err) = self.set_verbosity(&saved) {
+                    eprintln!("shvlog: ignoring the saved verbosity '{}': {}", saved, err);
                 }
-                let parts: Vec<&str> = level_str.split(':').collect();
-                let (target, level_abbr) = if parts.len() == 1 {
-                    (parts[0], "T")
-                } else if parts.len() == 2 {
-                    (parts[0], parts[1])
-                } else {
-                    panic!("Cannot happen");
-                };
-                let level = match level_abbr {
-                    "T" => log::Level::Trace,
-                    "D" => log::Level::Debug,
-                    "I" => log::Level::Info,
-                    "W" => log::Level::Warn,
-                    "E" => log::Level::Error,
-                    _ => log::Level::Info,
-                };
-                levels.insert(target.into(), level);
-            }
-        }
-        levels
-    }
-    fn levels_to_string(levels: &HashMap<String, log::Level>) -> String {
-        levels.iter()
-            .map(|(target, level)| format!("{}:{}", target, level))
-            .fold(String::new(), |acc, s| if acc.is_empty() { s } else { acc + "," + &s })
+            }
+        }
+        self.open_audit_file()?;
+        let instance_id = self.instance_id.clone().or_else(|| identity.device_id.clone());
+        // the file writer of init() stays, and so do its options
+        let file = FORMAT_OPTIONS.read().unwrap().file;
+        *FORMAT_OPTIONS.write().unwrap() = FormatOptions {
+            format: self.format,
+            colored: self.color_mode.enabled(self.output),
+            show_thread: self.show_thread,
+            show_hostname: self.show_hostname,
+            show_pid: self.show_pid,
+            hostname: identity.hostname.clone(),
+            pid: identity.pid,
+            module_colors: self.module_colors,
+            palette: self.palette.clone(),
+            timestamp: self.timestamp.clone(),
+            timestamp_precision: self.timestamp_precision,
+            started: identity.start_time,
+            show_delta: self.show_delta,
+            backtrace_level: self.backtrace_level,
+            max_line_len: self.max_line_len,
+            module_display: self.module_display,
+            show_line: self.show_line,
+            multiline: self.multiline,
+            show_file: self.show_file,
+            file,
+            file_path_prefix: self.file_path_prefix.clone(),
+            last_record: Mutex::new(None),
+            template: self.format_template.as_deref().map(Template::parse),
+            prefix: LogConfig::record_prefix(self.app_name.as_ref(), instance_id.as_ref()),
+            app_name: self.app_name.clone(),
+            instance_id,
+            json_keys: self.json_keys.clone(),
+        };
+        severity::set_severities(self.severities);
+        fields::set_enrichment(self.field_providers.clone());
+        timezone::set_timezone(self.timezone.clone());
+        silence::spawn_detector(self.silence_rules.iter().map(Arc::downgrade).collect(), self.silence_callback)?;
+        if let Some(monitor) = &self.load_monitor {
+            load::spawn_monitor(Arc::downgrade(monitor))?;
+        }
+        if let Some(heartbeat) = &self.heartbeat {
+            heartbeat::spawn_heartbeat(Arc::downgrade(heartbeat), identity.start_time)?;
+        }
+        crash::install(self.crash_dump.as_ref());
+        #[cfg(all(unix, feature = "systemd"))]
+        if let Some(notifier) = self.systemd_notifier.as_ref().filter(|_| self.systemd_watchdog) {
+            systemd::spawn_watchdog(Arc::downgrade(notifier))?;
+        }
+        Ok(())
+    }
+    fn open_audit_file(&mut self) -> std::io::Result<()> {
+        if let Some(path) = &self.audit_path {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            self.audit_file = Some(Mutex::new(file));
+        }
+        Ok(())
+    }
+    fn write_audit(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        if let Some(file) = &self.audit_file {
+            let mut file = file.lock().unwrap();
+            writeln!(
+                file,
+                "{}[{}:{}]{}",
+                timezone::localize(now.now()).format("%Y-%m-%dT%H:%M:%S%.3f"),
+                record.module_path().unwrap_or("<unnamed>"),
+                record.line().unwrap_or(0),
+                record.args(),
+            )?;
+        }
+        Ok(())
+    }
+    /// Emits a synthesized Error record when `target` logs more than `max_count` warnings within `window`.
+    pub fn escalate(mut self, target: &str, max_count: usize, window: Duration) -> Self {
+        self.escalations.push(EscalationRule::new(target, max_count, window));
+        self
+    }
+    /// Allows records of `target` to write at most `max_bytes` of message text per `window`,
+    /// further records are suppressed with a notice until the window resets.
+    pub fn quota(mut self, target: &str, max_bytes: usize, window: Duration) -> Self {
+        self.quotas.push(Quota::new(target, max_bytes, window));
+        self
+    }
+    /// Lets through the first `max_count` records at `level` or more severe from targets or
+    /// modules containing `pattern` within `window`, e.g. `burst("modbus", Level::Error, 10,
+    /// Duration::from_secs(60))` during an outage. Further records are muted until the window
+    /// expires, with a notice when muting starts and ends.
+    pub fn burst(mut self, pattern: &str, level: Level, max_count: usize, window: Duration) -> Self {
+        self.bursts.push(Burst::new(pattern, level, max_count, window));
+        self
+    }
+    /// Lets through at most `rate` records per second, with bursts of up to `burst` records,
+    /// from targets or modules containing `pattern`, e.g. `throttle("RpcData", 10.0, 10)`.
+    /// The number of dropped records is reported once records are admitted again.
+    pub fn throttle(mut self, pattern: &str, rate: f64, burst: usize) -> Self {
+        self.throttles.push(Throttle::new(pattern, rate, burst));
+        self
+    }
+    /// Keeps the `ratio` fraction of the records from targets or modules containing `pattern`,
+    /// e.g. `sample("RpcData", 0.01)` keeps 1%. The records are selected by hash of their
+    /// content, not randomly, so that repeated runs are comparable.
+    pub fn sample(mut self, pattern: &str, ratio: f64) -> Self {
+        self.samples.push(Sample::new(pattern, ratio));
+        self
+    }
+    /// Collapses consecutive identical records of `level` (same module, target and message)
+    /// into a "last message repeated N times" notice, written at most once per `interval`
+    /// while the repetitions go on and when a different record arrives.
+    pub fn dedup(mut self, level: Level, interval: Duration) -> Self {
+        self.dedup.set_interval(level, interval);
+        self
+    }
+    /// Writes a Warn record summarizing the records dropped by sampling, throttling, quotas,
+    /// bursts, load shedding and async queue overflow, at most once per `interval`, e.g.
+    /// "dropped 1523 RpcData trace records by throttling in the last 60s".
+    pub fn drop_summary(mut self, interval: Duration) -> Self {
+        self.drop_summary = Some(interval);
+        self
+    }
+    /// After an Error record from `target`, raises the targets and modules in `raised`
+    /// (or `target` itself when empty) to `level` for `duration`, then reverts.
+    pub fn trigger(mut self, target: &str, raised: &[&str], level: Level, duration: Duration) -> Self {
+        self.triggers.push(Trigger::new(target, raised, level, duration));
+        self
+    }
+    /// Suppresses Debug and Trace records while the one minute load average per CPU
+    /// exceeds `threshold`, checked every `interval`. Transitions are logged.
+    pub fn adapt_to_load(mut self, threshold: f64, interval: Duration) -> Self {
+        self.load_monitor = Some(Arc::new(LoadMonitor::new(threshold, interval)));
+        self
+    }
+    /// Emits an Info record with the uptime and the counts of written and dropped records
+    /// every `interval`, e.g. as a liveness signal for the fleet monitoring.
+    pub fn heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(Arc::new(Heartbeat::new(interval)));
+        self
+    }
+    /// Emits a Warn record when no records at all, or none from `target`, were produced for `period`.
+    pub fn detect_silence(mut self, target: Option<&str>, period: Duration) -> Self {
+        self.silence_rules.push(Arc::new(SilenceRule::new(target, period)));
+        self
+    }
+    /// Calls `callback` with the silent target and silence duration instead of emitting a Warn record.
+    pub fn on_silence(mut self, callback: fn(Option<&str>, Duration)) -> Self {
+        self.silence_callback = Some(callback);
+        self
+    }
+    /// Adds a `name=value` field to every record, e.g. the device serial number or site name.
+    /// It is rendered by all outputs, journald gets it uppercased like `DEVICE=...`.
+    pub fn with_field(self, name: &str, value: impl ToString) -> Self {
+        let value = value.to_string();
+        self.with_field_fn(name, move || value.clone())
+    }
+    /// Adds a `name=value` field to every record like `with_field()`, computed when the record
+    /// is written, e.g. to add the uptime or the firmware version read once it is known.
+    ///
+    /// `value` is called only for records passing the filter, on the logging thread and once
+    /// per output the record is written to, so it should be cheap and must not log.
+    pub fn with_field_fn(mut self, name: &str, value: impl Fn() -> String + Send + Sync + 'static) -> Self {
+        self.field_providers.push((name.into(), Arc::new(value)));
+        self
+    }
+    /// Keeps the last `capacity` emitted records in memory, see `ShvLogHandle::snapshot()`.
+    pub fn with_memory_buffer(mut self, capacity: usize) -> Self {
+        self.memory_buffer = Some(MemoryBuffer::new(capacity));
+        self
+    }
+    /// Keeps the last `capacity` records up to `level`, even those below the thresholds, and
+    /// writes them to a `crash-<timestamp>.log` file in `dir` on a panic, or with the signals
+    /// feature on SIGSEGV and SIGABRT. The panic message is written in front of them.
+    ///
+    /// The signal handler cannot lock nor allocate, it writes the last 64 KiB of the kept
+    /// records to `crash-<UTC timestamp>Z.log` in `dir`, which is opened when the config is
+    /// activated.
+    pub fn with_crash_dump(mut self, dir: impl Into<PathBuf>, capacity: usize, level: Level) -> Self {
+        self.crash_dump = Some(Arc::new(CrashDump::new(dir.into(), capacity, level)));
+        self
+    }
+    /// On an Error record, writes the `before` records preceding it, the record itself and the
+    /// `after` records following it up to `level`, even those below the thresholds, to a new
+    /// `incident-<id>.log` file in `dir`. A Warn record with target "shvlog" and an `incident`
+    /// field referencing the file is written to the outputs after the Error. Errors while the
+    /// incident collects the following records are part of it.
+    pub fn with_incident_dumps(mut self, dir: impl Into<PathBuf>, before: usize, after: usize, level: Level) -> Self {
+        self.incident_dumps = Some(IncidentDumps::new(dir.into(), before, after, level));
+        self
+    }
+    /// Saves the thresholds and the message filter to the `path` state file whenever they are
+    /// changed at runtime, by `ShvLogHandle`, RPC or a signal, and applies the saved ones in
+    /// place of those of this config when it is activated, so that a device keeps its adjusted
+    /// verbosity across restarts. `ShvLogHandle::reset_verbosity()` returns to the ones of
+    /// this config. Temporary changes of `set_level_for()` are saved too until reverted.
+    pub fn persist_verbosity(mut self, path: impl Into<PathBuf>) -> Self {
+        self.persisted_verbosity = Some(PersistedVerbosity::new(path.into()));
+        self
+    }
+    /// Pushes the most recent Error message and logging failures into systemd's `STATUS=`
+    /// and, with `watchdog` set, sends watchdog keepalives while records are written successfully.
+    /// Does nothing when not started by systemd.
+    #[cfg(all(unix, feature = "systemd"))]
+    pub fn notify_systemd(mut self, watchdog: bool) -> Self {
+        self.systemd_notifier = systemd::Notifier::from_env();
+        self.systemd_watchdog = watchdog;
+        self
+    }
+    /// Threshold from the most specific `module@target`, module or target rule; records with
+    /// a target set are matched against `-v` rules only.
+    fn static_threshold(&self, module: &str, target: &str) -> (LevelFilter, Option<&str>) {
+        let (level, rule) = self.static_rule(module, target);
+        (level, rule.map(|(key, _)| key))
+    }
+    /// Like `static_threshold()` with the hit counter of the deciding rule.
+    fn static_rule(&self, module: &str, target: &str) -> (LevelFilter, Option<(&str, &Hits)>) {
+        let is_target_set = module != target;
+        let found = if let Some(found) = self.compound_levels.find(module, target, self.match_mode) {
+            Some(found)
+        } else if is_target_set {
+            self.target_levels.find(target, MatchMode::Substring)
+        } else {
+            self.module_levels.find(module, self.match_mode)
+        };
+        match found {
+            Some((key, level, hits)) => (level, Some((key, hits))),
+            None => (self.default_level, None),
+        }
+    }
+    /// Whether a record of `metadata` may pass, the answer of `log_enabled!()`. The metadata
+    /// has no module path, so the target is matched both as a module and as a target, and the
+    /// field rules and the overlays of subscribers are assumed to match.
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        let target = metadata.target();
+        if target == AUDIT_TARGET || !self.silence_rules.is_empty() || test::capturing() {
+            return true;
+        }
+        if self.crash_dump.as_ref().is_some_and(|dump| metadata.level() <= dump.level) {
+            return true;
+        }
+        if self.incident_dumps.as_ref().is_some_and(|dumps| metadata.level() <= dumps.level) {
+            return true;
+        }
+        let threshold = self.enabled_cache.get_or_resolve("", target, || {
+            let as_module = self.static_threshold(target, target).0;
+            let as_target = self.static_threshold("", target).0;
+            let compound = self.compound_levels.max_for_target(target).unwrap_or(LevelFilter::Off);
+            as_module.max(as_target).max(compound)
+        });
+        let record = log::Record::builder().metadata(metadata.clone()).build();
+        let threshold = self.field_levels.iter()
+            .map(|rule| rule.level)
+            .chain(std::iter::once(subscribe::overlay_max_level()))
+            .chain(self.triggers.iter().filter_map(|trigger| trigger.raised_level(&record)).map(|level| level.to_level_filter()))
+            .fold(threshold, Ord::max);
+        metadata.level() <= threshold && !self.load_monitor.as_ref().is_some_and(|monitor| monitor.suppresses(&record))
+    }
+    /// Most verbose level a record may pass with. Set as `log::max_level()`, so that the
+    /// log macros skip more verbose records before evaluating their arguments.
+    fn max_level(&self) -> LevelFilter {
+        // silence detection counts records of all levels, captures take all records
+        if !self.silence_rules.is_empty() || test::capturing() {
+            return LevelFilter::Trace;
+        }
+        // audit records are Info, escalations count the warnings
+        let mut level = self.default_level.max(LevelFilter::Info);
+        if !self.escalations.is_empty() {
+            level = level.max(LevelFilter::Warn);
+        }
+        if let Some(dump) = &self.crash_dump {
+            level = level.max(dump.level.to_level_filter());
+        }
+        if let Some(dumps) = &self.incident_dumps {
+            level = level.max(dumps.level.to_level_filter());
+        }
+        let rules = self.module_levels.iter()
+            .chain(self.target_levels.iter())
+            .chain(self.compound_levels.iter())
+            .map(|(_, level)| level)
+            .chain(self.field_levels.iter().map(|rule| rule.level))
+            .chain(self.triggers.iter().map(|trigger| trigger.level.to_level_filter()));
+        for rule_level in rules {
+            level = level.max(rule_level);
+        }
+        level
+    }
+    /// Applies `max_level()` of the active config, raised for the overlays of subscribers, and
+    /// drops the thresholds cached with the previous rules, called after every change of the rules.
+    /// Level of the `-d` rule `pattern`, or of the `-v` one with `target`.
+    pub(crate) fn rule_level(&self, pattern: &str, target: bool) -> Option<LevelFilter> {
+        match PatternKind::of(pattern) {
+            PatternKind::Field => FieldRule::get(&self.field_levels, pattern),
+            PatternKind::ModuleTarget => self.compound_levels.get(pattern),
+            _ if target => self.target_levels.get(pattern),
+            _ => self.module_levels.get(pattern),
+        }
+    }
+    /// Sets the level of the `-d` rule `pattern`, or of the `-v` one with `target`, the
+    /// `module@target` and field patterns become such rules like in `new()`.
+    pub(crate) fn set_rule_level(&mut self, pattern: &str, level: LevelFilter, target: bool) {
+        match PatternKind::of(pattern) {
+            PatternKind::Field => FieldRule::insert(&mut self.field_levels, pattern, level),
+            PatternKind::ModuleTarget => self.compound_levels.insert(pattern, level),
+            _ if target => self.target_levels.insert(pattern, level),
+            _ => self.module_levels.insert(pattern, level),
+        }
+    }
+    /// Removes the rule set by `set_rule_level()`.
+    pub(crate) fn remove_rule(&mut self, pattern: &str, target: bool) {
+        match PatternKind::of(pattern) {
+            PatternKind::Field => FieldRule::remove(&mut self.field_levels, pattern),
+            PatternKind::ModuleTarget => self.compound_levels.remove(pattern),
+            _ if target => self.target_levels.remove(pattern),
+            _ => self.module_levels.remove(pattern),
+        }
+    }
+    fn update_max_level(&self) {
+        self.threshold_cache.clear();
+        self.enabled_cache.clear();
+        log::set_max_level(self.max_level().max(subscribe::overlay_max_level()));
+        if let Some(persisted) = &self.persisted_verbosity {
+            persisted.save(self.verbosity_string());
+        }
+    }
+    /// Replaces the thresholds and the message filter by those of a `verbosity_string()`.
+    fn set_verbosity(&mut self, verbosity: &str) -> Result<(), ParseError> {
+        let levels = LogConfig::from_verbosity_string(verbosity)?;
+        self.module_levels = levels.module_levels;
+        self.target_levels = levels.target_levels;
+        self.compound_levels = levels.compound_levels;
+        self.field_levels = levels.field_levels;
+        self.message_filter = levels.message_filter;
+        Ok(())
+    }
+    /// Dry-run rule matching, tells which rule decides a record with given module, target and level.
+    pub fn explain(&self, module: &str, target: &str, level: Level) -> Explanation {
+        let (threshold, rule) = if target == AUDIT_TARGET {
+            (LevelFilter::Trace, MatchedRule::Audit)
+        } else {
+            match self.static_threshold(module, target) {
+                (level, Some(key)) if CompoundRules::split(key).is_some() => (level, MatchedRule::ModuleTarget(key.into())),
+                (level, Some(key)) if module != target => (level, MatchedRule::Target(key.into())),
+                (level, Some(key)) => (level, MatchedRule::Module(key.into())),
+                (level, None) => (level, MatchedRule::Default),
+            }
+        };
+        Explanation {
+            module: module.into(),
+            target: target.into(),
+            level,
+            threshold,
+            rule,
+            passes: level <= threshold,
+        }
+    }
+    fn record_prefix(app_name: Option<&String>, instance_id: Option<&String>) -> String {
+        let parts: Vec<&str> = app_name.into_iter().chain(instance_id).map(|s| s.as_str()).collect();
+        if parts.is_empty() { "".into() } else { format!("<{}>", parts.join("/")) }
+    }
+    /// Module thresholds including the module@target and field ones, e.g. to change them
+    /// and create a config with `from_rules()`.
+    pub fn module_rules(&self) -> Vec<FilterRule> {
+        self.module_levels.iter()
+            .chain(self.compound_levels.iter())
+            .map(|(pattern, level)| FilterRule::new(pattern, level))
+            .chain(self.field_levels.iter().map(|rule| FilterRule::new(&rule.to_string(), rule.level)))
+            .collect()
+    }
+    pub fn target_rules(&self) -> Vec<FilterRule> {
+        self.target_levels.iter().map(|(pattern, level)| FilterRule::new(pattern, level)).collect()
+    }
+    /// Module, target, module@target and field rules, most specific first, then the default threshold.
+    pub(crate) fn rule_infos(&self) -> Vec<RuleInfo> {
+        self.module_levels.infos(RuleSource::Module, self.match_mode)
+            .chain(self.target_levels.infos(RuleSource::Target, MatchMode::Substring))
+            .chain(self.compound_levels.infos(self.match_mode))
+            .chain(self.field_levels.iter().map(|rule| RuleInfo::new(RuleSource::Field, &rule.to_string(), MatchMode::Substring, rule.level, &rule.hits)))
+            .chain(std::iter::once(RuleInfo::new(RuleSource::Default, "", self.match_mode, self.default_level, &self.default_hits)))
+            .collect()
     }
+    /// `-d` and `-v` threshold strings of the module and target rules, empty when there are none.
+    fn threshold_strings(&self) -> (String, String) {
+        (format_rules(&self.module_rules()), format_rules(&self.target_rules()))
+    }
+    /// Thresholds and message filter as `-d ... -v ... -g ...` string, parsed back by `from_verbosity_string()`.
     pub fn verbosity_string(&self) -> String {
         let mut ret: String = "".into();
-        if !self.module_levels.is_empty() {
-            ret = format!("-d {}", LogConfig::levels_to_string(&self.module_levels));
+        let (module_levels, target_levels) = self.threshold_strings();
+        if !module_levels.is_empty() {
+            ret = format!("-d {}", module_levels);
+        }
+        if !target_levels.is_empty() {
+            if !ret.is_empty() {
+                ret += " ";
+            }
+            ret = ret + &format!("-v {}", target_levels);
         }
-        if !self.target_levels.is_empty() {
+        if let Some(pattern) = &self.message_filter {
             if !ret.is_empty() {
-                ret = ret + " ";
+                ret += " ";
             }
-            ret = ret + &format!("-v {}", LogConfig::levels_to_string(&self.target_levels));
+            ret = ret + &format!("-g {}", pattern);
         }
         ret
     }
 }
 impl LogLineFilter for LogConfig {
     fn write(&self, now: &mut DeferredNow, record: &log::Record, log_line_writer: &dyn LogLineWriter) -> std::io::Result<()> {
-        let mut verbosity_level = Level::Info;
+        if self.redaction.is_empty() || !self.may_output(record) {
+            return self.filter_record(now, record, log_line_writer);
+        }
+        let message = self.redaction.message(record);
+        if message.is_none() && !self.redaction.masks_fields(record) {
+            return self.filter_record(now, record, log_line_writer);
+        }
+        let fields = MaskedFields { source: record.key_values(), redaction: &self.redaction };
+        match message {
+            Some(message) => self.filter_record(now, &record.to_builder().args(format_args!("{}", message)).key_values(&fields).build(), log_line_writer),
+            None => self.filter_record(now, &record.to_builder().key_values(&fields).build(), log_line_writer),
+        }
+    }
+}
+/// Whether the message of `record` contains `pattern`, see `LogConfig::with_message_filter()`.
+fn message_contains(record: &log::Record, pattern: &str) -> bool {
+    match record.args().as_str() {
+        Some(message) => message.contains(pattern),
+        None => record.args().to_string().contains(pattern),
+    }
+}
+
+impl LogConfig {
+    /// Threshold of `record` from the field rules or else the module and target rules.
+    fn threshold(&self, record: &log::Record) -> LevelFilter {
+        self.resolve_threshold(record, false)
+    }
+    /// Like `threshold()`, counts a hit of the deciding rule with `hit`.
+    fn resolve_threshold(&self, record: &log::Record, hit: bool) -> LevelFilter {
+        if let Some(rule) = self.field_levels.iter().find(|rule| rule.matches(record)) {
+            if hit {
+                rule.hits.fetch_add(1, Ordering::Relaxed);
+            }
+            return rule.level;
+        }
         let module = record.module_path().unwrap_or("");
-        let target = record.target();
-        let is_target_set = module != target;
-        //println!("level: {}, module: {}, target: {}, target_set: {}, message: '{}'", record.level(), module, target, is_target_set, record.args());
-        if is_target_set {
-            for (key, level) in &self.target_levels {
-                if let Some(_) = target.find(key) {
-                    //println!("target found: {} with level: {}", key, level);
-                    verbosity_level = *level;
-                    break;
+        self.threshold_cache.get_or_resolve_rule(module, record.target(), hit, || match self.static_rule(module, record.target()) {
+            (level, Some((_, hits))) => (level, Some(hits.clone())),
+            (level, None) => (level, Some(self.default_hits.clone())),
+        })
+    }
+    /// Whether `record` passes the thresholds and the message filter, the other filtering is
+    /// not applied. Used for the overlays of `ShvLogHandle::subscribe_with_verbosity()`.
+    pub(crate) fn passes_thresholds(&self, record: &log::Record) -> bool {
+        record.level() <= self.threshold(record)
+            && self.message_filter.as_deref().is_none_or(|pattern| message_contains(record, pattern))
+    }
+    /// Threshold of `record` raised by the active triggers.
+    fn raised_threshold(&self, record: &log::Record) -> LevelFilter {
+        self.raise_threshold(record, self.threshold(record))
+    }
+    fn raise_threshold(&self, record: &log::Record, threshold: LevelFilter) -> LevelFilter {
+        self.triggers.iter()
+            .filter_map(|trigger| trigger.raised_level(record))
+            .fold(threshold, |level, raised| level.max(raised.to_level_filter()))
+    }
+    /// Whether `record` may reach an output, the redaction is skipped for the others.
+    fn may_output(&self, record: &log::Record) -> bool {
+        record.target() == AUDIT_TARGET
+            || self.crash_dump.is_some()
+            || self.incident_dumps.is_some()
+            || test::capturing()
+            || record.level() <= subscribe::overlay_max_level()
+            || record.level() <= self.raised_threshold(record)
+    }
+    fn filter_record(&self, now: &mut DeferredNow, record: &log::Record, log_line_writer: &dyn LogLineWriter) -> std::io::Result<()> {
+        test::capture_record(now, record);
+        seen::note(record);
+        if let Some(summary) = self.drop_summary.and_then(drops::summary) {
+            log_line_writer.write(now, &log::Record::builder()
+                .args(format_args!("{}", summary))
+                .level(Level::Warn)
+                .target("shvlog")
+                .module_path_static(Some("shvlog::drops"))
+                .build())?;
+        }
+        for rule in &self.silence_rules {
+            rule.record_seen(record);
+        }
+        if record.target() == AUDIT_TARGET {
+            self.write_audit(now, record)?;
+            if let Some(buffer) = &self.memory_buffer {
+                buffer.push(LogRecord::from_record(now, record));
+            }
+            subscribe::notify(now, record);
+            stats::count(record);
+            return log_line_writer.write(now, record);
+        }
+        subscribe::notify_overlays(now, record);
+        if let Some(dump) = &self.crash_dump {
+            dump.keep(now, record);
+        }
+        if let Some(dumps) = &self.incident_dumps {
+            dumps.keep(now, record);
+        }
+        let verbosity_level = self.raise_threshold(record, self.resolve_threshold(record, true));
+        //println!("comparing to level: {}", verbosity_level);
+        let mut admitted = record.level() <= verbosity_level;
+        if let Some(monitor) = self.load_monitor.as_ref().filter(|_| admitted) {
+            admitted = !monitor.suppresses(record);
+            if !admitted {
+                drops::count(DropReason::Load, Some(record));
+            }
+        }
+        if let Some(pattern) = self.message_filter.as_deref().filter(|_| admitted) {
+            admitted = message_contains(record, pattern);
+        }
+        if let Some(sample) = self.samples.iter().find(|sample| sample.matches(record)).filter(|_| admitted) {
+            admitted = sample.admit(record);
+            if !admitted {
+                drops::count(DropReason::Sampling, Some(record));
+            }
+        }
+        if admitted {
+            admitted = self.dedup.admit(now, record, log_line_writer)?;
+        }
+        if admitted {
+            if let Some(throttle) = self.throttles.iter().find(|throttle| throttle.matches(record)) {
+                admitted = throttle.admit(now, record, log_line_writer)?;
+                if !admitted {
+                    drops::count(DropReason::Throttling, Some(record));
                 }
             }
-        } else {
-            for (key, level) in &self.module_levels  {
-                //println!("checking module '{}' contains: '{}'", module, key);
-                if let Some(_) = module.find(key) {
-                    //println!("module found: {} with level: {}", key, level);
-                    verbosity_level = *level;
-                    break;
+        }
+        if admitted {
+            if let Some(quota) = self.quotas.iter().find(|quota| quota.matches(record)) {
+                admitted = quota.admit(now, record, log_line_writer)?;
+                if !admitted {
+                    drops::count(DropReason::Quota, Some(record));
                 }
             }
         }
-        //println!("comparing to level: {}", verbosity_level);
-        if record.level() <= verbosity_level {
-            log_line_writer.write(now, record)?;
+        if admitted {
+            if let Some(burst) = self.bursts.iter().find(|burst| burst.matches(record)) {
+                admitted = burst.admit(now, record, log_line_writer)?;
+                if !admitted {
+                    drops::count(DropReason::Burst, Some(record));
+                }
+            }
+        }
+        if admitted {
+            if let Some(buffer) = &self.memory_buffer {
+                buffer.push(LogRecord::from_record(now, record));
+            }
+            subscribe::notify(now, record);
+            stats::count(record);
+            let result = log_line_writer.write(now, record);
+            #[cfg(all(unix, feature = "systemd"))]
+            if let Some(notifier) = &self.systemd_notifier {
+                notifier.report_write_result(&result);
+                if record.level() == Level::Error {
+                    notifier.report_error(record);
+                }
+            }
+            result?;
+        }
+        for rule in &self.escalations {
+            rule.check(now, record, log_line_writer)?;
+        }
+        for trigger in &self.triggers {
+            trigger.check(now, record, log_line_writer)?;
+        }
+        if let Some(dumps) = &self.incident_dumps {
+            dumps.check(now, record, log_line_writer)?;
         }
         Ok(())
     }
 }
 
-fn log_format(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record) -> Result<(), std::io::Error> {
-    // let sec = (now.now().unix_timestamp_nanos() / 1000_000_000) as i64;
-    // let nano = (now.now().unix_timestamp_nanos() % 1000_000_000) as u32;
-    // let ndt = NaiveDateTime::from_timestamp(sec, nano);
-    // let dt = chrono::Local.from_utc_datetime(&ndt);
-    let args = match record.level() {
-        Level::Error => Color::Red.paint(format!("|E|{}", record.args())),
-        Level::Warn => Color::Purple.paint(format!("|W|{}", record.args())),
-        Level::Info => Color::Cyan.paint(format!("|I|{}", record.args())),
-        Level::Debug => Color::Yellow.paint(format!("|D|{}", record.args())),
-        Level::Trace => Color::White.dimmed().paint(format!("|T|{}", record.args())),
+fn format_record(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record) -> Result<(), std::io::Error> {
+    write_record(w, now, record, true)
+}
+
+fn format_file_record(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record) -> Result<(), std::io::Error> {
+    write_record(w, now, record, false)
+}
+
+fn write_record(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record, stderr: bool) -> Result<(), std::io::Error> {
+    let options = FORMAT_OPTIONS.read().unwrap();
+    let (format, output) = if stderr {
+        (options.format, TextOutput { colored: options.colored, timestamp: true, max_len: options.max_line_len, module: options.module_display, line: options.show_line, file: options.show_file, multiline: options.multiline })
+    } else {
+        (options.file.format.unwrap_or(options.format), TextOutput { colored: false, timestamp: true, max_len: options.file.max_line_len, module: options.file.module_display, line: options.file.show_line, file: options.file.show_file, multiline: options.file.multiline })
+    };
+    format_as(w, now, record, &options, format, output)
+}
+
+/// Formats a record for a remote collector or sink like for stderr, but without colors and
+/// in `format` if set instead of the configured one.
+fn format_plain_record(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record, format: Option<LogFormat>) -> Result<(), std::io::Error> {
+    let options = FORMAT_OPTIONS.read().unwrap();
+    let output = TextOutput { colored: false, timestamp: true, max_len: options.max_line_len, module: options.module_display, line: options.show_line, file: options.show_file, multiline: options.multiline };
+    format_as(w, now, record, &options, format.unwrap_or(options.format), output)
+}
+
+fn format_as(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record, options: &FormatOptions, format: LogFormat, output: TextOutput) -> Result<(), std::io::Error> {
+    match format {
+        LogFormat::Text => multiline_format(w, now, record, options, output, log_format)?,
+        LogFormat::Json => return json::json_format(w, now, record, options, output.max_len),
+        LogFormat::Ecs => return json::ecs_format(w, now, record, options, output.max_len),
+        LogFormat::Systemd => multiline_format(w, now, record, options, output, systemd_format)?,
+    }
+    match backtrace(record, options) {
+        Some(backtrace) => write!(w, "\n{}", backtrace.trim_end()),
+        None => Ok(()),
+    }
+}
+
+type LineFormat = fn(&mut dyn std::io::Write, &mut DeferredNow, &Record, &FormatOptions, TextOutput) -> Result<(), std::io::Error>;
+
+/// Stands for the message when rendering the rest of a multi-line record.
+const MESSAGE_MARKER: &str = "\u{1}";
+
+/// Writes a record with a multi-line message as selected by `LogConfig::multiline()`.
+fn multiline_format(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record, options: &FormatOptions, output: TextOutput, format: LineFormat) -> Result<(), std::io::Error> {
+    if output.multiline == Multiline::AsIs {
+        return format(w, now, record, options, output);
+    }
+    let message = truncate(record.args().to_string(), output.max_len);
+    if !message.contains('\n') {
+        return format(w, now, record, options, output);
+    }
+    let mut rendered = Vec::new();
+    format(&mut rendered, now, &Record::builder()
+        .args(format_args!("{}", MESSAGE_MARKER))
+        .level(record.level())
+        .target(record.target())
+        .module_path(record.module_path())
+        .file(record.file())
+        .line(record.line())
+        .key_values(record.key_values())
+        .build(), options, TextOutput { max_len: None, ..output })?;
+    let rendered = String::from_utf8_lossy(&rendered);
+    // a template without the message
+    let Some((head, tail)) = rendered.split_once(MESSAGE_MARKER) else {
+        return format(w, now, record, options, output);
     };
-    let target = if record.module_path().unwrap_or("") == record.target() { "".to_string() } else { format!("({})", record.target()) };
-    write!(
-        w,
-        "{}{}{}{}",
-        //dt.format("%Y-%m-%dT%H:%M:%S.%3f%z"),
-        Color::Green.paint(format!("{}", now.now().format("%Y-%m-%dT%H:%M:%S%.3f"))),
-        Color::Yellow.paint(format!("[{}:{}]", record.module_path().unwrap_or("<unnamed>"), record.line().unwrap_or(0))),
-        Color::White.bold().paint(target),
-        args,
-    )
-}
-
-pub fn init(config: LogConfig) -> Result<LoggerHandle, FlexiLoggerError> {
-    let handle = Logger::try_with_str("debug")?
-        .filter(Box::new(config))
-        .format(log_format)
-        .set_palette("b1;3;2;4;6".into())
-        .start()?;
+    let mut lines = message.split('\n');
+    write!(w, "{}{}{}", head, lines.next().unwrap_or_default(), tail)?;
+    let indent = " ".repeat(visible_width(head.rsplit('\n').next().unwrap_or(head)));
+    let style = if output.colored { options.palette.level_style(record.level()) } else { Style::new() };
+    for line in lines {
+        match output.multiline {
+            Multiline::Prefix => write!(w, "\n{}{}{}", head, line, tail)?,
+            _ => write!(w, "\n{}{}", indent, style.paint(line))?,
+        }
+    }
+    Ok(())
+}
+
+/// Number of characters of `text` without the ANSI escape sequences.
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut escape = false;
+    for c in text.chars() {
+        if escape {
+            escape = !c.is_ascii_alphabetic();
+        } else if c == '\u{1b}' {
+            escape = true;
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+fn systemd_format(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record, options: &FormatOptions, output: TextOutput) -> Result<(), std::io::Error> {
+    write!(w, "<{}>", ShvSeverity::of(record.level()).syslog_severity())?;
+    log_format(w, now, record, options, TextOutput { colored: false, timestamp: false, ..output })
+}
+
+/// Style of the module field, derived from the module name with `LogConfig::module_colors()`.
+fn module_style(module: &str, options: &FormatOptions) -> Style {
+    // 256-color palette entries readable on both dark and light backgrounds
+    const PALETTE: [u8; 12] = [32, 37, 35, 71, 107, 136, 166, 172, 133, 169, 167, 98];
+    if !options.module_colors {
+        return options.palette.module_style();
+    }
+    let hash = module.bytes().fold(0x811c_9dc5_u32, |hash, byte| (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193));
+    Color::Fixed(PALETTE[hash as usize % PALETTE.len()]).normal()
+}
+
+/// Writes the `[module file:line]` field of the default format as selected by
+/// `ModuleDisplay`, `show_file()` and `show_line()`.
+fn write_module(w: &mut dyn std::io::Write, record: &Record, options: &FormatOptions, output: TextOutput) -> std::io::Result<()> {
+    let module = output.module.text(record.module_path().unwrap_or("<unnamed>")).filter(|module| !module.is_empty());
+    if module.is_none() && !output.file && !output.line {
+        return Ok(());
+    }
+    w.write_all(b"[")?;
+    if let Some(module) = module {
+        w.write_all(module.as_bytes())?;
+    }
+    if output.file {
+        if module.is_some() {
+            w.write_all(b" ")?;
+        }
+        w.write_all(source_file(record, options).as_bytes())?;
+    }
+    if output.line {
+        write!(w, ":{}", record.line().unwrap_or(0))?;
+    }
+    w.write_all(b"]")
+}
+
+/// Source file of the record without `LogConfig::file_path_prefix()`.
+fn source_file<'a>(record: &Record<'a>, options: &FormatOptions) -> &'a str {
+    let file = record.file().unwrap_or("<unnamed>");
+    options.file_path_prefix.as_deref().and_then(|prefix| file.strip_prefix(prefix)).unwrap_or(file)
+}
+
+/// Output of a writing helper as text, for the fields of a template.
+fn rendered(write: impl FnOnce(&mut dyn std::io::Write) -> std::io::Result<()>) -> String {
+    let mut text = Vec::new();
+    let _ = write(&mut text);
+    String::from_utf8(text).unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
+}
+
+/// Timestamp followed by the delta to the previous record if enabled.
+fn timestamp_text(now: &mut DeferredNow, options: &FormatOptions) -> String {
+    rendered(|w| write_timestamp_text(w, now, options))
+}
+
+fn write_timestamp_text(w: &mut dyn std::io::Write, now: &mut DeferredNow, options: &FormatOptions) -> std::io::Result<()> {
+    let timestamp = options.write_timestamp(w, now)?;
+    match options.delta(now) {
+        Some(delta) if timestamp => write!(w, " +{}s", options.seconds(delta)),
+        Some(delta) => write!(w, "+{}s", options.seconds(delta)),
+        None => Ok(()),
+    }
+}
+
+/// Message with the group indentation, error code and scope prefix, truncated to `max_len` bytes.
+fn message_text(record: &Record, max_len: Option<usize>) -> String {
+    truncate(rendered(|w| write_message(w, record, None)), max_len)
+}
+
+fn write_message(w: &mut dyn std::io::Write, record: &Record, max_len: Option<usize>) -> std::io::Result<()> {
+    // the truncation needs the whole text
+    if max_len.is_some() {
+        return w.write_all(message_text(record, max_len).as_bytes());
+    }
+    for _ in 0..group::depth() {
+        w.write_all(b"  ")?;
+    }
+    if let Some(code) = record.key_values().get(log::kv::Key::from_str(ERROR_CODE_KEY)) {
+        write!(w, "[{}] ", code)?;
+    }
+    scope::write_prefix(w)?;
+    write!(w, "{}", record.args())
+}
+
+/// Cuts `text` to at most `max_len` bytes at a character boundary, appending the number
+/// of cut bytes, e.g. `… (+1200 bytes)`.
+fn truncate(mut text: String, max_len: Option<usize>) -> String {
+    let Some(max_len) = max_len.filter(|max_len| text.len() > *max_len) else { return text };
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let cut = text.len() - end;
+    text.truncate(end);
+    text.push_str(&format!("\u{2026} (+{} bytes)", cut));
+    text
+}
+
+/// Key-value, context and enrichment fields as ` key=value` list.
+fn fields_text(record: &Record) -> String {
+    rendered(|w| write_fields(w, record))
+}
+
+fn write_fields(w: &mut dyn std::io::Write, record: &Record) -> std::io::Result<()> {
+    for (key, value) in fields::record_fields(record) {
+        write!(w, " {}={}", key, value)?;
+    }
+    for (key, value) in context::current().iter().chain(&fields::enrichment()) {
+        write!(w, " {}={}", key, value)?;
+    }
+    Ok(())
+}
+
+/// Name of the current thread, its numeric id if unnamed.
+fn thread_name() -> String {
+    let thread = std::thread::current();
+    match thread.name() {
+        Some(name) => name.into(),
+        None => {
+            let id = format!("{:?}", thread.id());
+            id.trim_start_matches("ThreadId(").trim_end_matches(')').into()
+        }
+    }
+}
+
+/// Properties of the text output a record is formatted for.
+#[derive(Clone, Copy)]
+struct TextOutput {
+    colored: bool,
+    timestamp: bool,
+    max_len: Option<usize>,
+    module: ModuleDisplay,
+    line: bool,
+    file: bool,
+    multiline: Multiline,
+}
+
+fn log_format(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record, options: &FormatOptions, output: TextOutput) -> Result<(), std::io::Error> {
+    if let Some(template) = &options.template {
+        template.write(w, now, record, options, output)?;
+    } else {
+        default_format(w, now, record, options, output)?;
+    }
+    Ok(())
+}
+
+/// Backtrace of the logging thread for records at or above `LogConfig::capture_backtrace()`.
+fn backtrace(record: &Record, options: &FormatOptions) -> Option<String> {
+    // the panic hook already includes the backtrace of the panic
+    if panic::in_hook() || options.backtrace_level.is_none_or(|level| record.level() > level) {
+        return None;
+    }
+    let backtrace = std::backtrace::Backtrace::force_capture();
+    if backtrace.status() != std::backtrace::BacktraceStatus::Captured {
+        return None;
+    }
+    // skips the frames of the logger, up to the `log` macro call
+    let backtrace = backtrace.to_string();
+    let is_frame = |line: &str| line.trim_start().split_once(": ").is_some_and(|(index, _)| index.parse::<usize>().is_ok());
+    let lines: Vec<&str> = backtrace.lines().collect();
+    let skip = lines.iter().rposition(|line| is_frame(line) && line.contains("log::__private_api"))
+        .and_then(|macro_frame| lines[macro_frame + 1..].iter().position(|line| is_frame(line)).map(|next| macro_frame + 1 + next))
+        .unwrap_or(0);
+    Some(lines[skip..].join("\n"))
+}
+
+/// Writes the output of `content` in `style` when colored.
+fn paint(w: &mut dyn std::io::Write, colored: bool, style: Style, content: impl FnOnce(&mut dyn std::io::Write) -> std::io::Result<()>) -> std::io::Result<()> {
+    if !colored {
+        return content(w);
+    }
+    write!(w, "{}", style.prefix())?;
+    content(w)?;
+    write!(w, "{}", style.suffix())
+}
+
+/// Writes the parts straight into `w`, allocating only for truncated messages, fields
+/// and unnamed threads.
+fn default_format(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record, options: &FormatOptions, output: TextOutput) -> Result<(), std::io::Error> {
+    let colored = output.colored;
+    paint(w, colored, Color::Blue.normal(), |w| w.write_all(options.prefix.as_bytes()))?;
+    paint(w, colored, options.palette.timestamp_style(), |w| if output.timestamp { write_timestamp_text(w, now, options) } else { Ok(()) })?;
+    paint(w, colored, Color::Blue.normal(), |w| match (options.show_hostname, options.show_pid) {
+        (true, true) => write!(w, "<{}:{}>", options.hostname, options.pid),
+        (true, false) => write!(w, "<{}>", options.hostname),
+        (false, true) => write!(w, "<{}>", options.pid),
+        (false, false) => Ok(()),
+    })?;
+    paint(w, colored, Color::Purple.normal(), |w| match std::thread::current().name().filter(|_| options.show_thread) {
+        Some(name) => write!(w, "{{{}}}", name),
+        None if options.show_thread => write!(w, "{{{}}}", thread_name()),
+        None => Ok(()),
+    })?;
+    paint(w, colored, module_style(record.module_path().unwrap_or(""), options), |w| write_module(w, record, options, output))?;
+    paint(w, colored, options.palette.target_style(), |w| {
+        if record.module_path().unwrap_or("") == record.target() { Ok(()) } else { write!(w, "({})", record.target()) }
+    })?;
+    paint(w, colored, options.palette.level_style(record.level()), |w| {
+        write!(w, "|{}|", &record.level().as_str()[..1])?;
+        write_message(w, record, output.max_len)
+    })?;
+    paint(w, colored, Color::White.dimmed(), |w| write_fields(w, record))
+}
+
+/// Initializes the logger, fails when it is already initialized, see `try_init()`.
+pub fn init(config: LogConfig) -> Result<ShvLogHandle, FlexiLoggerError> {
+    start(config, format_record, format_file_record).map_err(InitError::into_flexi_logger_error)
+}
+
+/// Initializes the logger like `init()`, telling apart a logger that is already initialized.
+pub fn try_init(config: LogConfig) -> Result<ShvLogHandle, InitError> {
+    start(config, format_record, format_file_record)
+}
+
+/// Initializes the logger like `init()`, or returns the handle of the logger initialized
+/// before and ignores `config`, e.g. in tests sharing a process.
+pub fn init_once(config: LogConfig) -> Result<ShvLogHandle, FlexiLoggerError> {
+    match try_init(config) {
+        Err(InitError::AlreadyInitialized(_)) => Ok(handle().expect("initialized logger has a handle")),
+        result => result.map_err(InitError::into_flexi_logger_error),
+    }
+}
+
+/// How `init_or_attach()` got the records of the caller logged.
+#[derive(Clone)]
+pub enum Attachment {
+    /// The logger was started with the given config.
+    Started(ShvLogHandle),
+    /// The logger was started before by code linking the same shvlog, e.g. the application
+    /// loading the plugin, the config is ignored and the filter and sinks are shared.
+    Attached(ShvLogHandle),
+    /// Another logger receives the records through the shared `log` crate, e.g. of a host with
+    /// its own shvlog copy, the config is ignored. A plugin with its own copy of the `log` crate
+    /// has its own logger and cannot attach, it should be built as a Rust dylib sharing it.
+    Host,
+}
+impl Attachment {
+    /// Handle of the logger unless the records go to the host logger.
+    pub fn handle(&self) -> Option<&ShvLogHandle> {
+        match self {
+            Attachment::Started(handle) | Attachment::Attached(handle) => Some(handle),
+            Attachment::Host => None,
+        }
+    }
+}
+
+/// Initializes the logger like `init()`, or attaches to the logger installed before instead
+/// of logging twice, e.g. in a dynamically loaded plugin.
+pub fn init_or_attach(config: LogConfig) -> Result<Attachment, FlexiLoggerError> {
+    match try_init(config) {
+        Ok(handle) => Ok(Attachment::Started(handle)),
+        Err(InitError::AlreadyInitialized(_)) => Ok(Attachment::Attached(handle().expect("initialized logger has a handle"))),
+        Err(InitError::OtherLogger(_)) => Ok(Attachment::Host),
+        Err(err) => Err(err.into_flexi_logger_error()),
+    }
+}
+
+/// Handle of the logger initialized by `init()` or its variants, e.g. for a library to adjust
+/// the verbosity without owning the initialization.
+pub fn handle() -> Option<ShvLogHandle> {
+    HANDLE.read().unwrap().clone()
+}
+
+/// Error of `try_init()`.
+#[derive(Debug)]
+pub enum InitError {
+    /// The logger was initialized before, see `handle()`.
+    AlreadyInitialized(log::SetLoggerError),
+    /// Another `log` logger is installed, e.g. by a dependency.
+    OtherLogger(log::SetLoggerError),
+    /// The logger cannot be started.
+    Logger(FlexiLoggerError),
+}
+impl InitError {
+    fn into_flexi_logger_error(self) -> FlexiLoggerError {
+        match self {
+            InitError::AlreadyInitialized(err) | InitError::OtherLogger(err) => FlexiLoggerError::Log(err),
+            InitError::Logger(err) => err,
+        }
+    }
+}
+impl std::fmt::Display for InitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InitError::AlreadyInitialized(_) => write!(f, "Logger is already initialized"),
+            InitError::OtherLogger(_) => write!(f, "Another logger is installed"),
+            InitError::Logger(err) => write!(f, "Cannot start logger: {}", err),
+        }
+    }
+}
+impl std::error::Error for InitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InitError::AlreadyInitialized(err) | InitError::OtherLogger(err) => Some(err),
+            InitError::Logger(err) => Some(err),
+        }
+    }
+}
+
+/// Initializes the logger like `init()`, but renders records on stderr and in the log file
+/// with `format` instead of the configured `LogFormat` and template, keeping the filtering
+/// of `config`. Journald and syslog output keep their own formats.
+pub fn init_with_format(config: LogConfig, format: FormatFunction) -> Result<ShvLogHandle, FlexiLoggerError> {
+    start(config, format, format).map_err(InitError::into_flexi_logger_error)
+}
+
+fn start(config: LogConfig, format: FormatFunction, file_format: FormatFunction) -> Result<ShvLogHandle, InitError> {
+    // held until started, a concurrent init fails without touching the running logger
+    let mut handle = HANDLE.write().unwrap();
+    // before the config is activated, so that a failed init starts no threads or hooks
+    handle::reserve_logger().map_err(|err| if handle.is_some() { InitError::AlreadyInitialized(err) } else { InitError::OtherLogger(err) })?;
+    let started = start_logger(config, format, file_format).map_err(InitError::Logger)?;
+    *handle = Some(started.clone());
+    Ok(started)
+}
+
+fn start_logger(mut config: LogConfig, format: FormatFunction, file_format: FormatFunction) -> Result<ShvLogHandle, FlexiLoggerError> {
+    let identity = InstanceIdentity::detect(config.device_id);
+    config.activate(&identity)?;
+    let file_log = config.file_log.clone();
+    let mut stderr_level = config.stderr_level;
+    let async_output = config.async_output;
+    let output = config.output;
+    let always_flush = config.always_flush;
+    let (buffer_capacity, flush_interval) = (config.buffer_capacity, config.flush_interval);
+    let flush_level = if always_flush { LevelFilter::Off } else { config.flush_level };
+    let routes = Arc::new(std::mem::take(&mut config.routes));
+    let routed = |destination, writer| route::with_routes(&routes, destination, writer);
+    let mut writers: Vec<Box<dyn LogWriter>> = Vec::new();
+    if let Some(file_log) = &file_log {
+        FORMAT_OPTIONS.write().unwrap().file = FileFormatOptions::of(file_log);
+        // the flexi_logger flusher thread flushes the buffered file
+        let write_mode = if always_flush { WriteMode::Direct } else { WriteMode::BufferDontFlushWith(buffer_capacity) };
+        writers.push(routed(Destination::File, writers::with_level(Box::new(file_log.writer(file_format, write_mode)?), file_log.level)));
+    }
+    #[cfg(all(unix, feature = "journald"))]
+    if config.journald {
+        writers.push(routed(Destination::Journald, Box::new(journald::JournaldWriter::new(config.app_name.as_deref(), config.journald_message_ids.clone())?)));
+    }
+    if let Some(syslog) = &config.syslog {
+        writers.push(routed(Destination::Syslog, writers::with_level(Box::new(syslog.writer(config.app_name.as_deref(), &identity.hostname)?), syslog.level)));
+    }
+    #[cfg(feature = "gelf")]
+    if let Some(gelf) = &config.gelf {
+        writers.push(routed(Destination::Gelf, writers::with_level(Box::new(gelf.writer(config.app_name.as_deref(), &identity.hostname, identity.device_id.as_deref())?), gelf.level)));
+    }
+    #[cfg(feature = "otel")]
+    if let Some(otel) = &config.otel {
+        let instance_id = config.instance_id.as_deref().or(identity.device_id.as_deref());
+        writers.push(routed(Destination::Otel, writers::with_level(Box::new(otel.writer(config.app_name.as_deref(), instance_id, &identity.hostname)?), otel.level)));
+    }
+    if let Some(remote) = &config.remote {
+        writers.push(routed(Destination::Remote, writers::with_level(Box::new(remote.writer()?), remote.level)));
+    }
+    if let Some(journal) = &config.shvjournal {
+        writers.push(routed(Destination::ShvJournal, writers::with_level(Box::new(journal.writer()?), journal.level)));
+    }
+    for (ix, sink) in std::mem::take(&mut config.sinks).into_iter().enumerate() {
+        writers.push(routed(Destination::Sink(ix), Box::new(SinkWriter::new(sink)?)));
+    }
+    let mut dropped = None;
+    if !routes.is_empty() {
+        // the console becomes a routed writer in place of the flexi_logger console or duplicate
+        let console: Option<Box<dyn LogWriter>> = match (writers.is_empty(), async_output) {
+            (true, Some((capacity, policy))) => {
+                let writer = AsyncWriter::new(format, capacity, policy, output)?;
+                dropped = Some(writer.dropped());
+                Some(Box::new(writer))
+            }
+            (true, None) => Some(Box::new(ConsoleWriter::new(format, output))),
+            (false, _) => stderr_level.map(|level| writers::with_level(Box::new(ConsoleWriter::new(format, output)), Some(level))),
+        };
+        if let Some(console) = console {
+            writers.push(routed(Destination::Stderr, console));
+        }
+        stderr_level = None;
+    }
+    let writer = writers::combine(writers);
+    *INSTANCE_IDENTITY.write().unwrap() = Some(identity);
+    // stderr duplication only applies when records go somewhere else
+    let stderr_level = stderr_level.filter(|_| writer.is_some());
+    let config = Arc::new(RwLock::new(config));
+    // records are filtered by LogConfig, so flexi_logger must let everything through
+    let mut logger = Logger::try_with_str("trace")?
+        .filter(Box::new(SharedFilter(config.clone())))
+        .format(format);
+    if output == Stream::Stdout {
+        logger = logger.log_to_stdout();
+    }
+    if !always_flush {
+        logger = logger.write_mode(WriteMode::BufferAndFlushWith(buffer_capacity, flush_interval));
+    }
+    if let Some(writer) = writer {
+        logger = logger.log_to_writer(writer);
+    } else if let Some((capacity, policy)) = async_output {
+        let writer = AsyncWriter::new(format, capacity, policy, output)?;
+        dropped = Some(writer.dropped());
+        logger = logger.log_to_writer(Box::new(writer));
+    }
+    if let Some(level) = stderr_level {
+        let duplicate = match level {
+            Level::Error => Duplicate::Error,
+            Level::Warn => Duplicate::Warn,
+            Level::Info => Duplicate::Info,
+            Level::Debug => Duplicate::Debug,
+            Level::Trace => Duplicate::Trace,
+        };
+        logger = match output {
+            Stream::Stderr => logger.duplicate_to_stderr(duplicate),
+            Stream::Stdout => logger.duplicate_to_stdout(duplicate),
+        };
+    }
+    let (logger, logger_handle) = logger.build()?;
+    handle::start_logger(ShvLogger { inner: logger, config: config.clone(), flush_level });
+    // flexi_logger enables everything, the config knows better
+    config.read().unwrap().update_max_level();
+    #[cfg(feature = "tracing-bridge")]
+    tracing_layer::install_bridge();
+    Ok(ShvLogHandle::new(logger_handle, config, dropped))
+}
+
+/// Initializes the logger from a TOML config file, see `LogConfig::from_toml_file()`, and
+/// reloads the config whenever the file changes.
+///
+/// The reloaded config replaces the active one like `ShvLogHandle::reset()`, so the file and
+/// syslog output stay as set up initially. A config file that fails to load is reported
+/// and the active config is kept. Fails also when the watcher thread cannot be spawned, the
+/// logger is started then.
+#[cfg(feature = "toml")]
+pub fn init_with_reload(path: impl Into<PathBuf>) -> Result<ShvLogHandle, ConfigError> {
+    let path = path.into();
+    let config = LogConfig::from_toml_file(&path)?;
+    let handle = init(config).map_err(ConfigError::Init)?;
+    reload::spawn_watcher(path, Arc::downgrade(&handle.config), Duration::from_secs(1)).map_err(|err| ConfigError::Init(FlexiLoggerError::OutputIo(err)))?;
     Ok(handle)
 }
+
+/// Identity of the running process, available after `init()` has been called.
+pub fn instance_identity() -> Option<InstanceIdentity> {
+    INSTANCE_IDENTITY.read().unwrap().clone()
+}
@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Weak;
+use std::thread;
+use std::time::Duration;
+
+/// Suppresses Debug and Trace records while the system load is above a threshold.
+pub(crate) struct LoadMonitor {
+    threshold: f64,
+    interval: Duration,
+    overloaded: AtomicBool,
+}
+impl LoadMonitor {
+    pub(crate) fn new(threshold: f64, interval: Duration) -> LoadMonitor {
+        LoadMonitor { threshold, interval, overloaded: AtomicBool::new(false) }
+    }
+    pub(crate) fn suppresses(&self, record: &log::Record) -> bool {
+        record.level() >= log::Level::Debug && self.overloaded.load(Ordering::Relaxed)
+    }
+    fn update(&self, load: f64) {
+        let overloaded = self.overloaded.load(Ordering::Relaxed);
+        if !overloaded && load > self.threshold {
+            self.overloaded.store(true, Ordering::Relaxed);
+            log::warn!("System overloaded (load {:.2} per CPU), suppressing Debug and Trace records", load);
+        } else if overloaded && load < self.threshold * 0.8 {
+            self.overloaded.store(false, Ordering::Relaxed);
+            log::info!("System load back to {:.2} per CPU, Debug and Trace records restored", load);
+        }
+    }
+}
+
+/// One minute load average divided by the number of CPUs.
+fn load_per_cpu() -> Option<f64> {
+    let loadavg = std::fs::read_to_string("/proc/loadavg").ok()?;
+    let load: f64 = loadavg.split_whitespace().next()?.parse().ok()?;
+    let cpus = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    Some(load / cpus as f64)
+}
+
+/// Spawns the monitor thread, it ends once the monitor is dropped with its config.
+pub(crate) fn spawn_monitor(monitor: Weak<LoadMonitor>) -> std::io::Result<()> {
+    thread::Builder::new()
+        .name("shvlog-load".into())
+        .spawn(move || {
+            // without /proc/loadavg there is nothing to monitor
+            while let (Some(monitor), Some(load)) = (monitor.upgrade(), load_per_cpu()) {
+                monitor.update(load);
+                let interval = monitor.interval;
+                drop(monitor);
+                thread::sleep(interval);
+            }
+        })?;
+    Ok(())
+}
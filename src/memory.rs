@@ -0,0 +1,35 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::{GetLogParams, LogRecord};
+
+/// Bounded buffer keeping the most recent emitted records.
+pub(crate) struct MemoryBuffer {
+    capacity: usize,
+    records: Mutex<VecDeque<LogRecord>>,
+}
+impl MemoryBuffer {
+    pub(crate) fn new(capacity: usize) -> MemoryBuffer {
+        MemoryBuffer { capacity, records: Mutex::new(VecDeque::with_capacity(capacity)) }
+    }
+    pub(crate) fn push(&self, record: LogRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = self.records.lock().unwrap();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+    pub(crate) fn snapshot(&self) -> Vec<LogRecord> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+    /// Like `snapshot()`, None while the buffer is locked.
+    pub(crate) fn try_snapshot(&self) -> Option<Vec<LogRecord>> {
+        self.records.try_lock().ok().map(|records| records.iter().cloned().collect())
+    }
+    pub(crate) fn query(&self, params: &GetLogParams) -> Vec<LogRecord> {
+        params.query(self.records.lock().unwrap().iter())
+    }
+}
@@ -0,0 +1,62 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Suffix of a record logged by `log_first_n!`, the last one allowed notes the suppression.
+#[doc(hidden)]
+pub struct Occurrence {
+    limit: usize,
+    last: bool,
+}
+impl fmt::Display for Occurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.last, self.limit) {
+            (false, _) => Ok(()),
+            (true, 1) => f.write_str(" (further occurrences are suppressed)"),
+            (true, limit) => write!(f, " (logged {} times, further occurrences are suppressed)", limit),
+        }
+    }
+}
+
+/// Counts an occurrence of a `log_first_n!` callsite, None once `limit` was reached.
+#[doc(hidden)]
+pub fn occurrence(count: &AtomicUsize, limit: usize) -> Option<Occurrence> {
+    // the count stays at the limit, the suppressed occurrences only load it
+    if count.load(Ordering::Relaxed) >= limit {
+        return None;
+    }
+    let previous = count.fetch_add(1, Ordering::Relaxed);
+    (previous < limit).then_some(Occurrence { limit, last: previous + 1 == limit })
+}
+
+/// Logs only the first `n` occurrences of the callsite, e.g.
+/// `log_first_n!(Level::Warn, 3, "frame from {} dropped", peer)`.
+///
+/// Occurrences count only while the level is enabled. The last logged record
+/// notes that further ones are suppressed.
+#[macro_export]
+macro_rules! log_first_n {
+    (target: $target:expr, $level:expr, $n:expr, $($arg:tt)+) => {{
+        static COUNT: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+        let (target, level) = ($target, $level);
+        if $crate::log::log_enabled!(target: target, level) {
+            if let Some(occurrence) = $crate::occurrence(&COUNT, $n) {
+                $crate::log::log!(target: target, level, "{}{}", format_args!($($arg)+), occurrence);
+            }
+        }
+    }};
+    ($level:expr, $n:expr, $($arg:tt)+) => ($crate::log_first_n!(target: module_path!(), $level, $n, $($arg)+));
+}
+
+/// Logs only the first occurrence of the callsite, see `log_first_n!`.
+#[macro_export]
+macro_rules! log_once {
+    (target: $target:expr, $level:expr, $($arg:tt)+) => ($crate::log_first_n!(target: $target, $level, 1, $($arg)+));
+    ($level:expr, $($arg:tt)+) => ($crate::log_first_n!($level, 1, $($arg)+));
+}
+
+/// Logs the first occurrence of the callsite with level Warn, e.g. `warn_once!("clock not synchronized")`.
+#[macro_export]
+macro_rules! warn_once {
+    (target: $target:expr, $($arg:tt)+) => ($crate::log_once!(target: $target, $crate::log::Level::Warn, $($arg)+));
+    ($($arg:tt)+) => ($crate::log_once!($crate::log::Level::Warn, $($arg)+));
+}
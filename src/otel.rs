@@ -0,0 +1,320 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use flexi_logger::writers::LogWriter;
+use flexi_logger::DeferredNow;
+
+use crate::json::JsonStr;
+use crate::{context, fields, scope, ERROR_CODE_KEY};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// OpenTelemetry log export over OTLP/HTTP with JSON encoding, see `LogConfig::with_otel()`.
+///
+/// Records are batched by the "shvlog-otel" thread and posted to the collector, failed
+/// batches are retried with backoff. The target, module, file, line and key-value fields
+/// become log record attributes, the application name, instance ID, hostname and process
+/// ID resource attributes.
+#[derive(Clone, Debug)]
+pub struct Otel {
+    endpoint: String,
+    headers: Vec<(String, String)>,
+    batch_size: usize,
+    batch_delay: Duration,
+    capacity: usize,
+    pub(crate) level: Option<log::Level>,
+}
+impl Otel {
+    /// Exports to a collector at `endpoint`, e.g. `"http://collector:4318"`. The `/v1/logs`
+    /// path is appended unless the endpoint has a path, HTTPS is not supported.
+    pub fn http(endpoint: &str) -> Otel {
+        Otel {
+            endpoint: endpoint.into(),
+            headers: Vec::new(),
+            batch_size: 512,
+            batch_delay: Duration::from_secs(1),
+            capacity: 10_000,
+            level: None,
+        }
+    }
+    /// Skips records less severe than `level`, see `Syslog::level()`.
+    pub fn level(mut self, level: log::Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+    /// Additional HTTP header of the export requests, e.g. an authorization token.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+    /// Records exported in one request at most, 512 by default.
+    pub fn batch_size(mut self, records: usize) -> Self {
+        self.batch_size = records.max(1);
+        self
+    }
+    /// Longest time a record waits for its batch, 1 s by default.
+    pub fn batch_delay(mut self, delay: Duration) -> Self {
+        self.batch_delay = delay;
+        self
+    }
+    /// Number of records kept while the collector is unreachable, 10000 by default.
+    pub fn buffer(mut self, records: usize) -> Self {
+        self.capacity = records.max(1);
+        self
+    }
+    pub(crate) fn writer(&self, service_name: Option<&str>, instance_id: Option<&str>, hostname: &str) -> std::io::Result<OtelWriter> {
+        let (host, path) = parse_endpoint(&self.endpoint)?;
+        let service_name = service_name.map(|name| name.to_string()).unwrap_or_else(|| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.file_name().map(|name| name.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| "unknown_service".into())
+        });
+        let mut resource = vec![
+            string_attribute("service.name", &service_name),
+            string_attribute("host.name", hostname),
+            format!("{{\"key\":\"process.pid\",\"value\":{{\"intValue\":\"{}\"}}}}", std::process::id()),
+        ];
+        if let Some(instance_id) = instance_id {
+            resource.push(string_attribute("service.instance.id", instance_id));
+        }
+        let exporter = Exporter {
+            host,
+            path,
+            headers: self.headers.clone(),
+            prefix: format!(
+                "{{\"resourceLogs\":[{{\"resource\":{{\"attributes\":[{}]}},\"scopeLogs\":[{{\"scope\":{{\"name\":\"shvlog\"}},\"logRecords\":[",
+                resource.join(","),
+            ),
+        };
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            changed: Condvar::new(),
+            capacity: self.capacity,
+        });
+        let thread_shared = shared.clone();
+        let (batch_size, batch_delay) = (self.batch_size, self.batch_delay);
+        let thread = thread::Builder::new()
+            .name("shvlog-otel".into())
+            .spawn(move || export_queued(&thread_shared, &exporter, batch_size, batch_delay))?;
+        Ok(OtelWriter { shared, thread: Mutex::new(Some(thread)) })
+    }
+}
+
+/// Splits `http://host:port/path` to the address and the request path.
+fn parse_endpoint(endpoint: &str) -> std::io::Result<(String, String)> {
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("invalid OTLP endpoint '{}', expected http://host:port", endpoint));
+    let rest = endpoint.strip_prefix("http://").ok_or_else(invalid)?;
+    let (host, path) = match rest.find('/') {
+        Some(ix) if ix + 1 < rest.len() => (&rest[..ix], rest[ix..].to_string()),
+        Some(ix) => (&rest[..ix], "/v1/logs".to_string()),
+        None => (rest, "/v1/logs".to_string()),
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let host = if host.contains(':') && !host.ends_with(']') { host.to_string() } else { format!("{}:4318", host) };
+    Ok((host, path))
+}
+
+fn string_attribute(key: &str, value: &str) -> String {
+    format!("{{\"key\":{},\"value\":{{\"stringValue\":{}}}}}", JsonStr(key), JsonStr(value))
+}
+
+fn value_attribute(key: &str, value: &log::kv::Value) -> String {
+    let value = if let Some(value) = value.to_bool() {
+        format!("{{\"boolValue\":{}}}", value)
+    } else if let Some(value) = value.to_i64() {
+        format!("{{\"intValue\":\"{}\"}}", value)
+    } else if let Some(value) = value.to_f64().filter(|value| value.is_finite()) {
+        format!("{{\"doubleValue\":{}}}", value)
+    } else {
+        format!("{{\"stringValue\":{}}}", JsonStr(&value.to_string()))
+    };
+    format!("{{\"key\":{},\"value\":{}}}", JsonStr(key), value)
+}
+
+/// Severity number and text of the OpenTelemetry log data model.
+fn severity(level: log::Level) -> (u8, &'static str) {
+    match level {
+        log::Level::Error => (17, "ERROR"),
+        log::Level::Warn => (13, "WARN"),
+        log::Level::Info => (9, "INFO"),
+        log::Level::Debug => (5, "DEBUG"),
+        log::Level::Trace => (1, "TRACE"),
+    }
+}
+
+/// The record as OTLP JSON `LogRecord`.
+fn log_record(now: &mut DeferredNow, record: &log::Record) -> String {
+    let (severity_number, severity_text) = severity(record.level());
+    let mut attributes = vec![string_attribute("target", record.target())];
+    if let Some(module) = record.module_path() {
+        attributes.push(string_attribute("code.namespace", module));
+    }
+    if let Some(file) = record.file() {
+        attributes.push(string_attribute("code.filepath", file));
+    }
+    if let Some(line) = record.line() {
+        attributes.push(format!("{{\"key\":\"code.lineno\",\"value\":{{\"intValue\":\"{}\"}}}}", line));
+    }
+    if let Some(code) = record.key_values().get(log::kv::Key::from_str(ERROR_CODE_KEY)) {
+        attributes.push(string_attribute(ERROR_CODE_KEY, &code.to_string()));
+    }
+    for (key, value) in fields::record_fields(record) {
+        attributes.push(value_attribute(key.as_str(), &value));
+    }
+    for (key, value) in context::current().into_iter().chain(fields::enrichment()) {
+        attributes.push(string_attribute(&key, &value));
+    }
+    let time = now.now().timestamp_nanos_opt().unwrap_or(0);
+    format!(
+        "{{\"timeUnixNano\":\"{}\",\"observedTimeUnixNano\":\"{}\",\"severityNumber\":{},\"severityText\":\"{}\",\"body\":{{\"stringValue\":{}}},\"attributes\":[{}]}}",
+        time,
+        time,
+        severity_number,
+        severity_text,
+        JsonStr(&format!("{}{}", scope::prefix(), record.args())),
+        attributes.join(","),
+    )
+}
+
+struct Exporter {
+    host: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    /// Request body up to the log records.
+    prefix: String,
+}
+impl Exporter {
+    fn export(&self, records: &[String]) -> std::io::Result<()> {
+        let body = format!("{}{}]}}]}}]}}", self.prefix, records.join(","));
+        let addr = self.host.to_socket_addrs()?.next()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("cannot resolve {}", self.host)))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.path,
+            self.host,
+            body.len(),
+        );
+        for (name, value) in &self.headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+        stream.write_all(body.as_bytes())?;
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status)?;
+        // "HTTP/1.1 200 OK"
+        match status.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(std::io::Error::other(format!("OTLP export failed: {}", status.trim()))),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    records: VecDeque<String>,
+    exporting: bool,
+    /// The last export failed, flush does not wait for an unreachable collector.
+    failing: bool,
+    closed: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    changed: Condvar,
+    capacity: usize,
+}
+
+pub(crate) struct OtelWriter {
+    shared: Arc<Shared>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+impl LogWriter for OtelWriter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        let record = log_record(now, record);
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.closed {
+            return Ok(());
+        }
+        if queue.records.len() >= self.shared.capacity {
+            queue.records.pop_front();
+        }
+        queue.records.push_back(record);
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+    /// Exports the queued records, unless the collector is unreachable.
+    fn flush(&self) -> std::io::Result<()> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        while (!queue.records.is_empty() || queue.exporting) && !queue.failing && !queue.closed {
+            queue = self.shared.changed.wait(queue).unwrap();
+        }
+        Ok(())
+    }
+    /// Exports the queued records once more and stops the exporting thread.
+    fn shutdown(&self) {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn export_queued(shared: &Shared, exporter: &Exporter, batch_size: usize, batch_delay: Duration) {
+    let mut backoff = batch_delay.max(Duration::from_millis(100));
+    loop {
+        let mut queue = shared.queue.lock().unwrap();
+        while queue.records.is_empty() && !queue.closed {
+            queue = shared.changed.wait(queue).unwrap();
+        }
+        // collects a batch, shutdown exports right away
+        let deadline = Instant::now() + batch_delay;
+        while queue.records.len() < batch_size && !queue.closed {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                break;
+            }
+            queue = shared.changed.wait_timeout(queue, timeout).unwrap().0;
+        }
+        if queue.records.is_empty() {
+            return;
+        }
+        let count = queue.records.len().min(batch_size);
+        let batch: Vec<_> = queue.records.drain(..count).collect();
+        let closed = queue.closed;
+        queue.exporting = true;
+        drop(queue);
+        let result = exporter.export(&batch);
+        let mut queue = shared.queue.lock().unwrap();
+        queue.exporting = false;
+        queue.failing = result.is_err();
+        shared.changed.notify_all();
+        if result.is_err() {
+            if closed {
+                return;
+            }
+            // requeues the batch, the oldest records are dropped if there is no room
+            for record in batch.into_iter().rev() {
+                if queue.records.len() < shared.capacity {
+                    queue.records.push_front(record);
+                }
+            }
+            let _ = shared.changed.wait_timeout_while(queue, backoff, |queue| !queue.closed).unwrap();
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        } else {
+            backoff = batch_delay.max(Duration::from_millis(100));
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use ansi_term::{Color, Style};
+use flexi_logger::Level;
+
+/// Colors of the colored text output, see `LogConfig::palette()`.
+///
+/// `Color::Fixed` selects a 256-color and `Color::RGB` a truecolor value.
+#[derive(Clone, Debug)]
+pub struct Palette {
+    error: Style,
+    warn: Style,
+    info: Style,
+    debug: Style,
+    trace: Style,
+    timestamp: Style,
+    module: Style,
+    target: Style,
+}
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            error: Color::Red.normal(),
+            warn: Color::Purple.normal(),
+            info: Color::Cyan.normal(),
+            debug: Color::Yellow.normal(),
+            trace: Color::White.dimmed(),
+            timestamp: Color::Green.normal(),
+            module: Color::Yellow.normal(),
+            target: Color::White.bold(),
+        }
+    }
+}
+impl Palette {
+    /// Style of the level and message of records with `level`.
+    pub fn level(mut self, level: Level, style: Style) -> Self {
+        match level {
+            Level::Error => self.error = style,
+            Level::Warn => self.warn = style,
+            Level::Info => self.info = style,
+            Level::Debug => self.debug = style,
+            Level::Trace => self.trace = style,
+        }
+        self
+    }
+    pub fn timestamp(mut self, style: Style) -> Self {
+        self.timestamp = style;
+        self
+    }
+    /// Style of the module and line field, unless `LogConfig::module_colors()` is enabled.
+    pub fn module(mut self, style: Style) -> Self {
+        self.module = style;
+        self
+    }
+    pub fn target(mut self, style: Style) -> Self {
+        self.target = style;
+        self
+    }
+    pub(crate) fn level_style(&self, level: Level) -> Style {
+        match level {
+            Level::Error => self.error,
+            Level::Warn => self.warn,
+            Level::Info => self.info,
+            Level::Debug => self.debug,
+            Level::Trace => self.trace,
+        }
+    }
+    pub(crate) fn timestamp_style(&self) -> Style {
+        self.timestamp
+    }
+    pub(crate) fn module_style(&self) -> Style {
+        self.module
+    }
+    pub(crate) fn target_style(&self) -> Style {
+        self.target
+    }
+}
+
+/// Parses a style of the config file, a color name (`red`, ..., `white`), 256-color number
+/// or `#rrggbb` truecolor value, optionally preceded by `bold`, `dimmed`, `italic` or `underline`.
+#[cfg_attr(not(feature = "toml"), allow(dead_code))]
+pub(crate) fn parse_style(text: &str) -> Option<Style> {
+    let mut words = text.split_whitespace().collect::<Vec<_>>();
+    let color = words.pop()?;
+    let color = match color {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "purple" | "magenta" => Color::Purple,
+        "cyan" => Color::Cyan,
+        "white" => Color::White,
+        _ => match color.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                let value = u32::from_str_radix(hex, 16).ok()?;
+                Color::RGB((value >> 16) as u8, (value >> 8) as u8, value as u8)
+            }
+            Some(_) => return None,
+            None => Color::Fixed(color.parse().ok()?),
+        },
+    };
+    let mut style = color.normal();
+    for word in words {
+        style = match word {
+            "bold" => style.bold(),
+            "dimmed" => style.dimmed(),
+            "italic" => style.italic(),
+            "underline" => style.underline(),
+            _ => return None,
+        };
+    }
+    Some(style)
+}
@@ -0,0 +1,93 @@
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::Cell;
+
+/// Target of the records written by the panic hook.
+pub const PANIC_TARGET: &str = "Panic";
+
+thread_local! {
+    static IN_HOOK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether the current thread is logging the record of the panic hook.
+pub(crate) fn in_hook() -> bool {
+    IN_HOOK.try_with(Cell::get).unwrap_or(false)
+}
+
+/// Logs panics as Error records before running the previously installed hook,
+/// so that panics end up in the same file or journal as the other records.
+///
+/// The record contains the panic message, the thread and a backtrace when enabled
+/// by `RUST_BACKTRACE`. Call after `init()`. Same as `PanicHook::default().install()`.
+pub fn install_panic_hook() {
+    PanicHook::default().install();
+}
+
+/// Panic hook with the level and target of the panic records, e.g.
+/// `PanicHook::default().target("Alert").install()` to route panics to an alert output.
+#[derive(Clone, Debug)]
+pub struct PanicHook {
+    level: log::Level,
+    target: String,
+    flush_on_abort: bool,
+}
+impl Default for PanicHook {
+    fn default() -> PanicHook {
+        PanicHook { level: log::Level::Error, target: PANIC_TARGET.into(), flush_on_abort: true }
+    }
+}
+impl PanicHook {
+    /// Level of the panic records, Error by default.
+    pub fn level(mut self, level: log::Level) -> Self {
+        self.level = level;
+        self
+    }
+    /// Target of the panic records, `PANIC_TARGET` by default.
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = target.into();
+        self
+    }
+    /// Whether the outputs are flushed when the panic aborts the process, on by default.
+    /// Off avoids blocking the abort on a stuck output, at the cost of the buffered records.
+    pub fn flush_on_abort(mut self, flush: bool) -> Self {
+        self.flush_on_abort = flush;
+        self
+    }
+    /// Installs the hook in front of the previously installed one, see `install_panic_hook()`.
+    pub fn install(self) {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let location = info.location();
+            let _ = IN_HOOK.try_with(|in_hook| in_hook.set(true));
+            log::logger().log(&log::Record::builder()
+                .args(format_args!("{}", panic_text(info)))
+                .level(self.level)
+                .target(&self.target)
+                // shown in place of the module, which is unknown
+                .module_path(location.map(|location| location.file()))
+                .file(location.map(|location| location.file()))
+                .line(location.map(|location| location.line()))
+                .build());
+            let _ = IN_HOOK.try_with(|in_hook| in_hook.set(false));
+            if self.flush_on_abort || !cfg!(panic = "abort") {
+                log::logger().flush();
+            }
+            previous(info);
+        }));
+    }
+}
+
+/// `thread 'name' panicked: message` followed by the backtrace when enabled by `RUST_BACKTRACE`.
+pub(crate) fn panic_text(info: &std::panic::PanicHookInfo) -> String {
+    let payload = info.payload();
+    let message = match payload.downcast_ref::<&str>() {
+        Some(message) => *message,
+        None => payload.downcast_ref::<String>().map_or("Box<dyn Any>", String::as_str),
+    };
+    let thread = std::thread::current();
+    let backtrace = Backtrace::capture();
+    let backtrace = match backtrace.status() {
+        BacktraceStatus::Captured => format!("\n{}", backtrace),
+        _ => "".into(),
+    };
+    format!("thread '{}' panicked: {}{}", thread.name().unwrap_or("<unnamed>"), message, backtrace)
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+
+use log::LevelFilter;
+
+/// Error of parsing `-d`/`-v` threshold strings, see `LogConfig::try_new()` and `FilterRule`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// Unknown level `level` in threshold `token`.
+    InvalidLevel { token: String, level: String },
+    /// Regex pattern of threshold `token` cannot be compiled.
+    InvalidPattern { token: String, reason: String },
+    /// Threshold `token` has a `/regex/` pattern without the `regex` feature.
+    RegexUnsupported { token: String },
+    /// Excluded `!pattern` threshold `token` with a level.
+    ExcludedWithLevel { token: String },
+    /// Option of a verbosity string without value.
+    MissingValue(String),
+}
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::InvalidLevel { token, level } => write!(f, "Invalid level '{}' in threshold '{}', expected T, D, I, W, E, O, a level name or 0-5", level, token),
+            ParseError::InvalidPattern { token, reason } => write!(f, "Invalid pattern in threshold '{}': {}", token, reason),
+            ParseError::RegexUnsupported { token } => write!(f, "Invalid pattern in threshold '{}': regex patterns require the regex feature", token),
+            ParseError::ExcludedWithLevel { token } => write!(f, "Invalid pattern in threshold '{}': excluded pattern cannot have a level", token),
+            ParseError::MissingValue(option) => write!(f, "Missing value of {}", option),
+        }
+    }
+}
+impl std::error::Error for ParseError {}
+
+/// Splits `pattern:level` at the last colon, the `::` of module paths is part of the pattern.
+fn split_level(token: &str) -> (&str, Option<&str>) {
+    match token.rfind(':') {
+        Some(ix) if !token[..ix].ends_with(':') => (&token[..ix], Some(&token[ix + 1..])),
+        _ => (token, None),
+    }
+}
+
+/// Abbreviation of `level` used in threshold strings.
+pub(crate) fn level_abbreviation(level: LevelFilter) -> &'static str {
+    &level.as_str()[..1]
+}
+
+/// Checks that a `/regex/` pattern compiles.
+fn check_pattern(token: &str, pattern: &str) -> Result<(), ParseError> {
+    match crate::rules::regex_source(pattern) {
+        #[cfg(feature = "regex")]
+        Some(source) => regex::Regex::new(source)
+            .map(|_| ())
+            .map_err(|err| ParseError::InvalidPattern { token: token.into(), reason: err.to_string() }),
+        #[cfg(not(feature = "regex"))]
+        Some(_) => Err(ParseError::RegexUnsupported { token: token.into() }),
+        None => Ok(()),
+    }
+}
+
+/// Parses level abbreviation, name or number (0 = Off, 1 = Error ... 5 = Trace), case insensitive.
+pub(crate) fn parse_level(level: &str) -> Option<LevelFilter> {
+    match level.to_ascii_lowercase().as_str() {
+        "t" | "trace" | "5" => Some(LevelFilter::Trace),
+        "d" | "debug" | "4" => Some(LevelFilter::Debug),
+        "i" | "info" | "3" => Some(LevelFilter::Info),
+        "w" | "warn" | "warning" | "2" => Some(LevelFilter::Warn),
+        "e" | "error" | "1" => Some(LevelFilter::Error),
+        "o" | "off" | "silent" | "0" => Some(LevelFilter::Off),
+        _ => None,
+    }
+}
+
+/// Kind of the pattern of a `FilterRule`, given by its syntax.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PatternKind {
+    /// Substring of the module path or target, see `MatchMode`.
+    Plain,
+    /// Pattern with `*` wildcards matching the whole name.
+    Glob,
+    /// `/regex/` searched in the name, requires the `regex` feature.
+    Regex,
+    /// `module@target` matching both, each part is a plain, glob or regex pattern.
+    ModuleTarget,
+    /// `key=value` or `key~value` matching a key-value field of the record.
+    Field,
+}
+impl PatternKind {
+    pub fn of(pattern: &str) -> PatternKind {
+        if crate::field_filter::FieldRule::is_field_rule(pattern) {
+            PatternKind::Field
+        } else if crate::rules::CompoundRules::split(pattern).is_some() {
+            PatternKind::ModuleTarget
+        } else if crate::rules::regex_source(pattern).is_some() {
+            PatternKind::Regex
+        } else if pattern.contains('*') {
+            PatternKind::Glob
+        } else {
+            PatternKind::Plain
+        }
+    }
+}
+
+/// Threshold of a `-d`/`-v` string, see `LogConfig::new()` for the meaning of the patterns.
+///
+/// Threshold strings follow the grammar
+///
+/// ```text
+/// thresholds = [ threshold ] { "," [ threshold ] }
+/// threshold  = pattern [ ":" level ] | "!" pattern
+/// pattern    = field | module "@" target | "/" regex "/" | glob | plain
+/// field      = key "=" value | key "~" [ "^" ] value
+/// level      = "T" | "D" | "I" | "W" | "E" | "O" | "0" | "1" | "2" | "3" | "4" | "5"
+///            | "trace" | "debug" | "info" | "warn" | "warning" | "error" | "off" | "silent"
+/// ```
+///
+/// Levels are case insensitive. The level is split off at the last colon unless another
+/// colon precedes it, so `shvapp::rpc` is a pattern and `shvapp::rpc:D` a pattern with a level.
+/// Patterns are trimmed and cannot contain commas. A missing level means Trace, `!pattern`
+/// means Off. `to_string()` gives the threshold parsed back to an equal rule.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FilterRule {
+    pub pattern: String,
+    pub kind: PatternKind,
+    pub level: LevelFilter,
+}
+impl FilterRule {
+    /// Rule of `pattern` with `level`. A leading `!` excludes the pattern like in the
+    /// thresholds, the rule then has level Off.
+    pub fn new(pattern: &str, level: LevelFilter) -> FilterRule {
+        let (pattern, level) = match pattern.strip_prefix('!') {
+            Some(excluded) => (excluded.trim_start_matches(|c: char| c == '!' || c.is_whitespace()), LevelFilter::Off),
+            None => (pattern, level),
+        };
+        FilterRule { pattern: pattern.into(), kind: PatternKind::of(pattern), level }
+    }
+    /// Parses a single `pattern[:level]` or `!pattern` threshold.
+    pub fn parse(token: &str) -> Result<FilterRule, ParseError> {
+        let (pattern, level) = split_level(token);
+        let (pattern, excluded) = match pattern.trim().strip_prefix('!') {
+            Some(pattern) => (pattern.trim(), true),
+            None => (pattern.trim(), false),
+        };
+        match crate::rules::CompoundRules::split(pattern) {
+            Some((module, target)) => check_pattern(token, module).and_then(|_| check_pattern(token, target))?,
+            None => check_pattern(token, pattern)?,
+        }
+        let level = match level {
+            Some(_) if excluded => return Err(ParseError::ExcludedWithLevel { token: token.into() }),
+            None if excluded => LevelFilter::Off,
+            None => LevelFilter::Trace,
+            Some(level) => parse_level(level).ok_or_else(|| ParseError::InvalidLevel { token: token.into(), level: level.into() })?,
+        };
+        Ok(FilterRule::new(pattern, level))
+    }
+}
+impl std::str::FromStr for FilterRule {
+    type Err = ParseError;
+    fn from_str(token: &str) -> Result<FilterRule, ParseError> {
+        FilterRule::parse(token)
+    }
+}
+impl std::fmt::Display for FilterRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the space keeps a trailing colon of the pattern from hiding the level separator
+        let separator = if self.pattern.ends_with(':') { " :" } else { ":" };
+        write!(f, "{}{}{}", self.pattern, separator, level_abbreviation(self.level))
+    }
+}
+
+/// Parses comma separated thresholds into rules, failing on the first invalid one.
+pub fn parse_rules(thresholds: &str) -> Result<Vec<FilterRule>, ParseError> {
+    thresholds.split(',').filter(|token| !token.is_empty()).map(FilterRule::parse).collect()
+}
+
+/// Comma separated thresholds of `rules`, parsed back by `parse_rules()`.
+pub fn format_rules(rules: &[FilterRule]) -> String {
+    rules.iter().map(FilterRule::to_string).collect::<Vec<_>>().join(",")
+}
+
+/// Parses comma separated `pattern[:level]` thresholds to levels by pattern, see `FilterRule`.
+///
+/// Thresholds with an invalid level are returned with Info level together with the errors,
+/// thresholds with an invalid pattern are left out.
+pub(crate) fn parse_level_strings(level_strings: &[String]) -> (HashMap<String, LevelFilter>, Vec<ParseError>) {
+    let mut levels = HashMap::new();
+    let mut errors = Vec::new();
+    for tresholds in level_strings {
+        for token in tresholds.split(',').filter(|token| !token.is_empty()) {
+            match FilterRule::parse(token) {
+                Ok(rule) => {
+                    levels.insert(rule.pattern, rule.level);
+                }
+                Err(err) => {
+                    if let ParseError::InvalidLevel { .. } = err {
+                        levels.insert(split_level(token).0.trim().into(), LevelFilter::Info);
+                    }
+                    errors.push(err);
+                }
+            }
+        }
+    }
+    (levels, errors)
+}
+
+/// Options of a `-d ... -v ... -g ...` verbosity string.
+#[derive(Default)]
+pub(crate) struct Verbosity {
+    pub(crate) module_tresholds: Vec<String>,
+    pub(crate) target_tresholds: Vec<String>,
+    pub(crate) message_filter: Option<String>,
+}
+
+/// Splits a verbosity string into its options, tokens without option are module thresholds.
+pub(crate) fn parse_verbosity(verbosity: &str) -> Result<Verbosity, ParseError> {
+    let mut options = Verbosity::default();
+    let mut tokens = verbosity.split_whitespace();
+    while let Some(token) = tokens.next() {
+        match token {
+            "-d" | "-v" | "-g" => {
+                let value = tokens.next().ok_or_else(|| ParseError::MissingValue(token.into()))?.to_string();
+                match token {
+                    "-d" => options.module_tresholds.push(value),
+                    "-v" => options.target_tresholds.push(value),
+                    _ => options.message_filter = Some(value),
+                }
+            }
+            _ => options.module_tresholds.push(token.to_string()),
+        }
+    }
+    Ok(options)
+}
+
+/// Converts env_logger directives like `info,shvbroker::rpc=trace/device` to module
+/// thresholds, the part after `/` becomes the message filter.
+pub(crate) fn parse_rust_log(spec: &str) -> Verbosity {
+    let (directives, message_filter) = match spec.split_once('/') {
+        Some((directives, filter)) => (directives, Some(filter.to_string()).filter(|filter| !filter.is_empty())),
+        None => (spec, None),
+    };
+    let module_tresholds = directives.split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .map(|directive| match directive.split_once('=') {
+            Some((module, level)) => format!("{}:{}", module, level),
+            None if parse_level(directive).is_some() => format!(":{}", directive),
+            None => directive.to_string(),
+        })
+        .collect();
+    Verbosity { module_tresholds, target_tresholds: Vec::new(), message_filter }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(thresholds: &str) -> (Vec<(String, LevelFilter)>, Vec<ParseError>) {
+        let (levels, errors) = parse_level_strings(&[thresholds.to_string()]);
+        let mut levels: Vec<_> = levels.into_iter().collect();
+        levels.sort();
+        (levels, errors)
+    }
+
+    #[test]
+    fn threshold_levels() {
+        let (parsed, errors) = levels("shvapp::rpc,rpc:debug,:3, broker :w,client:0,,x:E");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(parsed, [
+            ("".into(), LevelFilter::Info),
+            ("broker".into(), LevelFilter::Warn),
+            ("client".into(), LevelFilter::Off),
+            ("rpc".into(), LevelFilter::Debug),
+            ("shvapp::rpc".into(), LevelFilter::Trace),
+            ("x".into(), LevelFilter::Error),
+        ]);
+    }
+
+    #[test]
+    fn excluded_patterns() {
+        assert_eq!(levels("!rpc,! !broker").0, [("broker".into(), LevelFilter::Off), ("rpc".into(), LevelFilter::Off)]);
+        assert_eq!(levels("!rpc:D").1, [ParseError::ExcludedWithLevel { token: "!rpc:D".into() }]);
+    }
+
+    #[test]
+    fn invalid_level_falls_back_to_info() {
+        let (parsed, errors) = levels("rpc:loud,broker:D");
+        assert_eq!(parsed, [("broker".into(), LevelFilter::Debug), ("rpc".into(), LevelFilter::Info)]);
+        assert_eq!(errors, [ParseError::InvalidLevel { token: "rpc:loud".into(), level: "loud".into() }]);
+    }
+
+    #[test]
+    fn verbosity_options() {
+        let verbosity = parse_verbosity("-d rpc:D -v RpcMsg:T broker:I -g device").unwrap();
+        assert_eq!(verbosity.module_tresholds, ["rpc:D", "broker:I"]);
+        assert_eq!(verbosity.target_tresholds, ["RpcMsg:T"]);
+        assert_eq!(verbosity.message_filter.as_deref(), Some("device"));
+        assert_eq!(parse_verbosity("-d").err(), Some(ParseError::MissingValue("-d".into())));
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// State file keeping the runtime verbosity across restarts, see `LogConfig::persist_verbosity()`.
+pub(crate) struct PersistedVerbosity {
+    path: PathBuf,
+    /// Verbosity of the config before the saved one was applied.
+    pub(crate) factory: String,
+    /// Verbosity in the state file, it is written when the active one differs.
+    written: Mutex<Option<String>>,
+}
+impl PersistedVerbosity {
+    pub(crate) fn new(path: PathBuf) -> PersistedVerbosity {
+        PersistedVerbosity { path, factory: String::new(), written: Mutex::new(None) }
+    }
+    /// Remembers `factory` and returns the saved verbosity, None without a state file.
+    pub(crate) fn load(&mut self, factory: String) -> std::io::Result<Option<String>> {
+        self.factory = factory;
+        let saved = match std::fs::read_to_string(&self.path) {
+            Ok(saved) => Some(saved.trim().to_string()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        *self.written.lock().unwrap() = saved.clone();
+        Ok(saved)
+    }
+    /// Writes `verbosity` to the state file if it changed, replacing the file at once so that
+    /// a power loss leaves the previous or the new one.
+    pub(crate) fn save(&self, verbosity: String) {
+        let mut written = self.written.lock().unwrap();
+        if written.as_deref() == Some(verbosity.as_str()) || (written.is_none() && verbosity == self.factory) {
+            return;
+        }
+        let tmp = self.path.with_extension("tmp");
+        match std::fs::write(&tmp, format!("{}\n", verbosity)).and_then(|_| std::fs::rename(&tmp, &self.path)) {
+            Ok(()) => *written = Some(verbosity),
+            Err(err) => eprintln!("shvlog: cannot save the verbosity to {}: {}", self.path.display(), err),
+        }
+    }
+    /// Removes the state file, the factory verbosity applies again.
+    pub(crate) fn forget(&self) -> std::io::Result<()> {
+        let mut written = self.written.lock().unwrap();
+        match std::fs::remove_file(&self.path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        *written = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("shvlog-verbosity-{}", std::process::id()));
+        let mut state = PersistedVerbosity::new(path.clone());
+        assert_eq!(state.load("-d :I".into()).unwrap(), None);
+        state.save("-d :I".into());
+        assert!(!path.exists(), "the factory verbosity is not saved");
+        state.save("-d :I,rpc:D".into());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "-d :I,rpc:D\n");
+        let mut restarted = PersistedVerbosity::new(path.clone());
+        assert_eq!(restarted.load("-d :I".into()).unwrap().as_deref(), Some("-d :I,rpc:D"));
+        restarted.save("-d :I".into());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "-d :I\n");
+        restarted.forget().unwrap();
+        assert!(!path.exists());
+    }
+}
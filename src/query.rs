@@ -0,0 +1,91 @@
+use chrono::{DateTime, Local};
+
+use crate::LogRecord;
+
+/// Query of `ShvLogHandle::get_log()` with the semantics of SHV `getLog`.
+///
+/// Records from `since` (inclusive) up to `until` (exclusive) are returned oldest first,
+/// at most `record_count_limit()` of them.
+#[derive(Clone, Debug)]
+pub struct GetLogParams {
+    since: Option<DateTime<Local>>,
+    until: Option<DateTime<Local>>,
+    record_count_limit: usize,
+    path_pattern: Option<Vec<String>>,
+    level: Option<log::Level>,
+}
+impl Default for GetLogParams {
+    fn default() -> Self {
+        GetLogParams { since: None, until: None, record_count_limit: 1000, path_pattern: None, level: None }
+    }
+}
+impl GetLogParams {
+    pub fn new() -> GetLogParams {
+        GetLogParams::default()
+    }
+    /// Skips records older than `time`.
+    pub fn since(mut self, time: DateTime<Local>) -> Self {
+        self.since = Some(time);
+        self
+    }
+    /// Skips records logged at or after `time`.
+    pub fn until(mut self, time: DateTime<Local>) -> Self {
+        self.until = Some(time);
+        self
+    }
+    /// Maximal number of returned records, 1000 by default.
+    pub fn record_count_limit(mut self, count: usize) -> Self {
+        self.record_count_limit = count;
+        self
+    }
+    /// Pattern of the target as SHV path, with `/` or `::` separators. A `*` segment matches
+    /// one segment, `**` any number of them, `*` inside a segment any characters, e.g.
+    /// `shvapp/**/client` or `shvapp::rpc*`.
+    pub fn path_pattern(mut self, pattern: &str) -> Self {
+        self.path_pattern = Some(path_segments(pattern).map(String::from).collect());
+        self
+    }
+    /// Skips records less severe than `level`.
+    pub fn level(mut self, level: log::Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+    pub(crate) fn query<'a>(&self, records: impl Iterator<Item = &'a LogRecord>) -> Vec<LogRecord> {
+        records
+            .filter(|record| self.matches(record))
+            .take(self.record_count_limit)
+            .cloned()
+            .collect()
+    }
+    fn matches(&self, record: &LogRecord) -> bool {
+        self.since.is_none_or(|since| record.timestamp >= since)
+            && self.until.is_none_or(|until| record.timestamp < until)
+            && self.level.is_none_or(|level| record.level <= level)
+            && self.path_pattern.as_ref().is_none_or(|pattern| {
+                path_matches(pattern, &path_segments(&record.target).collect::<Vec<_>>())
+            })
+    }
+}
+
+fn path_segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split(['/', ':']).filter(|segment| !segment.is_empty())
+}
+
+fn path_matches(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => (0..=path.len()).any(|skip| path_matches(rest, &path[skip..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path_rest)) => segment_matches(first, segment) && path_matches(rest, path_rest),
+            None => false,
+        },
+    }
+}
+
+fn segment_matches(pattern: &str, segment: &str) -> bool {
+    if pattern.contains('*') {
+        crate::rules::glob_matches(&pattern.split('*').map(String::from).collect::<Vec<_>>(), segment)
+    } else {
+        pattern == segment
+    }
+}
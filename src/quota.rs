@@ -0,0 +1,110 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use flexi_logger::filter::LogLineWriter;
+use flexi_logger::DeferredNow;
+
+struct QuotaState {
+    window_start: Instant,
+    used_bytes: usize,
+    suppressed: usize,
+}
+
+/// Limits the message bytes a target may write within a time window.
+pub(crate) struct Quota {
+    target: String,
+    max_bytes: usize,
+    window: Duration,
+    state: Mutex<QuotaState>,
+}
+impl Quota {
+    pub(crate) fn new(target: &str, max_bytes: usize, window: Duration) -> Quota {
+        Quota {
+            target: target.into(),
+            max_bytes,
+            window,
+            state: Mutex::new(QuotaState { window_start: Instant::now(), used_bytes: 0, suppressed: 0 }),
+        }
+    }
+    pub(crate) fn matches(&self, record: &log::Record) -> bool {
+        record.target().contains(&self.target)
+    }
+    /// Accounts `record` and returns whether it fits into the quota.
+    /// Writes a notice when suppression starts and when the window resets after suppression.
+    pub(crate) fn admit(&self, now: &mut DeferredNow, record: &log::Record, log_line_writer: &dyn LogLineWriter) -> std::io::Result<bool> {
+        let message_len = record.args().to_string().len();
+        let mut state = self.state.lock().unwrap();
+        if state.window_start.elapsed() >= self.window {
+            if state.suppressed > 0 {
+                let notice = format!("Log quota for target '{}' reset, {} records were suppressed", self.target, state.suppressed);
+                write_notice(now, record, &notice, log::Level::Info, log_line_writer)?;
+            }
+            *state = QuotaState { window_start: Instant::now(), used_bytes: 0, suppressed: 0 };
+        }
+        if state.suppressed == 0 && state.used_bytes + message_len <= self.max_bytes {
+            state.used_bytes += message_len;
+            return Ok(true);
+        }
+        if state.suppressed == 0 {
+            let notice = format!(
+                "Log quota of {} bytes per {:?} exceeded for target '{}', suppressing records until the window resets",
+                self.max_bytes,
+                self.window,
+                self.target
+            );
+            write_notice(now, record, &notice, log::Level::Warn, log_line_writer)?;
+        }
+        state.suppressed += 1;
+        Ok(false)
+    }
+}
+
+fn write_notice(now: &mut DeferredNow, record: &log::Record, notice: &str, level: log::Level, log_line_writer: &dyn LogLineWriter) -> std::io::Result<()> {
+    log_line_writer.write(now, &log::Record::builder()
+        .args(format_args!("{}", notice))
+        .level(level)
+        .target(record.target())
+        .module_path(record.module_path())
+        .file(record.file())
+        .line(record.line())
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Collect(Mutex<Vec<String>>);
+    impl LogLineWriter for Collect {
+        fn write(&self, _now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+            self.0.lock().unwrap().push(record.args().to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bytes_per_window() {
+        let quota = Quota::new("modbus", 10, Duration::from_secs(1));
+        let output = Collect(Mutex::new(Vec::new()));
+        let log = |record: &log::Record| {
+            assert!(quota.matches(record));
+            if quota.admit(&mut DeferredNow::new(), record, &output).unwrap() {
+                output.write(&mut DeferredNow::new(), record).unwrap();
+            }
+        };
+        for message in ["hello", "world", "again", "more", "later"] {
+            if message == "later" {
+                quota.state.lock().unwrap().window_start -= Duration::from_secs(1);
+            }
+            log(&log::Record::builder().target("modbus::rtu").args(format_args!("{}", message)).build());
+        }
+        assert_eq!(*output.0.lock().unwrap(), [
+            "hello",
+            "world",
+            "Log quota of 10 bytes per 1s exceeded for target 'modbus', suppressing records until the window resets",
+            "Log quota for target 'modbus' reset, 2 records were suppressed",
+            "later",
+        ]);
+        assert!(!quota.matches(&log::Record::builder().target("rpc").build()));
+    }
+}
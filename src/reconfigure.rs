@@ -0,0 +1,259 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::thread;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use log::LevelFilter;
+
+use crate::field_filter::FieldRule;
+use crate::rules::{CompoundRules, Rules};
+use crate::throttle::Throttle;
+use crate::{FilterRule, LogConfig, ParseError};
+
+enum Change {
+    ModuleLevel(String, LevelFilter),
+    TargetLevel(String, LevelFilter),
+    /// Thresholds parsed from `-d`/`-v` strings or given as rules.
+    Levels(Box<LogConfig>),
+    MessageFilter(Option<String>),
+    Throttle(Throttle),
+    ClearThrottles,
+}
+
+/// Thresholds replaced by `Reconfiguration::set_levels()`.
+struct Thresholds {
+    module_levels: Rules,
+    target_levels: Rules,
+    compound_levels: CompoundRules,
+    field_levels: Vec<FieldRule>,
+    default_level: LevelFilter,
+}
+impl Thresholds {
+    /// Replaces the thresholds of `config`, returns the replaced ones.
+    fn swap(config: &mut LogConfig, thresholds: Thresholds) -> Thresholds {
+        Thresholds {
+            module_levels: std::mem::replace(&mut config.module_levels, thresholds.module_levels),
+            target_levels: std::mem::replace(&mut config.target_levels, thresholds.target_levels),
+            compound_levels: std::mem::replace(&mut config.compound_levels, thresholds.compound_levels),
+            field_levels: std::mem::replace(&mut config.field_levels, thresholds.field_levels),
+            default_level: std::mem::replace(&mut config.default_level, thresholds.default_level),
+        }
+    }
+    /// What the thresholds of `config` are compared by.
+    fn rules(config: &LogConfig) -> (Vec<FilterRule>, Vec<FilterRule>, LevelFilter) {
+        (config.module_rules(), config.target_rules(), config.default_level)
+    }
+}
+
+/// Reverts a change of an override of `ShvLogHandle::set_level_for()`, unless it was
+/// changed again meanwhile.
+enum Undo {
+    ModuleLevel { pattern: String, before: Option<LevelFilter>, after: LevelFilter },
+    TargetLevel { pattern: String, before: Option<LevelFilter>, after: LevelFilter },
+    Levels { before: Thresholds, after: (Vec<FilterRule>, Vec<FilterRule>, LevelFilter) },
+    MessageFilter { before: Option<String>, after: Option<String> },
+}
+
+lazy_static! {
+    /// Changes to revert once the latest override expires, in the applied order, with the
+    /// override id.
+    static ref PREVIOUS: Mutex<Option<(u64, Vec<Undo>)>> = Mutex::new(None);
+}
+static NEXT_OVERRIDE: AtomicU64 = AtomicU64::new(0);
+
+/// Changes of the active config collected by `ShvLogHandle::reconfigure()`, applied together.
+#[derive(Default)]
+pub struct Reconfiguration {
+    changes: Vec<Change>,
+    error: Option<ParseError>,
+}
+impl Reconfiguration {
+    /// Sets threshold of modules whose path contains `module`, like `ShvLogHandle::set_module_level()`.
+    pub fn set_module_level(&mut self, module: &str, level: LevelFilter) {
+        self.changes.push(Change::ModuleLevel(module.into(), level));
+    }
+    /// Sets threshold of records with target containing `target`, like `ShvLogHandle::set_target_level()`.
+    pub fn set_target_level(&mut self, target: &str, level: LevelFilter) {
+        self.changes.push(Change::TargetLevel(target.into(), level));
+    }
+    /// Replaces the thresholds from `-d`/`-v` strings, like `ShvLogHandle::set_levels()`.
+    /// An invalid threshold cancels the whole reconfiguration.
+    pub fn set_levels(&mut self, module_tresholds: &[String], target_tresholds: &[String]) {
+        match LogConfig::try_new(module_tresholds, target_tresholds) {
+            Ok(levels) => self.changes.push(Change::Levels(Box::new(levels))),
+            Err(err) => self.error = self.error.take().or(Some(err)),
+        }
+    }
+    /// Replaces the thresholds by typed rules, see `LogConfig::from_rules()`.
+    pub fn set_rules(&mut self, module_rules: &[FilterRule], target_rules: &[FilterRule]) {
+        self.changes.push(Change::Levels(Box::new(LogConfig::from_rules(module_rules, target_rules))));
+    }
+    /// Sets or clears the message filter, see `LogConfig::with_message_filter()`.
+    pub fn set_message_filter(&mut self, pattern: Option<&str>) {
+        self.changes.push(Change::MessageFilter(pattern.map(String::from)));
+    }
+    /// Adds a throttle, see `LogConfig::throttle()`.
+    pub fn throttle(&mut self, pattern: &str, rate: f64, burst: usize) {
+        self.changes.push(Change::Throttle(Throttle::new(pattern, rate, burst)));
+    }
+    /// Removes the throttles, including those of the initial config.
+    pub fn clear_throttles(&mut self) {
+        self.changes.push(Change::ClearThrottles);
+    }
+    /// Applies the changes in order, or none of them when one was invalid.
+    pub(crate) fn apply(self, config: &mut LogConfig) -> Result<(), ParseError> {
+        self.apply_undoable(config).map(drop)
+    }
+    /// Applies the changes like `apply()`, returns how to revert the thresholds and the
+    /// message filter.
+    fn apply_undoable(self, config: &mut LogConfig) -> Result<Vec<Undo>, ParseError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        let mut undo = Vec::new();
+        for change in self.changes {
+            match change {
+                Change::ModuleLevel(pattern, level) => {
+                    undo.push(Undo::ModuleLevel { before: config.rule_level(&pattern, false), after: level, pattern: pattern.clone() });
+                    config.set_rule_level(&pattern, level, false);
+                }
+                Change::TargetLevel(pattern, level) => {
+                    undo.push(Undo::TargetLevel { before: config.rule_level(&pattern, true), after: level, pattern: pattern.clone() });
+                    config.set_rule_level(&pattern, level, true);
+                }
+                Change::Levels(levels) => {
+                    let levels = *levels;
+                    let before = Thresholds::swap(config, Thresholds {
+                        module_levels: levels.module_levels,
+                        target_levels: levels.target_levels,
+                        compound_levels: levels.compound_levels,
+                        field_levels: levels.field_levels,
+                        default_level: config.default_level,
+                    });
+                    undo.push(Undo::Levels { before, after: Thresholds::rules(config) });
+                }
+                Change::MessageFilter(pattern) => {
+                    undo.push(Undo::MessageFilter { before: config.message_filter.clone(), after: pattern.clone() });
+                    config.message_filter = pattern;
+                }
+                Change::Throttle(throttle) => config.throttles.push(throttle),
+                Change::ClearThrottles => config.throttles.clear(),
+            }
+        }
+        config.update_max_level();
+        Ok(undo)
+    }
+}
+
+/// Starts the "shvlog-revert" thread reverting `reconfiguration` after `duration` and applies
+/// it to `active`. An override made before the previous one expired postpones the revert,
+/// which then reverts both of them. Invalid changes fail with `InvalidInput`.
+pub(crate) fn apply_for(active: &Arc<RwLock<LogConfig>>, reconfiguration: Reconfiguration, duration: Duration) -> std::io::Result<()> {
+    let id = NEXT_OVERRIDE.fetch_add(1, Ordering::Relaxed);
+    let weak = Arc::downgrade(active);
+    // held until applied, the revert waits for the override of its id
+    let mut previous = PREVIOUS.lock().unwrap();
+    // spawned first, nothing is applied when it fails, and the revert of changes that turn out
+    // invalid finds no override of its id
+    thread::Builder::new()
+        .name("shvlog-revert".into())
+        .spawn(move || {
+            thread::sleep(duration);
+            revert(&weak, id, duration);
+        })?;
+    let mut config = active.write().unwrap();
+    let undo = reconfiguration.apply_undoable(&mut config).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    let mut kept = previous.take().map(|(_, kept)| kept).unwrap_or_default();
+    kept.extend(undo);
+    *previous = Some((id, kept));
+    Ok(())
+}
+
+fn revert(active: &Weak<RwLock<LogConfig>>, id: u64, duration: Duration) {
+    let undo = {
+        let mut previous = PREVIOUS.lock().unwrap();
+        match previous.take() {
+            Some((latest, kept)) if latest == id => kept,
+            other => {
+                *previous = other;
+                return;
+            }
+        }
+    };
+    let Some(active) = active.upgrade() else {
+        return;
+    };
+    let verbosity = {
+        let mut config = active.write().unwrap();
+        for undo in undo.into_iter().rev() {
+            match undo {
+                Undo::ModuleLevel { pattern, before, after } => {
+                    if config.rule_level(&pattern, false) == Some(after) {
+                        match before {
+                            Some(level) => config.set_rule_level(&pattern, level, false),
+                            None => config.remove_rule(&pattern, false),
+                        }
+                    }
+                }
+                Undo::TargetLevel { pattern, before, after } => {
+                    if config.rule_level(&pattern, true) == Some(after) {
+                        match before {
+                            Some(level) => config.set_rule_level(&pattern, level, true),
+                            None => config.remove_rule(&pattern, true),
+                        }
+                    }
+                }
+                Undo::Levels { before, after } => {
+                    if Thresholds::rules(&config) == after {
+                        Thresholds::swap(&mut config, before);
+                    }
+                }
+                Undo::MessageFilter { before, after } => {
+                    if config.message_filter == after {
+                        config.message_filter = before;
+                    }
+                }
+            }
+        }
+        config.update_max_level();
+        config.verbosity_string()
+    };
+    log::info!(target: "shvlog", "verbosity override expired after {:?}, reverted to '{}'", duration, verbosity);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn temporary_compound_and_field_levels() {
+        let active = Arc::new(RwLock::new(LogConfig::from_verbosity_string("-d :I").unwrap()));
+        let mut invalid = Reconfiguration::default();
+        invalid.set_module_level("rpc@RpcMsg", LevelFilter::Trace);
+        invalid.set_levels(&["rpc:loud".into()], &[]);
+        let err = apply_for(&active, invalid, Duration::from_millis(10)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(active.read().unwrap().rule_level("rpc@RpcMsg", false), None);
+
+        let mut reconfiguration = Reconfiguration::default();
+        reconfiguration.set_module_level("rpc@RpcMsg", LevelFilter::Trace);
+        reconfiguration.set_target_level("device=7", LevelFilter::Debug);
+        apply_for(&active, reconfiguration, Duration::from_millis(50)).unwrap();
+        {
+            let config = active.read().unwrap();
+            assert_eq!(config.compound_levels.get("rpc@RpcMsg"), Some(LevelFilter::Trace));
+            assert_eq!(FieldRule::get(&config.field_levels, "device=7"), Some(LevelFilter::Debug));
+            assert_eq!(config.module_levels.get("rpc@RpcMsg"), None);
+            assert_eq!(config.verbosity_string(), "-d :I,rpc@RpcMsg:T,device=7:D");
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while active.read().unwrap().rule_level("rpc@RpcMsg", false).is_some() {
+            assert!(Instant::now() < deadline, "not reverted");
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(active.read().unwrap().verbosity_string(), "-d :I");
+    }
+}
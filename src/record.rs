@@ -0,0 +1,130 @@
+use std::fmt;
+
+use chrono::{DateTime, Local};
+use flexi_logger::DeferredNow;
+
+use crate::chainpack::{cpon_date_time, cpon_string, ChainPackWriter};
+use crate::json::JsonStr;
+use crate::timezone;
+
+/// Owned copy of an emitted log record.
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    pub timestamp: DateTime<Local>,
+    pub level: log::Level,
+    pub module: String,
+    pub target: String,
+    pub line: Option<u32>,
+    pub message: String,
+}
+impl LogRecord {
+    pub(crate) fn from_record(now: &mut DeferredNow, record: &log::Record) -> LogRecord {
+        LogRecord {
+            timestamp: *now.now(),
+            level: record.level(),
+            module: record.module_path().unwrap_or("").into(),
+            target: record.target().into(),
+            line: record.line(),
+            message: record.args().to_string(),
+        }
+    }
+    /// Target if it differs from the module path, like in the text output.
+    pub fn explicit_target(&self) -> Option<&str> {
+        if self.module == self.target { None } else { Some(&self.target) }
+    }
+    /// CPON map `{"timestamp":d"..","level":..,"module":..,"target":..,"line":..,"message":..}`,
+    /// the level is 1 for Error up to 5 for Trace, the line is a UInt like in ChainPack, a
+    /// missing line is null.
+    pub fn to_cpon(&self) -> String {
+        format!(
+            "{{\"timestamp\":{},\"level\":{},\"module\":{},\"target\":{},\"line\":{},\"message\":{}}}",
+            cpon_date_time(&timezone::localize(&self.timestamp)),
+            self.level as usize,
+            cpon_string(&self.module),
+            cpon_string(&self.target),
+            self.line.map_or_else(|| "null".to_string(), |line| format!("{}u", line)),
+            cpon_string(&self.message),
+        )
+    }
+    /// JSON object with the members of `to_cpon()`, the level as name like in `LogFormat::Json`,
+    /// e.g. `{"timestamp":"2024-01-02T03:04:05.678+01:00","level":"WARN",...}`.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"timestamp\":\"{}\",\"level\":\"{}\",\"module\":{},\"target\":{},\"line\":{},\"message\":{}}}",
+            timezone::localize(&self.timestamp).format("%Y-%m-%dT%H:%M:%S%.3f%:z"),
+            self.level.as_str(),
+            JsonStr(&self.module),
+            JsonStr(&self.target),
+            self.line.map_or_else(|| "null".to_string(), |line| line.to_string()),
+            JsonStr(&self.message),
+        )
+    }
+    /// The map of `to_cpon()` encoded as ChainPack.
+    pub fn to_chainpack(&self) -> Vec<u8> {
+        let mut writer = ChainPackWriter::default();
+        self.write_chainpack(&mut writer);
+        writer.0
+    }
+    /// CPON list of the records, e.g. of `ShvLogHandle::snapshot()`.
+    pub fn list_to_cpon(records: &[LogRecord]) -> String {
+        format!("[{}]", records.iter().map(LogRecord::to_cpon).collect::<Vec<_>>().join(","))
+    }
+    /// ChainPack list of the records.
+    pub fn list_to_chainpack(records: &[LogRecord]) -> Vec<u8> {
+        let mut writer = ChainPackWriter::default();
+        writer.list_begin();
+        for record in records {
+            record.write_chainpack(&mut writer);
+        }
+        writer.container_end();
+        writer.0
+    }
+    fn write_chainpack(&self, writer: &mut ChainPackWriter) {
+        writer.map_begin();
+        writer.string("timestamp");
+        writer.date_time(&timezone::localize(&self.timestamp));
+        writer.string("level");
+        writer.int(self.level as i64);
+        writer.string("module");
+        writer.string(&self.module);
+        writer.string("target");
+        writer.string(&self.target);
+        writer.string("line");
+        match self.line {
+            Some(line) => writer.uint(u64::from(line)),
+            None => writer.null(),
+        }
+        writer.string("message");
+        writer.string(&self.message);
+        writer.container_end();
+    }
+}
+/// Renders the record like the text output, without colors.
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}:{}]",
+            timezone::localize(&self.timestamp).format("%Y-%m-%dT%H:%M:%S%.3f%:z"),
+            if self.module.is_empty() { "<unnamed>" } else { &self.module },
+            self.line.unwrap_or(0)
+        )?;
+        if let Some(target) = self.explicit_target() {
+            write!(f, "({})", target)?;
+        }
+        write!(f, "|{}|{}", &self.level.as_str()[..1], self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpon_line() {
+        let mut record = LogRecord::from_record(&mut DeferredNow::new(), &log::Record::builder().level(log::Level::Warn).line(Some(42)).args(format_args!("a")).build());
+        assert!(record.to_cpon().ends_with(r#""level":2,"module":"","target":"","line":42u,"message":"a"}"#), "{}", record.to_cpon());
+        record.line = None;
+        assert!(record.to_cpon().contains(r#""line":null,"#));
+    }
+}
@@ -0,0 +1,96 @@
+use log::kv::{Error, Key, Source, Value, VisitSource};
+
+/// Replacement of the redacted text.
+const MASK: &str = "***";
+
+/// Masking of secrets in messages and key-value fields, see `LogConfig::redact()`.
+#[derive(Default)]
+pub(crate) struct Redaction {
+    #[cfg(feature = "regex")]
+    patterns: Vec<regex::Regex>,
+    fields: Vec<String>,
+}
+impl Redaction {
+    #[cfg(feature = "regex")]
+    pub(crate) fn add_pattern(&mut self, pattern: regex::Regex) {
+        self.patterns.push(pattern);
+    }
+    pub(crate) fn add_field(&mut self, key: &str) {
+        self.fields.push(key.into());
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        #[cfg(feature = "regex")]
+        if !self.patterns.is_empty() {
+            return false;
+        }
+        self.fields.is_empty()
+    }
+    /// Masked message, `None` when nothing matches.
+    #[cfg(feature = "regex")]
+    pub(crate) fn message(&self, record: &log::Record) -> Option<String> {
+        if self.patterns.is_empty() {
+            return None;
+        }
+        let message = record.args().to_string();
+        let mut masked = None;
+        for pattern in &self.patterns {
+            let text: &str = masked.as_deref().unwrap_or(&message);
+            if pattern.is_match(text) {
+                masked = Some(mask(pattern, text));
+            }
+        }
+        masked
+    }
+    #[cfg(not(feature = "regex"))]
+    pub(crate) fn message(&self, _record: &log::Record) -> Option<String> {
+        None
+    }
+    /// Whether a field of the record is to be masked.
+    pub(crate) fn masks_fields(&self, record: &log::Record) -> bool {
+        !self.fields.is_empty() && self.fields.iter().any(|key| record.key_values().get(Key::from_str(key)).is_some())
+    }
+    fn masks(&self, key: &Key) -> bool {
+        self.fields.iter().any(|field| field == key.as_str())
+    }
+}
+
+/// Replaces the matches of `pattern`, or its first capture group if it has one,
+/// e.g. `password=(\S+)` keeps the key visible.
+#[cfg(feature = "regex")]
+fn mask(pattern: &regex::Regex, text: &str) -> String {
+    let mut masked = String::new();
+    let mut end = 0;
+    for captures in pattern.captures_iter(text) {
+        let Some(secret) = captures.get(1).or_else(|| captures.get(0)) else { continue };
+        masked.push_str(&text[end..secret.start()]);
+        masked.push_str(MASK);
+        end = secret.end();
+    }
+    masked.push_str(&text[end..]);
+    masked
+}
+
+/// Key-value fields of a record with the redacted values masked.
+pub(crate) struct MaskedFields<'a> {
+    pub(crate) source: &'a dyn Source,
+    pub(crate) redaction: &'a Redaction,
+}
+impl Source for MaskedFields<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), Error> {
+        self.source.visit(&mut MaskingVisitor { visitor, redaction: self.redaction })
+    }
+}
+
+struct MaskingVisitor<'a, 'v, 'kvs> {
+    visitor: &'v mut dyn VisitSource<'kvs>,
+    redaction: &'a Redaction,
+}
+impl<'kvs> VisitSource<'kvs> for MaskingVisitor<'_, '_, 'kvs> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        if self.redaction.masks(&key) {
+            self.visitor.visit_pair(key, Value::from(MASK))
+        } else {
+            self.visitor.visit_pair(key, value)
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::path::{Path, PathBuf};
+use std::sync::{RwLock, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::{handle, LogConfig};
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Replaces the active config by the one loaded from `path`, errors are logged.
+pub(crate) fn reload_config(active: &RwLock<LogConfig>, path: &Path) {
+    match LogConfig::from_toml_file(path) {
+        Ok(config) => match handle::replace_config(active, config) {
+            Ok(()) => log::info!("Log config reloaded from {}", path.display()),
+            Err(err) => log::error!("Cannot activate log config from {}: {}", path.display(), err),
+        },
+        Err(err) => log::error!("{}", err),
+    }
+}
+
+/// Spawns the thread polling `path` every `interval`, it ends once the active config is dropped.
+pub(crate) fn spawn_watcher(path: PathBuf, active: Weak<RwLock<LogConfig>>, interval: Duration) -> std::io::Result<()> {
+    thread::Builder::new()
+        .name("shvlog-reload".into())
+        .spawn(move || {
+            let mut loaded = modified(&path);
+            loop {
+                thread::sleep(interval);
+                let Some(active) = active.upgrade() else {
+                    break;
+                };
+                let current = modified(&path);
+                // editors may still be writing, wait until the file stays unchanged for an interval
+                if current == loaded {
+                    continue;
+                }
+                thread::sleep(interval);
+                if current != modified(&path) {
+                    continue;
+                }
+                loaded = current;
+                reload_config(&active, &path);
+            }
+        })?;
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use flexi_logger::writers::LogWriter;
+use flexi_logger::DeferredNow;
+
+use crate::LogFormat;
+
+/// Delay of the first reconnection attempt, doubled after every failure.
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+enum Endpoint {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// Streams newline delimited records to a collector, see `LogConfig::with_remote()`.
+///
+/// Records are queued and sent by the "shvlog-remote" thread, which reconnects with
+/// exponential backoff when the connection is lost. While disconnected up to `buffer()`
+/// records are kept, older ones are dropped and reported after reconnecting.
+#[derive(Clone, Debug)]
+pub struct RemoteLog {
+    endpoint: Endpoint,
+    format: LogFormat,
+    capacity: usize,
+    max_backoff: Duration,
+    pub(crate) level: Option<log::Level>,
+}
+impl RemoteLog {
+    /// Connects to `addr`, e.g. `"collector:5170"`.
+    pub fn tcp(addr: &str) -> RemoteLog {
+        RemoteLog::new(Endpoint::Tcp(addr.into()))
+    }
+    /// Connects to a local stream socket.
+    #[cfg(unix)]
+    pub fn unix(path: impl Into<PathBuf>) -> RemoteLog {
+        RemoteLog::new(Endpoint::Unix(path.into()))
+    }
+    fn new(endpoint: Endpoint) -> RemoteLog {
+        RemoteLog { endpoint, format: LogFormat::Text, capacity: 10_000, max_backoff: Duration::from_secs(30), level: None }
+    }
+    /// Skips records less severe than `level`, see `Syslog::level()`.
+    pub fn level(mut self, level: log::Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+    /// Format of the sent records, text without colors by default.
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+    /// Number of records kept while the collector is unreachable, 10000 by default.
+    pub fn buffer(mut self, records: usize) -> Self {
+        self.capacity = records.max(1);
+        self
+    }
+    /// Longest delay between reconnection attempts, 30 s by default.
+    pub fn max_backoff(mut self, delay: Duration) -> Self {
+        self.max_backoff = delay.max(MIN_BACKOFF);
+        self
+    }
+    pub(crate) fn writer(&self) -> std::io::Result<RemoteWriter> {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            changed: Condvar::new(),
+            capacity: self.capacity,
+        });
+        let thread_shared = shared.clone();
+        let endpoint = self.endpoint.clone();
+        let max_backoff = self.max_backoff;
+        let format = self.format;
+        let thread = thread::Builder::new()
+            .name("shvlog-remote".into())
+            .spawn(move || send_queued(&thread_shared, &endpoint, format, max_backoff))?;
+        Ok(RemoteWriter { shared, format: self.format, thread: Mutex::new(Some(thread)) })
+    }
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+impl Connection {
+    fn open(endpoint: &Endpoint) -> std::io::Result<Connection> {
+        Ok(match endpoint {
+            Endpoint::Tcp(addr) => {
+                let mut result = Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("cannot resolve {}", addr)));
+                for addr in addr.to_socket_addrs()? {
+                    result = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT);
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+                Connection::Tcp(result?)
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => Connection::Unix(UnixStream::connect(path)?),
+        })
+    }
+    #[cfg_attr(not(unix), allow(clippy::infallible_destructuring_match))]
+    fn send(&mut self, lines: &[Vec<u8>]) -> std::io::Result<()> {
+        let stream: &mut dyn Write = match self {
+            Connection::Tcp(stream) => stream,
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream,
+        };
+        for line in lines {
+            stream.write_all(line)?;
+        }
+        stream.flush()
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    lines: VecDeque<Vec<u8>>,
+    /// Records dropped from the full queue, not yet reported to the collector.
+    dropped: u64,
+    sending: bool,
+    connected: bool,
+    closed: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    changed: Condvar,
+    capacity: usize,
+}
+
+pub(crate) struct RemoteWriter {
+    shared: Arc<Shared>,
+    format: LogFormat,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+impl LogWriter for RemoteWriter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        let mut line = Vec::new();
+        crate::format_plain_record(&mut line, now, record, Some(self.format))?;
+        line.push(b'\n');
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.closed {
+            return Ok(());
+        }
+        if queue.lines.len() >= self.shared.capacity {
+            queue.lines.pop_front();
+            queue.dropped += 1;
+        }
+        queue.lines.push_back(line);
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+    /// Waits until the queued records are sent, unless the collector is unreachable.
+    fn flush(&self) -> std::io::Result<()> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        while (!queue.lines.is_empty() || queue.sending) && queue.connected && !queue.closed {
+            queue = self.shared.changed.wait(queue).unwrap();
+        }
+        Ok(())
+    }
+    /// Sends the queued records if connected and stops the sending thread.
+    fn shutdown(&self) {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Notice about the dropped records in the format of the other records.
+fn dropped_notice(dropped: u64, format: LogFormat) -> Vec<u8> {
+    let mut line = Vec::new();
+    let message = format!("{} records dropped while the collector was unreachable", dropped);
+    let _ = crate::format_plain_record(&mut line, &mut DeferredNow::new(), &log::Record::builder()
+        .args(format_args!("{}", message))
+        .level(log::Level::Warn)
+        .target("shvlog")
+        .module_path_static(Some("shvlog::remote"))
+        .build(), Some(format));
+    line.push(b'\n');
+    line
+}
+
+fn send_queued(shared: &Shared, endpoint: &Endpoint, format: LogFormat, max_backoff: Duration) {
+    let mut connection = None;
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let mut queue = shared.queue.lock().unwrap();
+        while queue.lines.is_empty() && !queue.closed {
+            queue = shared.changed.wait(queue).unwrap();
+        }
+        if queue.lines.is_empty() || (queue.closed && connection.is_none()) {
+            return;
+        }
+        if connection.is_none() {
+            drop(queue);
+            match Connection::open(endpoint) {
+                Ok(opened) => {
+                    connection = Some(opened);
+                    backoff = MIN_BACKOFF;
+                    shared.queue.lock().unwrap().connected = true;
+                }
+                Err(_) => {
+                    // waits for the backoff, shutdown interrupts the wait
+                    let queue = shared.queue.lock().unwrap();
+                    let _ = shared.changed.wait_timeout_while(queue, backoff, |queue| !queue.closed).unwrap();
+                    backoff = (backoff * 2).min(max_backoff);
+                }
+            }
+            continue;
+        }
+        let mut lines: Vec<_> = queue.lines.drain(..).collect();
+        let dropped = std::mem::take(&mut queue.dropped);
+        queue.sending = true;
+        drop(queue);
+        if dropped > 0 {
+            lines.insert(0, dropped_notice(dropped, format));
+        }
+        let sent = connection.as_mut().is_some_and(|connection| connection.send(&lines).is_ok());
+        let mut queue = shared.queue.lock().unwrap();
+        queue.sending = false;
+        if !sent {
+            // requeues the records whose delivery is unknown, the oldest are dropped if there is no room
+            connection = None;
+            queue.connected = false;
+            let lines = if dropped > 0 { &lines[1..] } else { &lines[..] };
+            queue.dropped += dropped;
+            for line in lines.iter().rev() {
+                if queue.lines.len() >= shared.capacity {
+                    queue.dropped += 1;
+                } else {
+                    queue.lines.push_front(line.clone());
+                }
+            }
+        }
+        shared.changed.notify_all();
+    }
+}
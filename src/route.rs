@@ -0,0 +1,131 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use flexi_logger::writers::LogWriter;
+use flexi_logger::{DeferredNow, FormatFunction};
+use log::LevelFilter;
+
+use crate::Stream;
+
+/// Output a `Route` directs records to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Destination {
+    /// The console, stderr or stdout as set by `LogConfig::output()`.
+    Stderr,
+    File,
+    Journald,
+    Syslog,
+    Gelf,
+    Otel,
+    Remote,
+    ShvJournal,
+    /// Sink added by `LogConfig::add_sink()`, numbered from 0 in the order of adding.
+    Sink(usize),
+}
+
+/// Routing rule of `LogConfig::route()`, e.g. `Route::to(&[Destination::File]).target("RpcData").only()`.
+///
+/// The rules are evaluated for the records passing the filter. A record matching `only()`
+/// rules goes just to their destinations. Other records go to the destinations named by
+/// the matching rules, and to those named by no rule except `only()` ones.
+#[derive(Clone, Debug)]
+pub struct Route {
+    destinations: Vec<Destination>,
+    level: LevelFilter,
+    target: Option<String>,
+    only: bool,
+}
+impl Route {
+    /// Directs the matching records to `destinations`.
+    pub fn to(destinations: &[Destination]) -> Route {
+        Route { destinations: destinations.to_vec(), level: LevelFilter::Trace, target: None, only: false }
+    }
+    /// Matches records at `level` or more severe.
+    pub fn level(mut self, level: log::Level) -> Self {
+        self.level = level.to_level_filter();
+        self
+    }
+    /// Matches records with target containing `target`.
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+    /// Keeps the matching records from the destinations not named by this rule.
+    pub fn only(mut self) -> Self {
+        self.only = true;
+        self
+    }
+    fn matches(&self, record: &log::Record) -> bool {
+        record.level() <= self.level && self.target.as_deref().is_none_or(|target| record.target().contains(target))
+    }
+}
+
+/// Whether `record` goes to `destination` according to `routes`.
+fn routed(routes: &[Route], destination: Destination, record: &log::Record) -> bool {
+    let mut exclusive = routes.iter().filter(|route| route.only && route.matches(record)).peekable();
+    if exclusive.peek().is_some() {
+        return exclusive.any(|route| route.destinations.contains(&destination));
+    }
+    let mut named = routes.iter().filter(|route| !route.only && route.destinations.contains(&destination)).peekable();
+    named.peek().is_none() || named.any(|route| route.matches(record))
+}
+
+/// Writer of a destination skipping the records routed elsewhere.
+struct RoutedWriter {
+    routes: Arc<Vec<Route>>,
+    destination: Destination,
+    writer: Box<dyn LogWriter>,
+}
+impl LogWriter for RoutedWriter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        if routed(&self.routes, self.destination, record) {
+            self.writer.write(now, record)?;
+        }
+        Ok(())
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+    fn max_log_level(&self) -> LevelFilter {
+        self.writer.max_log_level()
+    }
+    fn shutdown(&self) {
+        self.writer.shutdown();
+    }
+}
+
+/// Applies `routes`, if any, to the writer of `destination`.
+pub(crate) fn with_routes(routes: &Arc<Vec<Route>>, destination: Destination, writer: Box<dyn LogWriter>) -> Box<dyn LogWriter> {
+    if routes.is_empty() {
+        return writer;
+    }
+    Box::new(RoutedWriter { routes: routes.clone(), destination, writer })
+}
+
+/// Console output as one of the routed writers, in place of the flexi_logger console.
+pub(crate) struct ConsoleWriter {
+    format: FormatFunction,
+    stream: Stream,
+}
+impl ConsoleWriter {
+    pub(crate) fn new(format: FormatFunction, stream: Stream) -> ConsoleWriter {
+        ConsoleWriter { format, stream }
+    }
+}
+impl LogWriter for ConsoleWriter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        let mut line = Vec::new();
+        (self.format)(&mut line, now, record)?;
+        line.push(b'\n');
+        match self.stream {
+            Stream::Stderr => std::io::stderr().write_all(&line),
+            Stream::Stdout => std::io::stdout().write_all(&line),
+        }
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        match self.stream {
+            Stream::Stderr => std::io::stderr().flush(),
+            Stream::Stdout => std::io::stdout().flush(),
+        }
+    }
+}
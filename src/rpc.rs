@@ -0,0 +1,153 @@
+//! Verbosity control methods to be mounted into an application's SHV RPC tree.
+//!
+//! The module does not depend on any SHV RPC crate, results are returned as CPON
+//! text which the application converts to its `RpcValue` type.
+
+use crate::chainpack::cpon_string;
+use crate::{parse, GetLogParams, LogRecord, ParseError, ShvLogHandle, ShvSeverity, Subscription};
+
+/// Description of a node method.
+#[derive(Clone, Copy, Debug)]
+pub struct MethodDescriptor {
+    pub name: &'static str,
+    pub param: &'static str,
+    pub result: &'static str,
+    pub access: &'static str,
+}
+
+pub const METHODS: &[MethodDescriptor] = &[
+    MethodDescriptor { name: "verbosity", param: "Null", result: "String", access: "rd" },
+    MethodDescriptor { name: "setVerbosity", param: "String", result: "Bool", access: "cmd" },
+    MethodDescriptor { name: "logLevels", param: "Null", result: "Map", access: "rd" },
+    MethodDescriptor { name: "logDomains", param: "Null", result: "Map", access: "rd" },
+];
+
+/// Signal method of the streamed records, see `LogNode::stream_signals()`.
+pub const LOG_SIGNAL: &str = "lognotify";
+
+/// Signal carrying a log record, the application sends it with its RPC connection.
+#[derive(Clone, Debug)]
+pub struct LogSignal {
+    pub path: String,
+    pub method: &'static str,
+    /// The record as CPON map, see `LogRecord::to_cpon()`.
+    pub param: String,
+    /// Alarm severity of the record, see `LogConfig::severity()`.
+    pub severity: ShvSeverity,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RpcError {
+    MethodNotFound(String),
+    InvalidParam(String),
+}
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcError::MethodNotFound(method) => write!(f, "Method not found: {}", method),
+            RpcError::InvalidParam(msg) => write!(f, "Invalid param: {}", msg),
+        }
+    }
+}
+impl std::error::Error for RpcError {}
+
+/// Node exposing the logger verbosity of a running application.
+pub struct LogNode<'a> {
+    handle: &'a ShvLogHandle,
+}
+impl<'a> LogNode<'a> {
+    pub fn new(handle: &'a ShvLogHandle) -> LogNode<'a> {
+        LogNode { handle }
+    }
+    /// Active thresholds as `-d ... -v ... -g ...` string.
+    pub fn verbosity(&self) -> String {
+        self.handle.verbosity_string()
+    }
+    /// Replaces thresholds and the message filter from a `-d ... -v ... -g ...` string,
+    /// a string without `-d`/`-v` options is taken as module thresholds. The message
+    /// filter is cleared when there is no `-g` option.
+    pub fn set_verbosity(&self, verbosity: &str) -> Result<(), RpcError> {
+        let invalid_param = |err: ParseError| RpcError::InvalidParam(err.to_string());
+        let options = parse::parse_verbosity(verbosity).map_err(invalid_param)?;
+        self.handle.set_levels(&options.module_tresholds, &options.target_tresholds).map_err(invalid_param)?;
+        self.handle.set_message_filter(options.message_filter.as_deref());
+        Ok(())
+    }
+    /// Active thresholds as CPON map `{"module":{pattern:level,...},"target":{...}}`.
+    pub fn log_levels(&self) -> String {
+        let to_cpon = |levels: Vec<(String, log::LevelFilter)>| {
+            levels.iter()
+                .map(|(key, level)| format!("{}:{}", cpon_string(key), cpon_string(parse::level_abbreviation(*level))))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        format!("{{\"module\":{{{}}},\"target\":{{{}}}}}", to_cpon(self.handle.module_levels()), to_cpon(self.handle.target_levels()))
+    }
+    /// Seen targets and modules as CPON map `{"target":[...],"module":[...]}`, see
+    /// `ShvLogHandle::seen_targets()`.
+    pub fn log_domains(&self) -> String {
+        let to_cpon = |names: Vec<String>| names.iter().map(|name| cpon_string(name)).collect::<Vec<_>>().join(",");
+        format!("{{\"target\":[{}],\"module\":[{}]}}", to_cpon(self.handle.seen_targets()), to_cpon(self.handle.seen_modules()))
+    }
+    /// Records of the memory buffer selected by `params` as CPON list of the
+    /// `LogRecord::to_cpon()` maps, the result of a `getLog` method.
+    pub fn get_log(&self, params: GetLogParams) -> String {
+        LogRecord::list_to_cpon(&self.handle.get_log(params))
+    }
+    /// Calls `send` with a `lognotify` signal on `path` for every record passing the filtering
+    /// at or above `level`, e.g. to stream Warn and Error records to a monitoring client while
+    /// logging Debug locally. The stream stops with `ShvLogHandle::unsubscribe()`.
+    pub fn stream_signals(&self, path: &str, level: log::Level, send: impl Fn(LogSignal) + Send + Sync + 'static) -> Subscription {
+        let path = path.to_string();
+        self.handle.subscribe(move |record| {
+            if record.level <= level {
+                send(LogSignal { path: path.clone(), method: LOG_SIGNAL, param: record.to_cpon(), severity: ShvSeverity::of(record.level) });
+            }
+        })
+    }
+    /// Calls `send` like `stream_signals()` for the records passing the thresholds of
+    /// `verbosity` instead of the active ones, see `ShvLogHandle::subscribe_with_verbosity()`,
+    /// e.g. for a client asking for `-d shvbroker::rpc:D`.
+    pub fn stream_signals_with_verbosity(&self, path: &str, verbosity: &str, send: impl Fn(LogSignal) + Send + Sync + 'static) -> Result<Subscription, RpcError> {
+        let path = path.to_string();
+        self.handle
+            .subscribe_with_verbosity(verbosity, move |record| {
+                send(LogSignal { path: path.clone(), method: LOG_SIGNAL, param: record.to_cpon(), severity: ShvSeverity::of(record.level) });
+            })
+            .map_err(|err| RpcError::InvalidParam(err.to_string()))
+    }
+    /// Dispatches an RPC call, `param` and the result are CPON.
+    pub fn call(&self, method: &str, param: Option<&str>) -> Result<String, RpcError> {
+        match method {
+            "verbosity" => Ok(cpon_string(&self.verbosity())),
+            "setVerbosity" => {
+                let param = param.and_then(parse_cpon_string)
+                    .ok_or_else(|| RpcError::InvalidParam("String expected".into()))?;
+                self.set_verbosity(&param)?;
+                Ok("true".into())
+            }
+            "logLevels" => Ok(self.log_levels()),
+            "logDomains" => Ok(self.log_domains()),
+            _ => Err(RpcError::MethodNotFound(method.into())),
+        }
+    }
+}
+
+fn parse_cpon_string(cpon: &str) -> Option<String> {
+    let inner = cpon.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut ret = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next()? {
+                'n' => ret.push('\n'),
+                'r' => ret.push('\r'),
+                't' => ret.push('\t'),
+                c => ret.push(c),
+            }
+        } else {
+            ret.push(c);
+        }
+    }
+    Some(ret)
+}
@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use log::LevelFilter;
+
+use crate::PatternKind;
+
+/// Number of records whose threshold a rule decided, shared with the threshold cache.
+pub(crate) type Hits = Arc<AtomicU64>;
+
+/// Kind of rule of `ShvLogHandle::rules()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleSource {
+    /// `-d` module rule.
+    Module,
+    /// `-v` target rule.
+    Target,
+    /// `module@target` rule.
+    ModuleTarget,
+    /// `key=value` or `key~value` field rule.
+    Field,
+    /// Threshold of the records matched by no rule.
+    Default,
+}
+
+/// Active threshold rule with the number of records it decided, see `ShvLogHandle::rules()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleInfo {
+    pub source: RuleSource,
+    /// Pattern as given, empty for the default threshold.
+    pub pattern: String,
+    pub kind: PatternKind,
+    /// How a plain pattern matches, substring for targets and fields.
+    pub match_mode: MatchMode,
+    pub level: LevelFilter,
+    /// Records whose threshold the rule decided since it was added, whether they passed or not.
+    pub hits: u64,
+}
+impl RuleInfo {
+    pub(crate) fn new(source: RuleSource, pattern: &str, match_mode: MatchMode, level: LevelFilter, hits: &AtomicU64) -> RuleInfo {
+        RuleInfo { source, pattern: pattern.into(), kind: PatternKind::of(pattern), match_mode, level, hits: hits.load(Ordering::Relaxed) }
+    }
+}
+
+/// How module rules are matched against the module path of a record.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    /// The pattern is a substring of the module path, so `rpc` matches `shvapp::rpc` and `mysrpca`.
+    #[default]
+    Substring,
+    /// The pattern is the module path or its `::` delimited prefix, `shvapp::rpc` matches
+    /// `shvapp::rpc::client` but not `shvapp::rpcx`. An empty pattern matches every module.
+    ModulePath,
+}
+impl MatchMode {
+    fn matches(self, pattern: &str, name: &str) -> bool {
+        match self {
+            MatchMode::Substring => name.contains(pattern),
+            MatchMode::ModulePath => match name.strip_prefix(pattern) {
+                Some(rest) => pattern.is_empty() || rest.is_empty() || rest.starts_with("::"),
+                None => false,
+            },
+        }
+    }
+}
+
+/// Compiled pattern of a rule.
+enum Matcher {
+    Plain,
+    /// Literal parts between the `*` wildcards.
+    Glob(Vec<String>),
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+impl Matcher {
+    fn new(pattern: &str) -> Matcher {
+        #[cfg(feature = "regex")]
+        if let Some(Ok(regex)) = regex_source(pattern).map(regex::Regex::new) {
+            return Matcher::Regex(regex);
+        }
+        if pattern.contains('*') {
+            Matcher::Glob(pattern.split('*').map(String::from).collect())
+        } else {
+            Matcher::Plain
+        }
+    }
+    fn matches(&self, pattern: &str, name: &str, mode: MatchMode) -> bool {
+        match self {
+            Matcher::Plain => mode.matches(pattern, name),
+            Matcher::Glob(parts) => match mode {
+                MatchMode::Substring => glob_matches(parts, name),
+                // the glob may match the whole path or any of its `::` delimited prefixes
+                MatchMode::ModulePath => glob_matches(parts, name)
+                    || name.match_indices("::").any(|(ix, _)| glob_matches(parts, &name[..ix])),
+            },
+            #[cfg(feature = "regex")]
+            Matcher::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// Regular expression of a `/regex/` pattern.
+pub(crate) fn regex_source(pattern: &str) -> Option<&str> {
+    pattern.strip_prefix('/')?.strip_suffix('/')
+}
+
+/// Whole `name` matches the glob split at its wildcards.
+pub(crate) fn glob_matches(parts: &[String], name: &str) -> bool {
+    let (first, rest) = match parts.split_first() {
+        Some(split) => split,
+        None => return name.is_empty(),
+    };
+    let Some(mut name) = name.strip_prefix(first.as_str()) else {
+        return false;
+    };
+    let Some((last, middle)) = rest.split_last() else {
+        return name.is_empty();
+    };
+    for part in middle {
+        match name.find(part.as_str()) {
+            Some(ix) => name = &name[ix + part.len()..],
+            None => return false,
+        }
+    }
+    name.ends_with(last.as_str())
+}
+
+struct Rule {
+    pattern: String,
+    matcher: Matcher,
+    level: LevelFilter,
+    hits: Hits,
+}
+
+/// Module or target threshold rules, kept ordered from the most specific one.
+///
+/// Patterns containing `*` are globs matching the whole name, `*` stands for any
+/// sequence of characters. With the `regex` feature, `/regex/` patterns are regular
+/// expressions searched in the name. The longest matching pattern wins, patterns of equal
+/// length are tried in alphabetical order.
+#[derive(Default)]
+pub(crate) struct Rules(Vec<Rule>);
+impl Rules {
+    pub(crate) fn new(levels: HashMap<String, LevelFilter>) -> Rules {
+        let mut rules = Rules::default();
+        for (pattern, level) in levels {
+            rules.insert(&pattern, level);
+        }
+        rules
+    }
+    fn order(a: &str, b: &str) -> std::cmp::Ordering {
+        b.len().cmp(&a.len()).then_with(|| a.cmp(b))
+    }
+    /// Adds the rule, replacing the level of an existing rule with the same pattern.
+    pub(crate) fn insert(&mut self, pattern: &str, level: LevelFilter) {
+        match self.0.binary_search_by(|rule| Rules::order(&rule.pattern, pattern)) {
+            Ok(ix) => self.0[ix].level = level,
+            Err(ix) => self.0.insert(ix, Rule { pattern: pattern.into(), matcher: Matcher::new(pattern), level, hits: Hits::default() }),
+        }
+    }
+    pub(crate) fn get(&self, pattern: &str) -> Option<LevelFilter> {
+        self.0.iter().find(|rule| rule.pattern == pattern).map(|rule| rule.level)
+    }
+    pub(crate) fn remove(&mut self, pattern: &str) {
+        if let Ok(ix) = self.0.binary_search_by(|rule| Rules::order(&rule.pattern, pattern)) {
+            self.0.remove(ix);
+        }
+    }
+    /// The most specific rule matching `name` with its hit counter.
+    pub(crate) fn find(&self, name: &str, mode: MatchMode) -> Option<(&str, LevelFilter, &Hits)> {
+        self.0.iter()
+            .find(|rule| rule.matcher.matches(&rule.pattern, name, mode))
+            .map(|rule| (rule.pattern.as_str(), rule.level, &rule.hits))
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, LevelFilter)> {
+        self.0.iter().map(|rule| (rule.pattern.as_str(), rule.level))
+    }
+    pub(crate) fn infos(&self, source: RuleSource, mode: MatchMode) -> impl Iterator<Item = RuleInfo> + '_ {
+        self.0.iter().map(move |rule| RuleInfo::new(source, &rule.pattern, mode, rule.level, &rule.hits))
+    }
+}
+
+/// `module@target` rules matching both the module path and the target of a record,
+/// ordered like `Rules`.
+#[derive(Default)]
+pub(crate) struct CompoundRules(Vec<CompoundRule>);
+struct CompoundRule {
+    key: String,
+    module: (String, Matcher),
+    target: (String, Matcher),
+    level: LevelFilter,
+    hits: Hits,
+}
+impl CompoundRules {
+    pub(crate) fn split(key: &str) -> Option<(&str, &str)> {
+        key.split_once('@')
+    }
+    /// Moves the compound rules out of parsed `levels`.
+    pub(crate) fn extract(&mut self, levels: &mut HashMap<String, LevelFilter>) {
+        let keys: Vec<String> = levels.keys().filter(|key| CompoundRules::split(key).is_some()).cloned().collect();
+        for key in keys {
+            if let Some(level) = levels.remove(&key) {
+                self.insert(&key, level);
+            }
+        }
+    }
+    /// Adds the rule like `Rules::insert()`, keys without `@` are ignored.
+    pub(crate) fn insert(&mut self, key: &str, level: LevelFilter) {
+        let Some((module, target)) = CompoundRules::split(key) else {
+            return;
+        };
+        let rule = CompoundRule {
+            key: key.into(),
+            module: (module.into(), Matcher::new(module)),
+            target: (target.into(), Matcher::new(target)),
+            level,
+            hits: Hits::default(),
+        };
+        match self.0.binary_search_by(|rule| Rules::order(&rule.key, key)) {
+            Ok(ix) => self.0[ix].level = level,
+            Err(ix) => self.0.insert(ix, rule),
+        }
+    }
+    pub(crate) fn get(&self, key: &str) -> Option<LevelFilter> {
+        self.0.iter().find(|rule| rule.key == key).map(|rule| rule.level)
+    }
+    pub(crate) fn remove(&mut self, key: &str) {
+        self.0.retain(|rule| rule.key != key);
+    }
+    /// The most specific rule matching both `module` and `target` with its hit counter.
+    pub(crate) fn find(&self, module: &str, target: &str, mode: MatchMode) -> Option<(&str, LevelFilter, &Hits)> {
+        self.0.iter()
+            .find(|rule| {
+                rule.module.1.matches(&rule.module.0, module, mode) && rule.target.1.matches(&rule.target.0, target, MatchMode::Substring)
+            })
+            .map(|rule| (rule.key.as_str(), rule.level, &rule.hits))
+    }
+    /// The most verbose level of the rules matching `target` with any module.
+    pub(crate) fn max_for_target(&self, target: &str) -> Option<LevelFilter> {
+        self.0.iter()
+            .filter(|rule| rule.target.1.matches(&rule.target.0, target, MatchMode::Substring))
+            .map(|rule| rule.level)
+            .max()
+    }
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, LevelFilter)> {
+        self.0.iter().map(|rule| (rule.key.as_str(), rule.level))
+    }
+    pub(crate) fn infos(&self, mode: MatchMode) -> impl Iterator<Item = RuleInfo> + '_ {
+        self.0.iter().map(move |rule| RuleInfo::new(RuleSource::ModuleTarget, &rule.key, mode, rule.level, &rule.hits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flexi_logger::filter::{LogLineFilter, LogLineWriter};
+    use flexi_logger::DeferredNow;
+
+    use super::*;
+    use crate::LogConfig;
+
+    struct Discard;
+    impl LogLineWriter for Discard {
+        fn write(&self, _now: &mut DeferredNow, _record: &log::Record) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Pattern of the rule deciding `name`.
+    fn matching(patterns: &[&str], name: &str, mode: MatchMode) -> Option<String> {
+        let rules = Rules::new(patterns.iter().map(|pattern| (pattern.to_string(), LevelFilter::Debug)).collect());
+        rules.find(name, mode).map(|(pattern, _, _)| pattern.to_string())
+    }
+
+    #[test]
+    fn longest_pattern_wins() {
+        assert_eq!(matching(&["rpc", "shvapp::rpc", ""], "shvapp::rpc::client", MatchMode::Substring).as_deref(), Some("shvapp::rpc"));
+        assert_eq!(matching(&["rpc", "shvapp::rpc", ""], "broker", MatchMode::Substring).as_deref(), Some(""));
+        // equal lengths are tried alphabetically
+        assert_eq!(matching(&["ba", "ab"], "abba", MatchMode::Substring).as_deref(), Some("ab"));
+    }
+
+    #[test]
+    fn module_path_mode() {
+        assert_eq!(matching(&["shvapp::rpc"], "shvapp::rpc::client", MatchMode::ModulePath).as_deref(), Some("shvapp::rpc"));
+        assert_eq!(matching(&["shvapp::rpc"], "shvapp::rpc", MatchMode::ModulePath).as_deref(), Some("shvapp::rpc"));
+        assert_eq!(matching(&["shvapp::rpc"], "shvapp::rpcx", MatchMode::ModulePath), None);
+        assert_eq!(matching(&["rpc"], "shvapp::rpc", MatchMode::ModulePath), None);
+        assert_eq!(matching(&["rpc"], "shvapp::rpc", MatchMode::Substring).as_deref(), Some("rpc"));
+        assert_eq!(matching(&[""], "shvapp", MatchMode::ModulePath).as_deref(), Some(""));
+    }
+
+    #[test]
+    fn globs() {
+        let globs = |parts: &str, name: &str| glob_matches(&parts.split('*').map(String::from).collect::<Vec<_>>(), name);
+        assert!(globs("shv*::client", "shvapp::client"));
+        assert!(globs("*rpc*", "rpc"));
+        assert!(globs("a*b*c", "aXbYbZc"));
+        assert!(!globs("shv*::client", "shvapp::client::x"));
+        assert!(!globs("a*a", "a"));
+        // a glob matches the whole name, in the module path mode also a `::` prefix of it
+        assert_eq!(matching(&["shv*::client"], "shvapp::client::x", MatchMode::Substring), None);
+        assert_eq!(matching(&["shv*::client"], "shvapp::client::x", MatchMode::ModulePath).as_deref(), Some("shv*::client"));
+        assert_eq!(matching(&["*::client", "shvapp::*x"], "shvapp::client", MatchMode::Substring).as_deref(), Some("*::client"));
+        assert_eq!(matching(&["*::client", "shvapp::*t"], "shvapp::client", MatchMode::Substring).as_deref(), Some("shvapp::*t"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regexes() {
+        assert_eq!(matching(&["/^shv.*rpc$/"], "shvapp::rpc", MatchMode::Substring).as_deref(), Some("/^shv.*rpc$/"));
+        assert_eq!(matching(&["/^shv.*rpc$/"], "shvapp::rpc::client", MatchMode::ModulePath), None);
+        // searched in the name unless anchored
+        assert_eq!(matching(&["/rpc|broker/"], "shvapp::broker::x", MatchMode::Substring).as_deref(), Some("/rpc|broker/"));
+        assert_eq!(PatternKind::of("/rpc/"), PatternKind::Regex);
+    }
+
+    #[test]
+    fn rule_hits() {
+        let config = LogConfig::new(&["rpc:D".into(), "peer=7:T".into()], &["Access:W".into()]);
+        let kvs = [("peer", "7")];
+        let records = [
+            log::Record::builder().level(log::Level::Debug).module_path(Some("app::rpc")).target("app::rpc").build(),
+            log::Record::builder().level(log::Level::Trace).module_path(Some("app::rpc")).target("app::rpc").build(),
+            log::Record::builder().level(log::Level::Info).module_path(Some("app::rpc")).target("Access").build(),
+            log::Record::builder().level(log::Level::Info).module_path(Some("app")).target("app").build(),
+            log::Record::builder().level(log::Level::Trace).module_path(Some("app")).target("app").key_values(&kvs).build(),
+        ];
+        for record in &records {
+            config.write(&mut DeferredNow::new(), record, &Discard).unwrap();
+        }
+        let hits: Vec<_> = config.rule_infos().into_iter().map(|rule| (rule.source, rule.pattern, rule.kind, rule.hits)).collect();
+        assert_eq!(hits, [
+            (RuleSource::Module, "rpc".to_string(), PatternKind::Plain, 2),
+            (RuleSource::Target, "Access".to_string(), PatternKind::Plain, 1),
+            (RuleSource::Field, "peer=7".to_string(), PatternKind::Field, 1),
+            (RuleSource::Default, "".to_string(), PatternKind::Plain, 1),
+        ]);
+    }
+}
@@ -0,0 +1,53 @@
+/// Keeps a deterministic fraction of the records of a target or module.
+pub(crate) struct Sample {
+    pattern: String,
+    ratio: f64,
+}
+impl Sample {
+    pub(crate) fn new(pattern: &str, ratio: f64) -> Sample {
+        Sample { pattern: pattern.into(), ratio: ratio.clamp(0.0, 1.0) }
+    }
+    pub(crate) fn matches(&self, record: &log::Record) -> bool {
+        record.target().contains(&self.pattern) || record.module_path().unwrap_or("").contains(&self.pattern)
+    }
+    /// Returns whether `record` is kept, decided by hash of its target, module, line and message,
+    /// so that repeated runs keep the same records.
+    pub(crate) fn admit(&self, record: &log::Record) -> bool {
+        let mut hash = Fnv1a::default();
+        hash.write(record.target().as_bytes());
+        hash.write(record.module_path().unwrap_or("").as_bytes());
+        hash.write(&record.line().unwrap_or(0).to_le_bytes());
+        match record.args().as_str() {
+            Some(message) => hash.write(message.as_bytes()),
+            None => hash.write(record.args().to_string().as_bytes()),
+        }
+        (hash.finish() as f64 / u64::MAX as f64) < self.ratio
+    }
+}
+
+/// FNV-1a, stable across Rust versions unlike `DefaultHasher`.
+struct Fnv1a(u64);
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+        // separates the fields
+        self.0 = (self.0 ^ 0xff).wrapping_mul(0x0100_0000_01b3);
+    }
+    /// Hash with the MurmurHash3 finalizer, FNV alone leaves the high bits poorly mixed
+    /// for messages differing only in the last characters.
+    fn finish(&self) -> u64 {
+        let mut hash = self.0;
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        hash ^ (hash >> 33)
+    }
+}
@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+use std::marker::PhantomData;
+
+thread_local! {
+    static SCOPES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Guard of a logging scope created by `scope()`, messages logged from the same
+/// thread while it lives are prefixed with the scope name.
+#[must_use = "the scope ends when the guard is dropped"]
+pub struct LogScope {
+    /// Scopes outside of this one, dropping the guard ends the inner ones too.
+    depth: usize,
+    // the scope lives in a thread-local stack, the guard must stay on its thread
+    _not_send: PhantomData<*const ()>,
+}
+impl Drop for LogScope {
+    fn drop(&mut self) {
+        SCOPES.with(|scopes| scopes.borrow_mut().truncate(self.depth));
+    }
+}
+
+/// Prefixes messages logged from the current thread with `name` until the returned guard
+/// is dropped, e.g. `let _scope = shvlog::scope("mount:/test/device");`.
+/// Nested scopes are prefixed outermost first.
+pub fn scope(name: impl Into<String>) -> LogScope {
+    let depth = SCOPES.with(|scopes| {
+        let mut scopes = scopes.borrow_mut();
+        scopes.push(name.into());
+        scopes.len() - 1
+    });
+    LogScope { depth, _not_send: PhantomData }
+}
+
+/// Scope names of the current thread joined as message prefix, e.g. `"mount:/x: get: "`.
+pub(crate) fn prefix() -> String {
+    SCOPES.with(|scopes| scopes.borrow().iter().map(|name| format!("{}: ", name)).collect())
+}
+
+/// Writes `prefix()` without collecting it.
+pub(crate) fn write_prefix(w: &mut dyn std::io::Write) -> std::io::Result<()> {
+    SCOPES.with(|scopes| scopes.borrow().iter().try_for_each(|name| write!(w, "{}: ", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guards_dropped_out_of_order() {
+        let outer = scope("outer");
+        let inner = scope("inner");
+        assert_eq!(prefix(), "outer: inner: ");
+        drop(outer);
+        assert_eq!(prefix(), "");
+        let other = scope("other");
+        drop(inner);
+        assert_eq!(prefix(), "other: ");
+        drop(other);
+        assert_eq!(prefix(), "");
+    }
+}
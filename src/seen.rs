@@ -0,0 +1,51 @@
+use std::collections::BTreeSet;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+
+/// Bound of the seen targets and modules each, so that generated targets cannot grow the sets
+/// without limit.
+const MAX_SEEN: usize = 1000;
+
+#[derive(Default)]
+struct Seen {
+    targets: BTreeSet<String>,
+    modules: BTreeSet<String>,
+}
+
+lazy_static! {
+    static ref SEEN: RwLock<Seen> = RwLock::new(Seen::default());
+}
+
+/// Notes the target and module of a record reaching the filter.
+pub(crate) fn note(record: &log::Record) {
+    let module = record.module_path().unwrap_or("");
+    {
+        let seen = SEEN.read().unwrap();
+        if seen.targets.contains(record.target()) && (module.is_empty() || seen.modules.contains(module)) {
+            return;
+        }
+    }
+    let mut seen = SEEN.write().unwrap();
+    if seen.targets.len() < MAX_SEEN && !seen.targets.contains(record.target()) {
+        seen.targets.insert(record.target().into());
+    }
+    if !module.is_empty() && seen.modules.len() < MAX_SEEN && !seen.modules.contains(module) {
+        seen.modules.insert(module.into());
+    }
+}
+
+/// Adds targets to `ShvLogHandle::seen_targets()` before their first record is logged, e.g.
+/// the targets of rarely used subsystems, so that they can be listed as `-v` options.
+pub fn register_targets(targets: &[&str]) {
+    let mut seen = SEEN.write().unwrap();
+    seen.targets.extend(targets.iter().map(|target| target.to_string()));
+}
+
+pub(crate) fn targets() -> Vec<String> {
+    SEEN.read().unwrap().targets.iter().cloned().collect()
+}
+
+pub(crate) fn modules() -> Vec<String> {
+    SEEN.read().unwrap().modules.iter().cloned().collect()
+}
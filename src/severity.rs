@@ -0,0 +1,60 @@
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+
+/// Severity of an SHV alarm, the common scale that record levels are mapped to for the RPC
+/// streaming, syslog, journald, GELF and the sd-daemon priority prefix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShvSeverity {
+    Error,
+    Warning,
+    Info,
+    Debug,
+}
+impl ShvSeverity {
+    /// Severity of `level` with the overrides of `LogConfig::severity()` of the active config.
+    pub fn of(level: log::Level) -> ShvSeverity {
+        SEVERITIES.read().unwrap()[level as usize - 1]
+    }
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ShvSeverity::Error => "error",
+            ShvSeverity::Warning => "warning",
+            ShvSeverity::Info => "info",
+            ShvSeverity::Debug => "debug",
+        }
+    }
+    /// RFC 5424 severity, also the journald `PRIORITY`.
+    pub fn syslog_severity(self) -> u8 {
+        match self {
+            ShvSeverity::Error => 3,
+            ShvSeverity::Warning => 4,
+            ShvSeverity::Info => 6,
+            ShvSeverity::Debug => 7,
+        }
+    }
+}
+/// Default mapping, Trace is mapped to Debug.
+impl From<log::Level> for ShvSeverity {
+    fn from(level: log::Level) -> ShvSeverity {
+        match level {
+            log::Level::Error => ShvSeverity::Error,
+            log::Level::Warn => ShvSeverity::Warning,
+            log::Level::Info => ShvSeverity::Info,
+            log::Level::Debug | log::Level::Trace => ShvSeverity::Debug,
+        }
+    }
+}
+
+/// Default severities indexed by level, Error first.
+pub(crate) fn default_severities() -> [ShvSeverity; 5] {
+    [log::Level::Error, log::Level::Warn, log::Level::Info, log::Level::Debug, log::Level::Trace].map(ShvSeverity::from)
+}
+
+lazy_static! {
+    static ref SEVERITIES: RwLock<[ShvSeverity; 5]> = RwLock::new(default_severities());
+}
+
+pub(crate) fn set_severities(severities: [ShvSeverity; 5]) {
+    *SEVERITIES.write().unwrap() = severities;
+}
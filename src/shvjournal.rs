@@ -0,0 +1,128 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use flexi_logger::writers::LogWriter;
+use flexi_logger::DeferredNow;
+
+use crate::LogRecord;
+
+const FILE_EXTENSION: &str = ".log2";
+/// Domain column of the records, the signal name of `rpc::LOG_SIGNAL`.
+const DOMAIN: &str = "lognotify";
+/// Value flags column, the records are spontaneous changes.
+const SPONTANEOUS: u32 = 2;
+
+/// Journal of the records in the shvjournal line format read by SHV `getLog`, see
+/// `LogConfig::with_shvjournal()`.
+///
+/// Each line has tab separated timestamp, up-time, path, value, short time, domain, value
+/// flags and user ID columns. The path is the target with `/` separators, the value is the
+/// CPON map of `LogRecord::to_cpon()`. Files are named by the UTC timestamp of their first
+/// record, e.g. `2024-03-01T12-00-00-000.log2`.
+#[derive(Clone, Debug)]
+pub struct ShvJournal {
+    dir: PathBuf,
+    file_size: u64,
+    journal_size: u64,
+    pub(crate) level: Option<log::Level>,
+}
+impl ShvJournal {
+    pub fn new(dir: impl Into<PathBuf>) -> ShvJournal {
+        ShvJournal { dir: dir.into(), file_size: 100 * 1024, journal_size: 100 * 1024 * 1024, level: None }
+    }
+    /// Skips records less severe than `level`, see `Syslog::level()`.
+    pub fn level(mut self, level: log::Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+    /// Starts a new file when the current one grows over `bytes`, 100 kB by default.
+    pub fn file_size(mut self, bytes: u64) -> Self {
+        self.file_size = bytes;
+        self
+    }
+    /// Deletes the oldest files when all of them take more than `bytes`, 100 MB by default.
+    pub fn journal_size(mut self, bytes: u64) -> Self {
+        self.journal_size = bytes;
+        self
+    }
+    pub(crate) fn writer(&self) -> std::io::Result<ShvJournalWriter> {
+        fs::create_dir_all(&self.dir)?;
+        Ok(ShvJournalWriter { journal: self.clone(), started: Instant::now(), file: Mutex::new(None) })
+    }
+}
+
+struct JournalFile {
+    file: File,
+    size: u64,
+}
+
+pub(crate) struct ShvJournalWriter {
+    journal: ShvJournal,
+    started: Instant,
+    file: Mutex<Option<JournalFile>>,
+}
+impl ShvJournalWriter {
+    fn open(&self, timestamp: &DateTime<Utc>) -> std::io::Result<JournalFile> {
+        let name = format!("{}{}", timestamp.format("%Y-%m-%dT%H-%M-%S-%3f"), FILE_EXTENSION);
+        let file = OpenOptions::new().create(true).append(true).open(self.journal.dir.join(&name))?;
+        let size = file.metadata()?.len();
+        remove_oldest(&self.journal.dir, &name, self.journal.journal_size)?;
+        Ok(JournalFile { file, size })
+    }
+}
+impl LogWriter for ShvJournalWriter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        let record = LogRecord::from_record(now, record);
+        let timestamp = record.timestamp.with_timezone(&Utc);
+        let line = format!(
+            "{}\t{}\t{}\t{}\t\t{}\t{}\t\n",
+            timestamp.format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+            self.started.elapsed().as_secs(),
+            record.target.replace("::", "/"),
+            record.to_cpon(),
+            DOMAIN,
+            SPONTANEOUS,
+        );
+        let mut current = self.file.lock().unwrap();
+        if current.as_ref().is_none_or(|current| current.size >= self.journal.file_size) {
+            *current = Some(self.open(&timestamp)?);
+        }
+        let current = current.as_mut().unwrap();
+        current.file.write_all(line.as_bytes())?;
+        current.size += line.len() as u64;
+        Ok(())
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        match &mut *self.file.lock().unwrap() {
+            Some(current) => current.file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Deletes the oldest journal files except `current` while the journal is larger than `limit`.
+fn remove_oldest(dir: &Path, current: &str, limit: u64) -> std::io::Result<()> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.ends_with(FILE_EXTENSION) && entry.file_type()?.is_file() {
+            files.push((name, entry.metadata()?.len()));
+        }
+    }
+    // the names sort by the timestamp
+    files.sort();
+    let mut total: u64 = files.iter().map(|(_, size)| size).sum();
+    for (name, size) in files {
+        if total <= limit || name == current {
+            break;
+        }
+        fs::remove_file(dir.join(&name))?;
+        total -= size;
+    }
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+use std::sync::{RwLock, Weak};
+use std::thread;
+
+use log::LevelFilter;
+use signal_hook::consts::{SIGHUP, SIGUSR1};
+use signal_hook::iterator::Signals;
+
+use crate::LogConfig;
+
+/// Next threshold of the SIGUSR1 cycle.
+fn raised(level: LevelFilter) -> LevelFilter {
+    match level {
+        LevelFilter::Off => LevelFilter::Error,
+        LevelFilter::Error => LevelFilter::Warn,
+        LevelFilter::Warn => LevelFilter::Info,
+        LevelFilter::Info => LevelFilter::Debug,
+        LevelFilter::Debug => LevelFilter::Trace,
+        LevelFilter::Trace => LevelFilter::Info,
+    }
+}
+
+fn raise_default_level(active: &RwLock<LogConfig>) {
+    let mut config = active.write().unwrap();
+    let level = raised(config.module_levels.get("").unwrap_or(config.default_level));
+    if config.module_levels.get("").is_some() {
+        config.module_levels.insert("", level);
+    }
+    config.default_level = level;
+    config.update_max_level();
+    drop(config);
+    log::warn!("Default log level set to {}", level);
+}
+
+/// Spawns the signal handling thread, it ends once the active config is dropped.
+pub(crate) fn spawn_handler(active: Weak<RwLock<LogConfig>>, config_file: Option<PathBuf>) -> std::io::Result<()> {
+    #[cfg(not(feature = "toml"))]
+    if config_file.is_some() {
+        return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "reloading the config file requires the toml feature"));
+    }
+    let mut signals = Signals::new([SIGUSR1, SIGHUP])?;
+    thread::Builder::new()
+        .name("shvlog-signals".into())
+        .spawn(move || {
+            for signal in signals.forever() {
+                let Some(active) = active.upgrade() else {
+                    break;
+                };
+                match signal {
+                    SIGUSR1 => raise_default_level(&active),
+                    SIGHUP => {
+                        if let Err(err) = crate::file::reopen() {
+                            log::error!("Cannot reopen the log file: {}", err);
+                        }
+                        #[cfg(feature = "toml")]
+                        if let Some(path) = &config_file {
+                            crate::reload::reload_config(&active, path);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        })?;
+    Ok(())
+}
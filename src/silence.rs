@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Rule reporting that no records (at all, or from one target) were produced for a period.
+pub(crate) struct SilenceRule {
+    target: Option<String>,
+    period: Duration,
+    last_seen: Mutex<Instant>,
+    reported: AtomicBool,
+}
+impl SilenceRule {
+    pub(crate) fn new(target: Option<&str>, period: Duration) -> SilenceRule {
+        SilenceRule {
+            target: target.map(|t| t.into()),
+            period,
+            last_seen: Mutex::new(Instant::now()),
+            reported: AtomicBool::new(false),
+        }
+    }
+    pub(crate) fn record_seen(&self, record: &log::Record) {
+        // records emitted by the detector itself must not break the silence
+        if record.module_path() == Some(module_path!()) {
+            return;
+        }
+        if let Some(target) = &self.target {
+            if !record.target().contains(target.as_str()) {
+                return;
+            }
+        }
+        *self.last_seen.lock().unwrap() = Instant::now();
+        self.reported.store(false, Ordering::Relaxed);
+    }
+    fn check(&self, callback: Option<fn(Option<&str>, Duration)>) {
+        let silent_for = self.last_seen.lock().unwrap().elapsed();
+        if silent_for < self.period || self.reported.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        match callback {
+            Some(callback) => callback(self.target.as_deref(), silent_for),
+            None => match &self.target {
+                Some(target) => log::warn!("No log records from target '{}' for {:.1}s", target, silent_for.as_secs_f64()),
+                None => log::warn!("No log records for {:.1}s", silent_for.as_secs_f64()),
+            },
+        }
+    }
+}
+
+/// Spawns the detector thread, it ends once the rules are dropped with their config.
+pub(crate) fn spawn_detector(rules: Vec<Weak<SilenceRule>>, callback: Option<fn(Option<&str>, Duration)>) -> std::io::Result<()> {
+    let Some(min_period) = rules.iter().filter_map(|rule| rule.upgrade()).map(|rule| rule.period).min() else {
+        return Ok(());
+    };
+    let interval = (min_period / 4).clamp(Duration::from_millis(10), Duration::from_secs(1));
+    thread::Builder::new()
+        .name("shvlog-silence".into())
+        .spawn(move || loop {
+            thread::sleep(interval);
+            let live_rules: Vec<_> = rules.iter().filter_map(|rule| rule.upgrade()).collect();
+            if live_rules.is_empty() {
+                break;
+            }
+            for rule in live_rules {
+                rule.check(callback);
+            }
+        })?;
+    Ok(())
+}
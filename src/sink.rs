@@ -0,0 +1,370 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use flexi_logger::writers::LogWriter;
+use flexi_logger::DeferredNow;
+use lazy_static::lazy_static;
+use log::kv::{Error, Key, Value, VisitSource};
+
+use crate::sample::Sample;
+use crate::timezone::{self, Tz};
+
+/// Custom output of the filtered and formatted records, see `LogConfig::add_sink()`.
+///
+/// Sinks are called by the logging thread, unless `queue_capacity()` gives a slow backend
+/// its own thread.
+pub trait LogSink: Send + Sync {
+    fn write(&self, record: &FormattedRecord) -> std::io::Result<()>;
+    fn flush(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+    /// Most verbose level passed to the sink, after the module and target thresholds.
+    fn max_level(&self) -> log::LevelFilter {
+        log::LevelFilter::Trace
+    }
+    /// Fraction of the records of `level` passed to the sink, e.g. 0.05 of Debug for an
+    /// expensive collector, selected by hash like `LogConfig::sample()`.
+    fn sample_ratio(&self, _level: log::Level) -> f64 {
+        1.0
+    }
+    /// Timezone of the timestamps in `FormattedRecord::text`, e.g. UTC for a network sink
+    /// while the console shows the local time. None uses `LogConfig::timezone()`.
+    fn timezone(&self) -> Option<Tz> {
+        None
+    }
+    /// Terminator appended to `FormattedRecord::text`, e.g. `"\r\n"` for logs read on Windows,
+    /// which then also ends the lines of multi-line records. None by default, the sink separates
+    /// the records itself.
+    fn line_ending(&self) -> &str {
+        ""
+    }
+    /// Capacity of a queue of the records written to the sink by its own "shvlog-sink"
+    /// thread, so that a slow sink, e.g. remote syslog over a flaky link, does not hold up the
+    /// other outputs. Records are dropped when the queue is full, see
+    /// `ShvLogHandle::sink_stats()`. None writes from the logging thread.
+    fn queue_capacity(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Metrics of a sink in the order of `LogConfig::add_sink()`, see `ShvLogHandle::sink_stats()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SinkStats {
+    pub written: u64,
+    /// Records dropped because the queue was full.
+    pub dropped: u64,
+    /// Records waiting in the queue.
+    pub queued: usize,
+    /// Time the last written record waited in the queue.
+    pub lag: Duration,
+    pub max_lag: Duration,
+}
+
+lazy_static! {
+    static ref SINK_STATS: Mutex<Vec<Arc<Mutex<SinkStats>>>> = Mutex::new(Vec::new());
+}
+
+pub(crate) fn sink_stats() -> Vec<SinkStats> {
+    SINK_STATS.lock().unwrap().iter().map(|stats| *stats.lock().unwrap()).collect()
+}
+
+/// Record passed to a `LogSink`.
+pub struct FormattedRecord<'a> {
+    pub timestamp: DateTime<Local>,
+    /// The record as logged, with the key-value fields and the redactions applied.
+    pub record: &'a log::Record<'a>,
+    /// The record in the configured format and template, without colors, followed by
+    /// `LogSink::line_ending()`.
+    pub text: &'a str,
+}
+impl FormattedRecord<'_> {
+    pub fn level(&self) -> log::Level {
+        self.record.level()
+    }
+}
+
+/// Owned copy of a record queued for a sink thread.
+struct Queued {
+    timestamp: DateTime<Local>,
+    level: log::Level,
+    target: String,
+    module: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    message: String,
+    fields: Vec<(String, String)>,
+    text: String,
+    queued: Instant,
+}
+
+struct OwnedFields(Vec<(String, String)>);
+impl<'kvs> VisitSource<'kvs> for OwnedFields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    records: VecDeque<Queued>,
+    writing: bool,
+    closed: bool,
+}
+
+struct Shared {
+    sink: Box<dyn LogSink>,
+    queue: Mutex<Queue>,
+    changed: Condvar,
+    capacity: usize,
+    stats: Arc<Mutex<SinkStats>>,
+}
+
+pub(crate) struct SinkWriter {
+    shared: Arc<Shared>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+impl SinkWriter {
+    /// Starts the sink thread if the sink asks for a queue.
+    pub(crate) fn new(sink: Box<dyn LogSink>) -> std::io::Result<SinkWriter> {
+        let stats = Arc::new(Mutex::new(SinkStats::default()));
+        SINK_STATS.lock().unwrap().push(stats.clone());
+        let capacity = sink.queue_capacity();
+        let shared = Arc::new(Shared { sink, queue: Mutex::new(Queue::default()), changed: Condvar::new(), capacity: capacity.unwrap_or(0).max(1), stats });
+        let thread = match capacity {
+            Some(_) => {
+                let thread_shared = shared.clone();
+                Some(thread::Builder::new().name("shvlog-sink".into()).spawn(move || write_queued(&thread_shared))?)
+            }
+            None => None,
+        };
+        Ok(SinkWriter { shared, thread: Mutex::new(thread) })
+    }
+}
+impl LogWriter for SinkWriter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        let shared = &self.shared;
+        if record.level() > shared.sink.max_level() {
+            return Ok(());
+        }
+        let ratio = shared.sink.sample_ratio(record.level());
+        if ratio < 1.0 && !Sample::new("", ratio).admit(record) {
+            return Ok(());
+        }
+        let mut text = Vec::new();
+        match shared.sink.timezone() {
+            Some(sink_timezone) => timezone::with_timezone(sink_timezone, || crate::format_plain_record(&mut text, now, record, None))?,
+            None => crate::format_plain_record(&mut text, now, record, None)?,
+        }
+        let mut text = String::from_utf8_lossy(&text);
+        let line_ending = shared.sink.line_ending();
+        if !line_ending.is_empty() {
+            if line_ending == "\r\n" {
+                text = text.replace('\n', line_ending).into();
+            }
+            text.to_mut().push_str(line_ending);
+        }
+        if self.thread.lock().unwrap().is_none() {
+            shared.stats.lock().unwrap().written += 1;
+            return shared.sink.write(&FormattedRecord { timestamp: *now.now(), record, text: &text });
+        }
+        let mut queue = shared.queue.lock().unwrap();
+        if queue.records.len() >= shared.capacity {
+            shared.stats.lock().unwrap().dropped += 1;
+            return Ok(());
+        }
+        let mut fields = OwnedFields(Vec::new());
+        let _ = record.key_values().visit(&mut fields);
+        queue.records.push_back(Queued {
+            timestamp: *now.now(),
+            level: record.level(),
+            target: record.target().into(),
+            module: record.module_path().map(Into::into),
+            file: record.file().map(Into::into),
+            line: record.line(),
+            message: record.args().to_string(),
+            fields: fields.0,
+            text: text.into_owned(),
+            queued: Instant::now(),
+        });
+        shared.stats.lock().unwrap().queued = queue.records.len();
+        shared.changed.notify_all();
+        Ok(())
+    }
+    /// Waits until the queued records are written.
+    fn flush(&self) -> std::io::Result<()> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        while (!queue.records.is_empty() || queue.writing) && !queue.closed {
+            queue = self.shared.changed.wait(queue).unwrap();
+        }
+        drop(queue);
+        self.shared.sink.flush()
+    }
+    /// Writes the queued records and stops the sink thread.
+    fn shutdown(&self) {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn write_queued(shared: &Shared) {
+    loop {
+        let records: Vec<Queued> = {
+            let mut queue = shared.queue.lock().unwrap();
+            while queue.records.is_empty() && !queue.closed {
+                queue = shared.changed.wait(queue).unwrap();
+            }
+            if queue.records.is_empty() {
+                return;
+            }
+            queue.writing = true;
+            queue.records.drain(..).collect()
+        };
+        shared.stats.lock().unwrap().queued = 0;
+        for queued in &records {
+            let lag = queued.queued.elapsed();
+            let fields: Vec<(&str, &str)> = queued.fields.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+            let result = shared.sink.write(&FormattedRecord {
+                timestamp: queued.timestamp,
+                record: &log::Record::builder()
+                    .args(format_args!("{}", queued.message))
+                    .level(queued.level)
+                    .target(&queued.target)
+                    .module_path(queued.module.as_deref())
+                    .file(queued.file.as_deref())
+                    .line(queued.line)
+                    .key_values(&fields)
+                    .build(),
+                text: &queued.text,
+            });
+            if let Err(err) = result {
+                eprintln!("shvlog: cannot write to a sink: {}", err);
+            }
+            let mut stats = shared.stats.lock().unwrap();
+            stats.written += 1;
+            stats.lag = lag;
+            stats.max_lag = stats.max_lag.max(lag);
+        }
+        shared.queue.lock().unwrap().writing = false;
+        shared.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Collect(Arc<Mutex<Vec<String>>>);
+    impl LogSink for Collect {
+        fn write(&self, record: &FormattedRecord) -> std::io::Result<()> {
+            self.0.lock().unwrap().push(record.record.args().to_string());
+            Ok(())
+        }
+        fn sample_ratio(&self, level: log::Level) -> f64 {
+            if level == log::Level::Debug { 0.1 } else { 1.0 }
+        }
+    }
+
+    #[test]
+    fn sampling() {
+        let sink = Collect::default();
+        let writer = SinkWriter::new(Box::new(sink.clone())).unwrap();
+        for ix in 0..1000 {
+            let level = if ix % 2 == 0 { log::Level::Info } else { log::Level::Debug };
+            writer.write(&mut DeferredNow::new(), &log::Record::builder().level(level).target("app").args(format_args!("record {}", ix)).build()).unwrap();
+        }
+        let written = sink.0.lock().unwrap();
+        let debug = written.iter().filter(|message| message.trim_start_matches("record ").parse::<u32>().unwrap() % 2 == 1).count();
+        assert_eq!(written.len() - debug, 500);
+        assert!((20..80).contains(&debug), "{} Debug records", debug);
+    }
+
+    struct Crlf(Collect);
+    impl LogSink for Crlf {
+        fn write(&self, record: &FormattedRecord) -> std::io::Result<()> {
+            self.0.0.lock().unwrap().push(record.text.into());
+            Ok(())
+        }
+        fn line_ending(&self) -> &str {
+            "\r\n"
+        }
+    }
+
+    #[test]
+    fn line_ending() {
+        let sink = Collect::default();
+        let writer = SinkWriter::new(Box::new(Crlf(sink.clone()))).unwrap();
+        writer.write(&mut DeferredNow::new(), &log::Record::builder().level(log::Level::Info).target("app").args(format_args!("first\nsecond")).build()).unwrap();
+        let text = &sink.0.lock().unwrap()[0];
+        assert!(text.ends_with("first\r\nsecond\r\n"), "{:?}", text);
+        assert_eq!(text.matches('\n').count(), text.matches("\r\n").count());
+    }
+
+    struct Offset(Collect);
+    impl LogSink for Offset {
+        fn write(&self, record: &FormattedRecord) -> std::io::Result<()> {
+            self.0.0.lock().unwrap().push(record.text.into());
+            Ok(())
+        }
+        fn timezone(&self) -> Option<Tz> {
+            Some(Tz::Fixed(chrono::FixedOffset::east_opt(5 * 3600).unwrap()))
+        }
+    }
+
+    #[test]
+    fn timezone() {
+        let sink = Collect::default();
+        let writer = SinkWriter::new(Box::new(Offset(sink.clone()))).unwrap();
+        writer.write(&mut DeferredNow::new(), &log::Record::builder().level(log::Level::Info).target("app").args(format_args!("hello")).build()).unwrap();
+        let text = &sink.0.lock().unwrap()[0];
+        assert!(text.contains("+05:00"), "{}", text);
+    }
+
+    /// Sink blocking until released, like a network sink over a broken link.
+    struct Stuck(Arc<(Mutex<bool>, Condvar)>, Collect);
+    impl LogSink for Stuck {
+        fn write(&self, record: &FormattedRecord) -> std::io::Result<()> {
+            let (released, changed) = &*self.0;
+            drop(changed.wait_while(released.lock().unwrap(), |released| !*released).unwrap());
+            assert_eq!(record.record.key_values().get(log::kv::Key::from("id")).map(|id| id.to_string()).as_deref(), Some("7"));
+            self.1.write(record)
+        }
+        fn queue_capacity(&self) -> Option<usize> {
+            Some(2)
+        }
+    }
+
+    #[test]
+    fn queue() {
+        let release = Arc::new((Mutex::new(false), Condvar::new()));
+        let sink = Collect::default();
+        let writer = SinkWriter::new(Box::new(Stuck(release.clone(), sink.clone()))).unwrap();
+        let log = |message: &str| writer.write(&mut DeferredNow::new(), &log::Record::builder().level(log::Level::Info).target("app").key_values(&[("id", 7)]).args(format_args!("{}", message)).build()).unwrap();
+        log("one");
+        // the sink thread takes the first record and blocks, two more fit into the queue
+        while writer.shared.stats.lock().unwrap().queued > 0 {
+            thread::yield_now();
+        }
+        log("two");
+        log("three");
+        log("four");
+        assert_eq!(writer.shared.stats.lock().unwrap().dropped, 1);
+        *release.0.lock().unwrap() = true;
+        release.1.notify_all();
+        writer.flush().unwrap();
+        assert_eq!(*sink.0.lock().unwrap(), ["one", "two", "three"]);
+        let stats = *writer.shared.stats.lock().unwrap();
+        assert_eq!((stats.written, stats.queued), (3, 0));
+        assert!(stats.max_lag >= stats.lag);
+        writer.shutdown();
+    }
+}
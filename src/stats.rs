@@ -0,0 +1,80 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+use crate::DropReason;
+
+/// Number of records per level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LevelCounts {
+    pub error: u64,
+    pub warn: u64,
+    pub info: u64,
+    pub debug: u64,
+    pub trace: u64,
+}
+impl LevelCounts {
+    /// Number of records of `level`.
+    pub fn get(&self, level: log::Level) -> u64 {
+        match level {
+            log::Level::Error => self.error,
+            log::Level::Warn => self.warn,
+            log::Level::Info => self.info,
+            log::Level::Debug => self.debug,
+            log::Level::Trace => self.trace,
+        }
+    }
+    /// Number of records of all levels.
+    pub fn total(&self) -> u64 {
+        self.error + self.warn + self.info + self.debug + self.trace
+    }
+    fn add(&mut self, level: log::Level) {
+        let count = match level {
+            log::Level::Error => &mut self.error,
+            log::Level::Warn => &mut self.warn,
+            log::Level::Info => &mut self.info,
+            log::Level::Debug => &mut self.debug,
+            log::Level::Trace => &mut self.trace,
+        };
+        *count += 1;
+    }
+}
+
+/// Counts of the records written since `init()`, see `ShvLogHandle::stats()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LogStats {
+    pub levels: LevelCounts,
+    /// Counts per record target, the target is the module path unless set explicitly.
+    pub targets: BTreeMap<String, LevelCounts>,
+    /// Records passing the thresholds but not written, per reason.
+    pub dropped: BTreeMap<DropReason, u64>,
+}
+
+lazy_static! {
+    static ref TARGET_COUNTS: Mutex<HashMap<String, LevelCounts>> = Mutex::new(HashMap::new());
+}
+
+/// Counts a written record.
+pub(crate) fn count(record: &log::Record) {
+    let mut targets = TARGET_COUNTS.lock().unwrap();
+    match targets.get_mut(record.target()) {
+        Some(counts) => counts.add(record.level()),
+        None => targets.entry(record.target().into()).or_default().add(record.level()),
+    }
+}
+
+pub(crate) fn stats() -> LogStats {
+    let targets: BTreeMap<String, LevelCounts> = TARGET_COUNTS.lock().unwrap().iter()
+        .map(|(target, counts)| (target.clone(), *counts))
+        .collect();
+    let mut levels = LevelCounts::default();
+    for counts in targets.values() {
+        levels.error += counts.error;
+        levels.warn += counts.warn;
+        levels.info += counts.info;
+        levels.debug += counts.debug;
+        levels.trace += counts.trace;
+    }
+    LogStats { levels, targets, dropped: crate::drops::totals() }
+}
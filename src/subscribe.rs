@@ -0,0 +1,215 @@
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, TrySendError};
+use std::sync::{Arc, RwLock, Weak};
+
+use flexi_logger::DeferredNow;
+use lazy_static::lazy_static;
+
+use crate::{LogConfig, LogRecord};
+
+type Callback = Arc<dyn Fn(&LogRecord) + Send + Sync>;
+
+struct Subscriber {
+    id: u64,
+    /// Thresholds of the subscriber replacing the active ones, see
+    /// `ShvLogHandle::subscribe_with_verbosity()`.
+    overlay: Option<LogConfig>,
+    callback: Callback,
+}
+
+lazy_static! {
+    static ref SUBSCRIBERS: RwLock<Vec<Subscriber>> = RwLock::new(Vec::new());
+}
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+/// Set while a subscriber has an overlay, spares the lock on every record.
+static OVERLAYS: AtomicBool = AtomicBool::new(false);
+
+/// Queued record with the overlay subscribers it matched, or None for the other subscribers.
+type Pending = (LogRecord, Option<Vec<u64>>);
+
+thread_local! {
+    static NOTIFYING: Cell<bool> = const { Cell::new(false) };
+    static PENDING: RefCell<Vec<Pending>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Subscription created by `ShvLogHandle::subscribe()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Subscription(u64);
+
+/// Channel of the records created by `ShvLogHandle::subscribe_channel()`, unsubscribes when
+/// dropped.
+pub struct RecordReceiver {
+    receiver: Receiver<LogRecord>,
+    subscription: Subscription,
+    dropped: Arc<AtomicU64>,
+    config: Option<Weak<RwLock<LogConfig>>>,
+}
+impl RecordReceiver {
+    /// Records dropped because the channel was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+impl std::ops::Deref for RecordReceiver {
+    type Target = Receiver<LogRecord>;
+    fn deref(&self) -> &Receiver<LogRecord> {
+        &self.receiver
+    }
+}
+impl Drop for RecordReceiver {
+    fn drop(&mut self) {
+        unsubscribe(self.subscription);
+        if let Some(config) = self.config.as_ref().and_then(Weak::upgrade) {
+            config.read().unwrap().update_max_level();
+        }
+    }
+}
+
+/// Subscribes a channel queueing at most `capacity` records, `config` is the active config
+/// to update when `overlay` is given.
+pub(crate) fn subscribe_channel(overlay: Option<LogConfig>, capacity: usize, config: &Arc<RwLock<LogConfig>>) -> RecordReceiver {
+    let (sender, receiver) = mpsc::sync_channel(capacity);
+    let dropped = Arc::new(AtomicU64::new(0));
+    let config = overlay.as_ref().map(|_| Arc::downgrade(config));
+    let counter = dropped.clone();
+    let subscription = subscribe(overlay, Arc::new(move |record: &LogRecord| {
+        if let Err(TrySendError::Full(_)) = sender.try_send(record.clone()) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+    RecordReceiver { receiver, subscription, dropped, config }
+}
+
+pub(crate) fn subscribe(overlay: Option<LogConfig>, callback: Callback) -> Subscription {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut subscribers = SUBSCRIBERS.write().unwrap();
+    subscribers.push(Subscriber { id, overlay, callback });
+    OVERLAYS.store(subscribers.iter().any(|subscriber| subscriber.overlay.is_some()), Ordering::Relaxed);
+    Subscription(id)
+}
+
+pub(crate) fn unsubscribe(subscription: Subscription) {
+    let mut subscribers = SUBSCRIBERS.write().unwrap();
+    subscribers.retain(|subscriber| subscriber.id != subscription.0);
+    OVERLAYS.store(subscribers.iter().any(|subscriber| subscriber.overlay.is_some()), Ordering::Relaxed);
+}
+
+/// The most verbose level of the overlays, records up to it must reach the filter.
+pub(crate) fn overlay_max_level() -> log::LevelFilter {
+    if !OVERLAYS.load(Ordering::Relaxed) {
+        return log::LevelFilter::Off;
+    }
+    SUBSCRIBERS.read().unwrap().iter()
+        .filter_map(|subscriber| subscriber.overlay.as_ref())
+        .map(LogConfig::max_level)
+        .max()
+        .unwrap_or(log::LevelFilter::Off)
+}
+
+/// Queues `record` for the subscribers without overlay, delivered by `deliver()` once the
+/// filter released the active config, so that the callbacks may log and the config may be
+/// replaced meanwhile.
+pub(crate) fn notify(now: &mut DeferredNow, record: &log::Record) {
+    if SUBSCRIBERS.read().unwrap().iter().all(|subscriber| subscriber.overlay.is_some()) || NOTIFYING.with(Cell::get) {
+        return;
+    }
+    let record = LogRecord::from_record(now, record);
+    PENDING.with(|pending| pending.borrow_mut().push((record, None)));
+}
+
+/// Queues `record` for the overlay subscribers whose thresholds it passes, regardless of
+/// the active thresholds.
+pub(crate) fn notify_overlays(now: &mut DeferredNow, record: &log::Record) {
+    if !OVERLAYS.load(Ordering::Relaxed) || NOTIFYING.with(Cell::get) {
+        return;
+    }
+    let matched: Vec<u64> = SUBSCRIBERS.read().unwrap().iter()
+        .filter(|subscriber| subscriber.overlay.as_ref().is_some_and(|overlay| overlay.passes_thresholds(record)))
+        .map(|subscriber| subscriber.id)
+        .collect();
+    if !matched.is_empty() {
+        let record = LogRecord::from_record(now, record);
+        PENDING.with(|pending| pending.borrow_mut().push((record, Some(matched))));
+    }
+}
+
+/// Passes the queued records to the subscribers. Records logged by the subscribers
+/// themselves are not passed back to them.
+pub(crate) fn deliver() {
+    let records = PENDING.with(|pending| std::mem::take(&mut *pending.borrow_mut()));
+    if records.is_empty() {
+        return;
+    }
+    let callbacks: Vec<(u64, bool, Callback)> = SUBSCRIBERS.read().unwrap().iter()
+        .map(|subscriber| (subscriber.id, subscriber.overlay.is_some(), subscriber.callback.clone()))
+        .collect();
+    NOTIFYING.with(|notifying| notifying.set(true));
+    for (record, matched) in &records {
+        for (id, has_overlay, callback) in &callbacks {
+            let wanted = match matched {
+                Some(matched) => matched.contains(id),
+                None => !has_overlay,
+            };
+            if wanted {
+                callback(record);
+            }
+        }
+    }
+    NOTIFYING.with(|notifying| notifying.set(false));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(record: &log::Record) {
+        let mut now = DeferredNow::new();
+        notify(&mut now, record);
+        notify_overlays(&mut now, record);
+        deliver();
+    }
+
+    #[test]
+    fn channel() {
+        let config = Arc::new(RwLock::new(LogConfig::from_verbosity_string("-d :I").unwrap()));
+        let all = subscribe_channel(None, 64, &config);
+        // records of the other tests reach the subscribers too, the overlays select only these
+        let debug = subscribe_channel(Some(LogConfig::from_verbosity_string("-d :off,channel:D").unwrap()), 2, &config);
+        for (level, message) in [(log::Level::Info, "one"), (log::Level::Debug, "two"), (log::Level::Trace, "three"), (log::Level::Info, "four")] {
+            log(&log::Record::builder().level(level).target("channel").module_path(Some("channel")).args(format_args!("{}", message)).build());
+        }
+        let messages: Vec<_> = all.try_iter().filter(|record| record.module == "channel").map(|record| record.message).collect();
+        assert_eq!(messages, ["one", "two", "three", "four"]);
+        let messages: Vec<_> = debug.try_iter().map(|record| record.message).collect();
+        assert_eq!(messages, ["one", "two"]);
+        assert_eq!(debug.dropped(), 1);
+        let subscription = debug.subscription;
+        drop(debug);
+        assert!(SUBSCRIBERS.read().unwrap().iter().all(|subscriber| subscriber.id != subscription.0));
+    }
+
+    struct Discard;
+    impl flexi_logger::filter::LogLineWriter for Discard {
+        fn write(&self, _now: &mut DeferredNow, _record: &log::Record) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn overlay_below_active_thresholds() {
+        use flexi_logger::filter::LogLineFilter;
+        let config = Arc::new(RwLock::new(LogConfig::from_verbosity_string("-d :I").unwrap()));
+        let plain = subscribe_channel(None, 64, &config);
+        let overlay = subscribe_channel(Some(LogConfig::from_verbosity_string("-d :off,overlay:D").unwrap()), 64, &config);
+        assert!(overlay_max_level() >= log::LevelFilter::Debug);
+        for (level, message) in [(log::Level::Info, "one"), (log::Level::Debug, "two"), (log::Level::Trace, "three")] {
+            config.read().unwrap().write(&mut DeferredNow::new(), &log::Record::builder().level(level).target("overlay").module_path(Some("overlay")).args(format_args!("{}", message)).build(), &Discard).unwrap();
+            deliver();
+        }
+        let messages: Vec<_> = plain.try_iter().filter(|record| record.module == "overlay").map(|record| record.message).collect();
+        assert_eq!(messages, ["one"]);
+        let messages: Vec<_> = overlay.try_iter().map(|record| record.message).collect();
+        assert_eq!(messages, ["one", "two"]);
+    }
+}
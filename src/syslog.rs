@@ -0,0 +1,257 @@
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use flexi_logger::writers::LogWriter;
+use flexi_logger::DeferredNow;
+
+use crate::{timezone, ShvSeverity};
+
+/// Syslog facility, see RFC 5424 section 6.2.1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Facility {
+    Kern = 0,
+    User = 1,
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Lpr = 6,
+    News = 7,
+    Uucp = 8,
+    Cron = 9,
+    Authpriv = 10,
+    Ftp = 11,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+/// Syslog severity, see RFC 5424 section 6.2.1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyslogSeverity {
+    Emergency = 0,
+    Alert = 1,
+    Critical = 2,
+    Error = 3,
+    Warning = 4,
+    Notice = 5,
+    Informational = 6,
+    Debug = 7,
+}
+
+#[derive(Clone, Debug)]
+enum Transport {
+    Udp(String),
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// RFC 5424 syslog output, see `LogConfig::with_syslog()`.
+#[derive(Clone, Debug)]
+pub struct Syslog {
+    transport: Transport,
+    facility: Facility,
+    target_facilities: Vec<(String, Facility)>,
+    severities: [Option<SyslogSeverity>; 5],
+    app_name: Option<String>,
+    pub(crate) level: Option<log::Level>,
+}
+impl Syslog {
+    /// Sends records as UDP datagrams to `addr`, e.g. `"collector:514"`.
+    pub fn udp(addr: &str) -> Syslog {
+        Syslog::new(Transport::Udp(addr.into()))
+    }
+    /// Streams records over TCP to `addr` using octet counting framing (RFC 6587).
+    pub fn tcp(addr: &str) -> Syslog {
+        Syslog::new(Transport::Tcp(addr.into()))
+    }
+    /// Sends records to a local datagram socket, usually `/dev/log`.
+    #[cfg(unix)]
+    pub fn unix(path: impl Into<PathBuf>) -> Syslog {
+        Syslog::new(Transport::Unix(path.into()))
+    }
+    fn new(transport: Transport) -> Syslog {
+        Syslog { transport, facility: Facility::User, target_facilities: Vec::new(), severities: [None; 5], app_name: None, level: None }
+    }
+    /// Skips records less severe than `level`, e.g. Warn over the network while the other
+    /// outputs get Debug. The module and target thresholds apply first.
+    pub fn level(mut self, level: log::Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+    /// Facility of the records, `User` by default.
+    pub fn facility(mut self, facility: Facility) -> Self {
+        self.facility = facility;
+        self
+    }
+    /// Facility of the records of `target`, e.g. `Auth` for the `Access` target.
+    pub fn target_facility(mut self, target: &str, facility: Facility) -> Self {
+        self.target_facilities.retain(|(t, _)| t != target);
+        self.target_facilities.push((target.into(), facility));
+        self
+    }
+    /// Sends records of `level` with `severity`, e.g. Debug as `Informational` where the site
+    /// policy drops debug messages. Defaults to the severity of `LogConfig::severity()`.
+    pub fn severity(mut self, level: log::Level, severity: SyslogSeverity) -> Self {
+        self.severities[level as usize - 1] = Some(severity);
+        self
+    }
+    /// APP-NAME of the records, defaults to the application name of `LogConfig` or the executable name.
+    pub fn app_name(mut self, app_name: &str) -> Self {
+        self.app_name = Some(app_name.into());
+        self
+    }
+    pub(crate) fn writer(&self, app_name: Option<&str>, hostname: &str) -> std::io::Result<SyslogWriter> {
+        let app_name = self.app_name.as_deref().or(app_name).map(|name| name.to_string()).unwrap_or_else(|| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.file_name().map(|name| name.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| "-".into())
+        });
+        Ok(SyslogWriter {
+            connection: Mutex::new(Connection::open(&self.transport)?),
+            transport: self.transport.clone(),
+            facility: self.facility,
+            target_facilities: self.target_facilities.clone(),
+            severities: self.severities,
+            app_name: header_field(&app_name, 48),
+            hostname: header_field(hostname, 255),
+            pid: std::process::id(),
+        })
+    }
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixDatagram, PathBuf),
+}
+impl Connection {
+    fn open(transport: &Transport) -> std::io::Result<Connection> {
+        Ok(match transport {
+            Transport::Udp(addr) => {
+                let addr = addr.to_socket_addrs()?.next()
+                    .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("cannot resolve {}", addr)))?;
+                let socket = UdpSocket::bind(if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+                socket.connect(addr)?;
+                Connection::Udp(socket)
+            }
+            Transport::Tcp(addr) => Connection::Tcp(TcpStream::connect(addr)?),
+            #[cfg(unix)]
+            Transport::Unix(path) => Connection::Unix(UnixDatagram::unbound()?, path.clone()),
+        })
+    }
+    fn send(&mut self, message: &[u8]) -> std::io::Result<()> {
+        match self {
+            Connection::Udp(socket) => socket.send(message).map(|_| ()),
+            Connection::Tcp(stream) => {
+                write!(stream, "{} ", message.len())?;
+                stream.write_all(message)
+            }
+            #[cfg(unix)]
+            Connection::Unix(socket, path) => socket.send_to(message, &*path).map(|_| ()),
+        }
+    }
+}
+
+/// Header fields are printable ASCII without spaces, `-` when empty.
+fn header_field(value: &str, max_len: usize) -> String {
+    let field: String = value.chars().filter(|c| c.is_ascii_graphic()).take(max_len).collect();
+    if field.is_empty() { "-".into() } else { field }
+}
+
+/// Escapes a structured data parameter value.
+fn param_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]")
+}
+
+pub(crate) struct SyslogWriter {
+    connection: Mutex<Connection>,
+    transport: Transport,
+    facility: Facility,
+    target_facilities: Vec<(String, Facility)>,
+    severities: [Option<SyslogSeverity>; 5],
+    app_name: String,
+    hostname: String,
+    pid: u32,
+}
+impl SyslogWriter {
+    /// PRI part of the header of the record.
+    fn priority(&self, record: &log::Record) -> u8 {
+        let facility = self.target_facilities.iter()
+            .find(|(target, _)| target == record.target())
+            .map_or(self.facility, |(_, facility)| *facility);
+        let severity = match self.severities[record.level() as usize - 1] {
+            Some(severity) => severity as u8,
+            None => ShvSeverity::of(record.level()).syslog_severity(),
+        };
+        facility as u8 * 8 + severity
+    }
+}
+impl LogWriter for SyslogWriter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        let module = record.module_path().unwrap_or("");
+        let msg_id = if record.target() == module { "-".into() } else { header_field(record.target(), 32) };
+        let message = format!(
+            "<{}>1 {} {} {} {} {} [shvlog@32473 module=\"{}\" line=\"{}\"] {}",
+            self.priority(record),
+            timezone::localize(now.now()).format("%Y-%m-%dT%H:%M:%S%.6f%:z"),
+            self.hostname,
+            self.app_name,
+            self.pid,
+            msg_id,
+            param_value(module),
+            record.line().unwrap_or(0),
+            record.args(),
+        );
+        let mut connection = self.connection.lock().unwrap();
+        if connection.send(message.as_bytes()).is_err() {
+            // the collector may have been restarted, reconnect once
+            *connection = Connection::open(&self.transport)?;
+            connection.send(message.as_bytes())?;
+        }
+        Ok(())
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        if let Connection::Tcp(stream) = &mut *self.connection.lock().unwrap() {
+            stream.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(level: log::Level, target: &str) -> log::Record<'_> {
+        log::Record::builder().level(level).target(target).args(format_args!("")).build()
+    }
+
+    #[test]
+    fn priority() {
+        let writer = Syslog::udp("127.0.0.1:514")
+            .facility(Facility::Daemon)
+            .target_facility("Access", Facility::Auth)
+            .severity(log::Level::Debug, SyslogSeverity::Informational)
+            .severity(log::Level::Error, SyslogSeverity::Critical)
+            .writer(None, "host")
+            .unwrap();
+        assert_eq!(writer.priority(&record(log::Level::Warn, "app")), 3 * 8 + 4);
+        assert_eq!(writer.priority(&record(log::Level::Debug, "app")), 3 * 8 + 6);
+        assert_eq!(writer.priority(&record(log::Level::Error, "Access")), 4 * 8 + 2);
+        assert_eq!(writer.priority(&record(log::Level::Info, "Access")), 4 * 8 + 6);
+    }
+}
@@ -0,0 +1,66 @@
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+/// Sends `sd_notify` messages to the socket systemd passes in `NOTIFY_SOCKET`.
+pub(crate) struct Notifier {
+    socket: UnixDatagram,
+    path: String,
+    healthy: AtomicBool,
+}
+impl Notifier {
+    /// Returns `None` when the process is not run by systemd with `NotifyAccess` set.
+    pub(crate) fn from_env() -> Option<Arc<Notifier>> {
+        let path = std::env::var("NOTIFY_SOCKET").ok()?;
+        let socket = UnixDatagram::unbound().ok()?;
+        Some(Arc::new(Notifier { socket, path, healthy: AtomicBool::new(true) }))
+    }
+    fn send(&self, state: &str) {
+        #[cfg(target_os = "linux")]
+        if let Some(name) = self.path.strip_prefix('@') {
+            use std::os::linux::net::SocketAddrExt;
+            if let Ok(addr) = std::os::unix::net::SocketAddr::from_abstract_name(name) {
+                let _ = self.socket.send_to_addr(state.as_bytes(), &addr);
+            }
+            return;
+        }
+        let _ = self.socket.send_to(state.as_bytes(), &self.path);
+    }
+    pub(crate) fn report_error(&self, record: &log::Record) {
+        let message = format!("{}", record.args());
+        self.send(&format!("STATUS=Last error: {}", message.lines().next().unwrap_or("")));
+    }
+    pub(crate) fn report_write_result(&self, result: &std::io::Result<()>) {
+        match result {
+            Ok(()) => if !self.healthy.swap(true, Ordering::Relaxed) {
+                self.send("STATUS=Logging recovered");
+            },
+            Err(err) => if self.healthy.swap(false, Ordering::Relaxed) {
+                self.send(&format!("STATUS=Logging failed: {}", err));
+            },
+        }
+    }
+}
+
+/// Sends `WATCHDOG=1` keepalives at half the `WATCHDOG_USEC` interval for as long as writing records succeeds.
+/// The thread ends once the notifier is dropped with its config.
+pub(crate) fn spawn_watchdog(notifier: Weak<Notifier>) -> std::io::Result<()> {
+    let Some(usec) = std::env::var("WATCHDOG_USEC").ok().and_then(|usec| usec.parse::<u64>().ok()) else {
+        return Ok(());
+    };
+    let interval = Duration::from_micros(usec) / 2;
+    thread::Builder::new()
+        .name("shvlog-watchdog".into())
+        .spawn(move || {
+            while let Some(notifier) = notifier.upgrade() {
+                if notifier.healthy.load(Ordering::Relaxed) {
+                    notifier.send("WATCHDOG=1");
+                }
+                drop(notifier);
+                thread::sleep(interval);
+            }
+        })?;
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use ansi_term::{Color, Style};
+use flexi_logger::DeferredNow;
+
+use crate::{FormatOptions, TextOutput};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Timestamp,
+    Level,
+    LevelAbbr,
+    Module,
+    File,
+    Line,
+    Target,
+    Message,
+    Fields,
+    Prefix,
+    Host,
+    Pid,
+    Thread,
+}
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        Some(match name {
+            "ts" => Field::Timestamp,
+            "level" => Field::Level,
+            "lvl" => Field::LevelAbbr,
+            "module" => Field::Module,
+            "file" => Field::File,
+            "line" => Field::Line,
+            "target" => Field::Target,
+            "msg" => Field::Message,
+            "fields" => Field::Fields,
+            "prefix" => Field::Prefix,
+            "host" => Field::Host,
+            "pid" => Field::Pid,
+            "thread" => Field::Thread,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+/// Text format parsed from a template, see `LogConfig::with_format()`.
+#[derive(Clone, Debug)]
+pub(crate) struct Template(Vec<Segment>);
+impl Template {
+    pub(crate) fn parse(template: &str) -> Template {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+        while let Some(ix) = rest.find(['{', '}']) {
+            literal.push_str(&rest[..ix]);
+            rest = &rest[ix..];
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                literal.push_str(&rest[..1]);
+                rest = &rest[2..];
+                continue;
+            }
+            let field = rest.strip_prefix('{')
+                .and_then(|tail| tail.find('}').map(|end| (&tail[..end], &tail[end + 1..])))
+                .and_then(|(name, tail)| Field::from_name(name).map(|field| (field, tail)));
+            match field {
+                Some((field, tail)) => {
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(field));
+                    rest = tail;
+                }
+                // unknown placeholders are kept as written
+                None => {
+                    literal.push_str(&rest[..1]);
+                    rest = &rest[1..];
+                }
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Template(segments)
+    }
+    pub(crate) fn write(&self, w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &log::Record, options: &FormatOptions, output: TextOutput) -> std::io::Result<()> {
+        let style = |style: Style| if output.colored { style } else { Style::new() };
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(text) => write!(w, "{}", text)?,
+                Segment::Field(field) => {
+                    let (color, text) = match field {
+                        Field::Timestamp if output.timestamp => (options.palette.timestamp_style(), crate::timestamp_text(now, options)),
+                        Field::Timestamp => (Style::new(), "".into()),
+                        Field::Level => (options.palette.level_style(record.level()), record.level().to_string()),
+                        Field::LevelAbbr => (options.palette.level_style(record.level()), record.level().as_str()[..1].into()),
+                        Field::Module => (crate::module_style(record.module_path().unwrap_or(""), options), output.module.text(record.module_path().unwrap_or("<unnamed>")).unwrap_or_default().into()),
+                        Field::File => (options.palette.module_style(), crate::source_file(record, options).into()),
+                        Field::Line if output.line => (options.palette.module_style(), record.line().unwrap_or(0).to_string()),
+                        Field::Line => (Style::new(), "".into()),
+                        Field::Target => (options.palette.target_style(), record.target().into()),
+                        Field::Message => (options.palette.level_style(record.level()), crate::message_text(record, output.max_len)),
+                        Field::Fields => (Color::White.dimmed(), crate::fields_text(record).trim_start().into()),
+                        Field::Prefix => (Color::Blue.normal(), options.prefix.clone()),
+                        Field::Host => (Color::Blue.normal(), options.hostname.clone()),
+                        Field::Pid => (Color::Blue.normal(), options.pid.to_string()),
+                        Field::Thread => (Color::Purple.normal(), crate::thread_name()),
+                    };
+                    if !text.is_empty() {
+                        write!(w, "{}", style(color).paint(text))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+//! Capturing of the records logged by the current thread, for unit tests.
+//!
+//! ```ignore
+//! let capture = shvlog::test::capture();
+//! connect_device();
+//! capture.assert_logged(log::Level::Warn, "connection refused");
+//! ```
+//!
+//! Records are captured regardless of the thresholds, also when the logger was set up by `init()`.
+//! Each test runs on its own thread, so parallel tests do not see each other's records.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+use flexi_logger::DeferredNow;
+
+use crate::LogRecord;
+
+type Records = Rc<RefCell<Vec<LogRecord>>>;
+
+/// Set by the first `capture()`, from then on records of all levels are logged.
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static CAPTURES: RefCell<Vec<Records>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Logger installed by `capture()` when no other logger is set.
+struct CaptureLogger;
+impl log::Log for CaptureLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+    fn log(&self, record: &log::Record) {
+        capture_record(&mut DeferredNow::new(), record);
+    }
+    fn flush(&self) {}
+}
+
+/// Guard of a capture started by `capture()`, records are captured until it is dropped.
+#[must_use = "records are captured until the guard is dropped"]
+pub struct LogCapture {
+    records: Records,
+}
+impl LogCapture {
+    /// Records captured so far, oldest first.
+    pub fn records(&self) -> Vec<LogRecord> {
+        self.records.borrow().clone()
+    }
+    /// Whether a record of `level` with message containing `pattern` was captured.
+    pub fn logged(&self, level: log::Level, pattern: &str) -> bool {
+        self.records.borrow().iter().any(|record| record.level == level && record.message.contains(pattern))
+    }
+    /// Panics with the captured records unless a record of `level` with message
+    /// containing `pattern` was captured.
+    #[track_caller]
+    pub fn assert_logged(&self, level: log::Level, pattern: &str) {
+        if !self.logged(level, pattern) {
+            let records: Vec<String> = self.records.borrow().iter().map(|record| format!("  {}", record)).collect();
+            panic!("no {} record containing '{}' was logged, captured records:\n{}", level, pattern, records.join("\n"));
+        }
+    }
+}
+impl Drop for LogCapture {
+    fn drop(&mut self) {
+        CAPTURES.with(|captures| captures.borrow_mut().retain(|records| !Rc::ptr_eq(records, &self.records)));
+    }
+}
+
+/// Starts capturing the records logged by the current thread.
+///
+/// Installs a capturing logger when no logger is set, records then go nowhere else.
+pub fn capture() -> LogCapture {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        CAPTURING.store(true, Ordering::Relaxed);
+        let _ = log::set_boxed_logger(Box::new(CaptureLogger));
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+    let records = Records::default();
+    CAPTURES.with(|captures| captures.borrow_mut().push(records.clone()));
+    LogCapture { records }
+}
+
+pub(crate) fn capturing() -> bool {
+    CAPTURING.load(Ordering::Relaxed)
+}
+
+/// Passes `record` to the captures of the current thread.
+pub(crate) fn capture_record(now: &mut DeferredNow, record: &log::Record) {
+    // try_with, records may be logged during thread teardown
+    let _ = CAPTURES.try_with(|captures| {
+        let captures = captures.borrow();
+        if !captures.is_empty() {
+            let record = LogRecord::from_record(now, record);
+            for records in captures.iter() {
+                records.borrow_mut().push(record.clone());
+            }
+        }
+    });
+}
@@ -0,0 +1,92 @@
+use std::sync::Mutex;
+use std::time::Instant;
+
+use flexi_logger::filter::LogLineWriter;
+use flexi_logger::DeferredNow;
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    dropped: usize,
+}
+
+/// Token bucket limiting the record rate of a target or module.
+pub(crate) struct Throttle {
+    pattern: String,
+    rate: f64,
+    burst: usize,
+    bucket: Mutex<Bucket>,
+}
+impl Throttle {
+    pub(crate) fn new(pattern: &str, rate: f64, burst: usize) -> Throttle {
+        Throttle {
+            pattern: pattern.into(),
+            rate,
+            burst,
+            bucket: Mutex::new(Bucket { tokens: burst as f64, refilled: Instant::now(), dropped: 0 }),
+        }
+    }
+    pub(crate) fn matches(&self, record: &log::Record) -> bool {
+        record.target().contains(&self.pattern) || record.module_path().unwrap_or("").contains(&self.pattern)
+    }
+    /// Takes a token for `record` and returns whether it is to be written.
+    /// Writes the number of dropped records before the first record admitted after them.
+    pub(crate) fn admit(&self, now: &mut DeferredNow, record: &log::Record, log_line_writer: &dyn LogLineWriter) -> std::io::Result<bool> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let instant = Instant::now();
+        let refill = instant.duration_since(bucket.refilled).as_secs_f64() * self.rate;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst as f64);
+        bucket.refilled = instant;
+        if bucket.tokens < 1.0 {
+            bucket.dropped += 1;
+            return Ok(false);
+        }
+        bucket.tokens -= 1.0;
+        if bucket.dropped > 0 {
+            let notice = format!("{} records of '{}' dropped by throttling to {}/s", bucket.dropped, self.pattern, self.rate);
+            bucket.dropped = 0;
+            log_line_writer.write(now, &log::Record::builder()
+                .args(format_args!("{}", notice))
+                .level(log::Level::Warn)
+                .target(record.target())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build())?;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Collect(Mutex<Vec<String>>);
+    impl LogLineWriter for Collect {
+        fn write(&self, _now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+            self.0.lock().unwrap().push(record.args().to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn burst_then_rate() {
+        let throttle = Throttle::new("RpcData", 1.0, 2);
+        let output = Collect(Mutex::new(Vec::new()));
+        let log = |record: &log::Record| {
+            assert!(throttle.matches(record));
+            if throttle.admit(&mut DeferredNow::new(), record, &output).unwrap() {
+                output.write(&mut DeferredNow::new(), record).unwrap();
+            }
+        };
+        for n in 1..=6 {
+            if n == 6 {
+                throttle.bucket.lock().unwrap().refilled -= std::time::Duration::from_secs(1);
+            }
+            log(&log::Record::builder().target("rpc").module_path(Some("shvapp::RpcData")).args(format_args!("{}", n)).build());
+        }
+        assert_eq!(*output.0.lock().unwrap(), ["1", "2", "3 records of 'RpcData' dropped by throttling to 1/s", "6"]);
+        assert!(!throttle.matches(&log::Record::builder().target("rpc").module_path(Some("shvapp::device")).build()));
+    }
+}
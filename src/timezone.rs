@@ -0,0 +1,386 @@
+use std::cell::RefCell;
+use std::sync::RwLock;
+
+use chrono::{DateTime, FixedOffset, Local};
+use lazy_static::lazy_static;
+
+lazy_static! {
+    /// Timezone of the active config, see `LogConfig::timezone()`.
+    static ref TIMEZONE: RwLock<Tz> = RwLock::new(Tz::Local);
+}
+
+thread_local! {
+    /// Timezone of the output being rendered, see `with_timezone()`.
+    static OUTPUT_TIMEZONE: RefCell<Option<Tz>> = const { RefCell::new(None) };
+}
+
+/// Timezone of the rendered timestamps, see `LogConfig::timezone()`.
+#[derive(Clone, Debug, Default)]
+pub enum Tz {
+    /// The system timezone.
+    #[default]
+    Local,
+    /// Fixed UTC offset, e.g. `FixedOffset::east_opt(3600)` for `+01:00`.
+    Fixed(FixedOffset),
+    /// IANA timezone like `Europe/Prague` with its daylight saving time, see `Tz::named()`.
+    #[cfg(feature = "tz")]
+    Named(std::sync::Arc<zoneinfo::ZoneInfo>),
+}
+impl Tz {
+    pub fn utc() -> Tz {
+        Tz::Fixed(FixedOffset::east_opt(0).unwrap())
+    }
+    /// Loads the IANA timezone `name` from the system zoneinfo, `$TZDIR` or `/usr/share/zoneinfo`.
+    #[cfg(feature = "tz")]
+    pub fn named(name: &str) -> std::io::Result<Tz> {
+        zoneinfo::ZoneInfo::load(name).map(|zone| Tz::Named(std::sync::Arc::new(zone)))
+    }
+    /// Parses `local`, `utc`, an offset like `+01:00`, `-0530` or `+02` and, with the `tz`
+    /// feature, an IANA timezone name.
+    pub fn parse(text: &str) -> Result<Tz, String> {
+        match text {
+            "local" => return Ok(Tz::Local),
+            "utc" | "UTC" | "Z" => return Ok(Tz::utc()),
+            _ => {}
+        }
+        if let Some(offset) = parse_offset(text) {
+            return FixedOffset::east_opt(offset).map(Tz::Fixed).ok_or_else(|| format!("UTC offset '{}' out of range", text));
+        }
+        #[cfg(feature = "tz")]
+        return Tz::named(text).map_err(|err| format!("unknown timezone '{}': {}", text, err));
+        #[cfg(not(feature = "tz"))]
+        Err(format!("invalid timezone '{}', expected local, utc or an offset like +01:00, timezone names require the tz feature", text))
+    }
+    /// `time` shifted to the timezone.
+    pub(crate) fn localize(&self, time: &DateTime<Local>) -> DateTime<FixedOffset> {
+        match self {
+            Tz::Local => time.fixed_offset(),
+            Tz::Fixed(offset) => time.with_timezone(offset),
+            #[cfg(feature = "tz")]
+            Tz::Named(zone) => time.with_timezone(&zone.offset_at(time.timestamp())),
+        }
+    }
+}
+
+/// Seconds east of UTC of `+hh[:mm]`, `-hhmm` and the like.
+fn parse_offset(text: &str) -> Option<i32> {
+    let sign = match text.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits: String = text[1..].chars().filter(|c| *c != ':').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) || !matches!(digits.len(), 2 | 4) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits.get(2..).filter(|minutes| !minutes.is_empty()).map_or(Some(0), |minutes| minutes.parse().ok())?;
+    (minutes < 60).then_some(sign * (hours * 3600 + minutes * 60))
+}
+
+pub(crate) fn set_timezone(timezone: Tz) {
+    *TIMEZONE.write().unwrap() = timezone;
+}
+
+/// Calls `render` with the timestamps in `timezone` instead of the one of the active config,
+/// e.g. for a sink with its own timezone.
+pub(crate) fn with_timezone<R>(timezone: Tz, render: impl FnOnce() -> R) -> R {
+    let previous = OUTPUT_TIMEZONE.with(|output| output.replace(Some(timezone)));
+    let result = render();
+    OUTPUT_TIMEZONE.with(|output| *output.borrow_mut() = previous);
+    result
+}
+
+/// `time` in the timezone of the rendered output, by default of the active config.
+pub(crate) fn localize(time: &DateTime<Local>) -> DateTime<FixedOffset> {
+    OUTPUT_TIMEZONE.with(|output| match &*output.borrow() {
+        Some(timezone) => timezone.localize(time),
+        None => TIMEZONE.read().unwrap().localize(time),
+    })
+}
+
+#[cfg(feature = "tz")]
+pub(crate) mod zoneinfo {
+    use std::path::PathBuf;
+
+    use chrono::{Datelike, FixedOffset, NaiveDate};
+
+    /// IANA timezone loaded by `Tz::named()`, the transitions of a TZif file, RFC 8536,
+    /// followed by its POSIX TZ rule for later times.
+    #[derive(Debug)]
+    pub struct ZoneInfo {
+        name: String,
+        /// UTC time of the transition and the UTC offset from then on.
+        transitions: Vec<(i64, i32)>,
+        /// UTC offset before the first transition.
+        initial: i32,
+        rule: Option<Rule>,
+    }
+    impl ZoneInfo {
+        pub(super) fn load(name: &str) -> std::io::Result<ZoneInfo> {
+            let invalid = |reason: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} '{}'", reason, name));
+            if name.is_empty() || name.starts_with('/') || name.split('/').any(|part| part == "..") {
+                return Err(invalid("invalid timezone name"));
+            }
+            let dir = std::env::var_os("TZDIR").map_or_else(|| PathBuf::from("/usr/share/zoneinfo"), PathBuf::from);
+            let data = std::fs::read(dir.join(name))?;
+            let (transitions, initial, rule) = parse_tzif(&data).ok_or_else(|| invalid("invalid zoneinfo file of"))?;
+            Ok(ZoneInfo { name: name.into(), transitions, initial, rule })
+        }
+        pub fn name(&self) -> &str {
+            &self.name
+        }
+        pub(super) fn offset_at(&self, utc: i64) -> FixedOffset {
+            let ix = self.transitions.partition_point(|(time, _)| *time <= utc);
+            let offset = match (ix, &self.rule) {
+                (ix, Some(rule)) if ix == self.transitions.len() => rule.offset_at(utc),
+                (0, _) => self.initial,
+                (ix, _) => self.transitions[ix - 1].1,
+            };
+            FixedOffset::east_opt(offset).unwrap_or_else(|| FixedOffset::east_opt(0).unwrap())
+        }
+    }
+
+    struct Reader<'a>(&'a [u8]);
+    impl<'a> Reader<'a> {
+        fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+            if self.0.len() < len {
+                return None;
+            }
+            let (taken, rest) = self.0.split_at(len);
+            self.0 = rest;
+            Some(taken)
+        }
+        fn u32(&mut self) -> Option<usize> {
+            self.take(4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+        }
+    }
+
+    type Parsed = (Vec<(i64, i32)>, i32, Option<Rule>);
+
+    /// The version 2+ data block with 64-bit times if present, else the version 1 one.
+    fn parse_tzif(data: &[u8]) -> Option<Parsed> {
+        let mut reader = Reader(data);
+        let (version, counts) = header(&mut reader)?;
+        if version == 0 {
+            let (transitions, initial) = data_block(&mut reader, counts, 4)?;
+            return Some((transitions, initial, None));
+        }
+        let (_, _, leap_count, time_count, type_count, char_count) = counts;
+        reader.take(time_count * 5 + type_count * 6 + char_count + leap_count * 8 + counts.0 + counts.1)?;
+        let (_, counts) = header(&mut reader)?;
+        let (transitions, initial) = data_block(&mut reader, counts, 8)?;
+        let footer = std::str::from_utf8(reader.0).ok()?.trim_start_matches('\n');
+        let rule = footer.split('\n').next().filter(|rule| !rule.is_empty()).and_then(Rule::parse);
+        Some((transitions, initial, rule))
+    }
+
+    type Counts = (usize, usize, usize, usize, usize, usize);
+
+    fn header(reader: &mut Reader) -> Option<(u8, Counts)> {
+        if reader.take(4)? != b"TZif" {
+            return None;
+        }
+        let version = reader.take(16)?[0];
+        let counts = (reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?);
+        Some((version.saturating_sub(b'0'), counts))
+    }
+
+    fn data_block(reader: &mut Reader, counts: Counts, time_size: usize) -> Option<(Vec<(i64, i32)>, i32)> {
+        let (utc_count, std_count, leap_count, time_count, type_count, char_count) = counts;
+        let times = reader.take(time_count * time_size)?;
+        let indices = reader.take(time_count)?;
+        let types: Vec<i32> = reader.take(type_count * 6)?.chunks(6)
+            .map(|ttinfo| i32::from_be_bytes([ttinfo[0], ttinfo[1], ttinfo[2], ttinfo[3]]))
+            .collect();
+        reader.take(char_count + leap_count * (time_size + 4) + std_count + utc_count)?;
+        let transitions = times.chunks(time_size).zip(indices)
+            .map(|(time, ix)| {
+                let time = match time_size {
+                    4 => i64::from(i32::from_be_bytes([time[0], time[1], time[2], time[3]])),
+                    _ => i64::from_be_bytes([time[0], time[1], time[2], time[3], time[4], time[5], time[6], time[7]]),
+                };
+                types.get(usize::from(*ix)).map(|offset| (time, *offset))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some((transitions, *types.first()?))
+    }
+
+    /// Day of a POSIX TZ rule.
+    #[derive(Debug)]
+    enum Day {
+        /// `Jn`, 1 to 365, February 29 is never counted.
+        Julian(u32),
+        /// `n`, 0 to 365.
+        Ordinal(u32),
+        /// `Mm.w.d`, day `d` (0 = Sunday) of week `w` (5 = the last) of month `m`.
+        MonthWeek(u32, u32, u32),
+    }
+    impl Day {
+        fn date(&self, year: i32) -> Option<NaiveDate> {
+            match *self {
+                Day::Julian(day) => {
+                    let leap = NaiveDate::from_ymd_opt(year, 2, 29).is_some();
+                    NaiveDate::from_yo_opt(year, day + u32::from(leap && day >= 60))
+                }
+                Day::Ordinal(day) => NaiveDate::from_yo_opt(year, day + 1),
+                Day::MonthWeek(month, week, weekday) => {
+                    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+                    let mut day = 1 + (weekday + 7 - first.weekday().num_days_from_sunday()) % 7 + (week - 1) * 7;
+                    while NaiveDate::from_ymd_opt(year, month, day).is_none() {
+                        day -= 7;
+                    }
+                    NaiveDate::from_ymd_opt(year, month, day)
+                }
+            }
+        }
+    }
+
+    /// The daylight saving time of a POSIX TZ rule like `CET-1CEST,M3.5.0,M10.5.0/3`.
+    #[derive(Debug)]
+    struct Dst {
+        offset: i32,
+        start: (Day, i32),
+        end: (Day, i32),
+    }
+
+    #[derive(Debug)]
+    struct Rule {
+        offset: i32,
+        dst: Option<Dst>,
+    }
+    impl Rule {
+        fn parse(text: &str) -> Option<Rule> {
+            let mut rest = skip_name(text)?;
+            let (std_offset, tail) = parse_time(rest)?;
+            // POSIX offsets are west of UTC
+            let offset = -std_offset;
+            rest = tail;
+            if rest.is_empty() {
+                return Some(Rule { offset, dst: None });
+            }
+            rest = skip_name(rest)?;
+            let dst_offset = match parse_time(rest) {
+                Some((dst_offset, tail)) => {
+                    rest = tail;
+                    -dst_offset
+                }
+                None => offset + 3600,
+            };
+            // the US rule is the customary default
+            let rules = rest.strip_prefix(',').unwrap_or("M3.2.0,M11.1.0");
+            let (start, end) = rules.split_once(',')?;
+            Some(Rule { offset, dst: Some(Dst { offset: dst_offset, start: parse_day(start)?, end: parse_day(end)? }) })
+        }
+        fn offset_at(&self, utc: i64) -> i32 {
+            let dst = match &self.dst {
+                Some(dst) => dst,
+                None => return self.offset,
+            };
+            let year = match chrono::DateTime::from_timestamp(utc + i64::from(self.offset), 0) {
+                Some(time) => time.year(),
+                None => return self.offset,
+            };
+            let transition = |(day, time): &(Day, i32), offset: i32| {
+                day.date(year).and_then(|date| date.and_hms_opt(0, 0, 0))
+                    .map(|midnight| midnight.and_utc().timestamp() + i64::from(*time) - i64::from(offset))
+            };
+            // the start is given in the standard time, the end in the daylight saving time
+            let (start, end) = match (transition(&dst.start, self.offset), transition(&dst.end, dst.offset)) {
+                (Some(start), Some(end)) => (start, end),
+                _ => return self.offset,
+            };
+            let in_dst = if start <= end { start <= utc && utc < end } else { utc < end || start <= utc };
+            if in_dst { dst.offset } else { self.offset }
+        }
+    }
+
+    /// Skips an alphabetic or `<...>` quoted abbreviation.
+    fn skip_name(text: &str) -> Option<&str> {
+        let rest = match text.strip_prefix('<') {
+            Some(quoted) => &quoted[quoted.find('>')? + 1..],
+            None => text.trim_start_matches(|c: char| c.is_ascii_alphabetic()),
+        };
+        (rest.len() < text.len()).then_some(rest)
+    }
+
+    /// Parses `[+-]hh[:mm[:ss]]` as seconds.
+    fn parse_time(text: &str) -> Option<(i32, &str)> {
+        let (sign, unsigned) = match text.as_bytes().first()? {
+            b'-' => (-1, &text[1..]),
+            b'+' => (1, &text[1..]),
+            _ => (1, text),
+        };
+        let len = unsigned.find(|c: char| !c.is_ascii_digit() && c != ':').unwrap_or(unsigned.len());
+        if len == 0 {
+            return None;
+        }
+        let mut seconds = 0;
+        for (part, scale) in unsigned[..len].split(':').zip([3600, 60, 1]) {
+            seconds += part.parse::<i32>().ok()? * scale;
+        }
+        Some((sign * seconds, &unsigned[len..]))
+    }
+
+    /// Parses `date[/time]` of a rule, the time defaults to 02:00.
+    fn parse_day(text: &str) -> Option<(Day, i32)> {
+        let (day, time) = match text.split_once('/') {
+            Some((day, time)) => (day, parse_time(time).filter(|(_, rest)| rest.is_empty())?.0),
+            None => (text, 2 * 3600),
+        };
+        let day = if let Some(julian) = day.strip_prefix('J') {
+            Day::Julian(julian.parse().ok().filter(|day| (1..=365).contains(day))?)
+        } else if let Some(month_week) = day.strip_prefix('M') {
+            let mut parts = month_week.split('.').map(|part| part.parse::<u32>().ok());
+            let (month, week, weekday) = (parts.next()??, parts.next()??, parts.next()??);
+            if !(1..=12).contains(&month) || !(1..=5).contains(&week) || weekday > 6 {
+                return None;
+            }
+            Day::MonthWeek(month, week, weekday)
+        } else {
+            Day::Ordinal(day.parse().ok().filter(|day| *day <= 365)?)
+        };
+        Some((day, time))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// UTC offsets of `rule` just before and at `utc`.
+        fn around(rule: &Rule, utc: i64) -> (i32, i32) {
+            (rule.offset_at(utc - 1), rule.offset_at(utc))
+        }
+
+        #[test]
+        fn central_european_rule() {
+            let rule = Rule::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+            // 2024-03-31T01:00:00Z, 02:00 CET, and 2024-10-27T01:00:00Z, 03:00 CEST
+            assert_eq!(around(&rule, 1_711_846_800), (3600, 7200));
+            assert_eq!(around(&rule, 1_729_990_800), (7200, 3600));
+            // 2024-01-15 and 2024-07-15
+            assert_eq!(rule.offset_at(1_705_276_800), 3600);
+            assert_eq!(rule.offset_at(1_721_001_600), 7200);
+        }
+
+        #[test]
+        fn southern_hemisphere_rule() {
+            let rule = Rule::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+            // 2024-04-06T16:00:00Z, 03:00 AEDT, and 2024-10-05T16:00:00Z, 02:00 AEST
+            assert_eq!(around(&rule, 1_712_419_200), (39600, 36000));
+            assert_eq!(around(&rule, 1_728_144_000), (36000, 39600));
+            // 2024-01-15 and 2024-07-15
+            assert_eq!(rule.offset_at(1_705_276_800), 39600);
+            assert_eq!(rule.offset_at(1_721_001_600), 36000);
+        }
+
+        #[test]
+        fn rule_forms() {
+            assert_eq!(Rule::parse("<+0330>-3:30").unwrap().offset_at(0), 12600);
+            // the default US rule, 2024-03-10T09:00:00Z is 02:00 MST
+            let rule = Rule::parse("MST7MDT").unwrap();
+            assert_eq!(around(&rule, 1_710_061_200), (-25200, -21600));
+            assert!(Rule::parse("CET-1CEST,M13.5.0,M10.5.0").is_none());
+        }
+    }
+}
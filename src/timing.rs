@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant};
+
+/// Guard created by `time_block!`, logs the time elapsed since its creation when dropped.
+#[must_use = "the elapsed time is logged when the guard is dropped"]
+pub struct TimedBlock {
+    name: String,
+    target: &'static str,
+    module_path: &'static str,
+    file: &'static str,
+    line: u32,
+    start: Instant,
+    warn_after: Option<Duration>,
+}
+impl TimedBlock {
+    #[doc(hidden)]
+    pub fn start(name: String, target: &'static str, module_path: &'static str, file: &'static str, line: u32) -> TimedBlock {
+        TimedBlock { name, target, module_path, file, line, start: Instant::now(), warn_after: None }
+    }
+    /// Logs at Warn instead of Debug when the block takes longer than `threshold`.
+    pub fn warn_after(mut self, threshold: Duration) -> Self {
+        self.warn_after = Some(threshold);
+        self
+    }
+}
+impl Drop for TimedBlock {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        let level = match self.warn_after {
+            Some(threshold) if elapsed > threshold => log::Level::Warn,
+            _ => log::Level::Debug,
+        };
+        if level <= log::max_level() {
+            let limit = match self.warn_after {
+                Some(threshold) if level == log::Level::Warn => format!(", more than {:?}", threshold),
+                _ => String::new(),
+            };
+            log::logger().log(&log::Record::builder()
+                .args(format_args!("{} took {:?}{}", self.name, elapsed, limit))
+                .level(level)
+                .target(self.target)
+                .module_path_static(Some(self.module_path))
+                .file_static(Some(self.file))
+                .line(Some(self.line))
+                .build());
+        }
+    }
+}
+
+/// Measures the duration of a block, e.g.
+/// `let _t = shvlog::time_block!(target: "Perf", "load config").warn_after(Duration::from_millis(100));`.
+///
+/// The elapsed time is logged at Debug when the guard is dropped, or at Warn when it exceeds
+/// the threshold set by `TimedBlock::warn_after()`.
+#[macro_export]
+macro_rules! time_block {
+    (target: $target:expr, $($arg:tt)+) => ($crate::TimedBlock::start(format!($($arg)+), $target, module_path!(), file!(), line!()));
+    ($($arg:tt)+) => ($crate::TimedBlock::start(format!($($arg)+), module_path!(), module_path!(), file!(), line!()));
+}
@@ -0,0 +1,127 @@
+use std::fmt::Write;
+#[cfg(feature = "tracing-bridge")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::kv::{Key, Source, Value, VisitSource};
+use tracing_core::field::{Field, Visit};
+#[cfg(feature = "tracing-bridge")]
+use tracing_core::span::{Attributes, Id, Record};
+#[cfg(feature = "tracing-bridge")]
+use tracing_core::subscriber::Interest;
+#[cfg(feature = "tracing-bridge")]
+use tracing_core::Metadata;
+use tracing_core::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Layer passing tracing events to the logger started by `init()`, so they are
+/// filtered by the module and target thresholds and formatted like `log` records.
+///
+/// Fields other than the message become key-value fields of the record. With the
+/// `tracing-bridge` feature, `init()` installs an equivalent global subscriber itself.
+///
+/// ```ignore
+/// use tracing_subscriber::layer::SubscriberExt;
+/// let _handle = shvlog::init(config)?;
+/// tracing::subscriber::set_global_default(tracing_subscriber::registry().with(shvlog::ShvLogLayer))?;
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShvLogLayer;
+
+#[derive(Default)]
+struct EventFields {
+    message: String,
+    fields: Vec<(&'static str, String)>,
+}
+impl Visit for EventFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.fields.push((field.name(), value.into()));
+        }
+    }
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            self.fields.push((field.name(), format!("{:?}", value)));
+        }
+    }
+}
+impl Source for EventFields {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), log::kv::Error> {
+        for (key, value) in &self.fields {
+            visitor.visit_pair(Key::from_str(key), Value::from(value.as_str()))?;
+        }
+        Ok(())
+    }
+}
+
+fn log_level(level: &Level) -> log::Level {
+    match *level {
+        Level::ERROR => log::Level::Error,
+        Level::WARN => log::Level::Warn,
+        Level::INFO => log::Level::Info,
+        Level::DEBUG => log::Level::Debug,
+        _ => log::Level::Trace,
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ShvLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        log_event(event);
+    }
+}
+
+fn log_event(event: &Event<'_>) {
+    let metadata = event.metadata();
+    let mut fields = EventFields::default();
+    event.record(&mut fields);
+    log::logger().log(
+        &log::Record::builder()
+            .args(format_args!("{}", fields.message))
+            .level(log_level(metadata.level()))
+            .target(metadata.target())
+            .module_path(metadata.module_path())
+            .file(metadata.file())
+            .line(metadata.line())
+            .key_values(&fields)
+            .build(),
+    );
+}
+
+/// Minimal global subscriber installed by `init()` with the `tracing-bridge` feature, passing
+/// tracing events of dependencies to the logger like `ShvLogLayer`. Spans are ignored.
+#[cfg(feature = "tracing-bridge")]
+struct BridgeSubscriber {
+    next_span: AtomicU64,
+}
+#[cfg(feature = "tracing-bridge")]
+impl Subscriber for BridgeSubscriber {
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        // the thresholds can change at runtime, ask enabled() every time
+        Interest::sometimes()
+    }
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        let level = log_level(metadata.level());
+        level <= log::max_level() && log::logger().enabled(&log::Metadata::builder().level(level).target(metadata.target()).build())
+    }
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(self.next_span.fetch_add(1, Ordering::Relaxed))
+    }
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+    fn event(&self, event: &Event<'_>) {
+        log_event(event);
+    }
+    fn enter(&self, _span: &Id) {}
+    fn exit(&self, _span: &Id) {}
+}
+
+/// Makes `BridgeSubscriber` the global tracing dispatcher, unless the application has
+/// already set its own.
+#[cfg(feature = "tracing-bridge")]
+pub(crate) fn install_bridge() {
+    let subscriber = BridgeSubscriber { next_span: AtomicU64::new(1) };
+    let _ = tracing_core::dispatcher::set_global_default(tracing_core::Dispatch::new(subscriber));
+}
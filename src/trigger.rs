@@ -0,0 +1,68 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use flexi_logger::filter::LogLineWriter;
+use flexi_logger::DeferredNow;
+
+/// Temporarily raises verbosity of a set of targets after an Error from the trigger target.
+pub(crate) struct Trigger {
+    target: String,
+    raised: Vec<String>,
+    pub(crate) level: log::Level,
+    duration: Duration,
+    active_until: Mutex<Option<Instant>>,
+}
+impl Trigger {
+    pub(crate) fn new(target: &str, raised: &[&str], level: log::Level, duration: Duration) -> Trigger {
+        let raised = if raised.is_empty() { vec![target.into()] } else { raised.iter().map(|t| t.to_string()).collect() };
+        Trigger {
+            target: target.into(),
+            raised,
+            level,
+            duration,
+            active_until: Mutex::new(None),
+        }
+    }
+    /// Level the record is raised to while the trigger is active.
+    pub(crate) fn raised_level(&self, record: &log::Record) -> Option<log::Level> {
+        let active = matches!(*self.active_until.lock().unwrap(), Some(until) if Instant::now() < until);
+        let module = record.module_path().unwrap_or("");
+        let target = record.target();
+        if active && self.raised.iter().any(|key| target.contains(key.as_str()) || module.contains(key.as_str())) {
+            Some(self.level)
+        } else {
+            None
+        }
+    }
+    /// Activates the trigger on an Error record from the trigger target, writing a notice when it starts.
+    pub(crate) fn check(&self, now: &mut DeferredNow, record: &log::Record, log_line_writer: &dyn LogLineWriter) -> std::io::Result<()> {
+        if record.level() != log::Level::Error || !record.target().contains(&self.target) {
+            return Ok(());
+        }
+        let instant = Instant::now();
+        let was_active = {
+            let mut active_until = self.active_until.lock().unwrap();
+            let was_active = matches!(*active_until, Some(until) if instant < until);
+            *active_until = Some(instant + self.duration);
+            was_active
+        };
+        if was_active {
+            return Ok(());
+        }
+        let notice = format!(
+            "Error from target '{}', raising {} to {} for {:?}",
+            self.target,
+            self.raised.join(","),
+            self.level,
+            self.duration
+        );
+        log_line_writer.write(now, &log::Record::builder()
+            .args(format_args!("{}", notice))
+            .level(log::Level::Info)
+            .target(record.target())
+            .module_path(record.module_path())
+            .file(record.file())
+            .line(record.line())
+            .build())
+    }
+}
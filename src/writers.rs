@@ -0,0 +1,71 @@
+use flexi_logger::writers::LogWriter;
+use flexi_logger::DeferredNow;
+
+/// Writer passing each record to several writers.
+pub(crate) struct MultiWriter(pub(crate) Vec<Box<dyn LogWriter>>);
+impl LogWriter for MultiWriter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        let mut result = Ok(());
+        for writer in &self.0 {
+            // one failing writer must not starve the others
+            if let Err(err) = writer.write(now, record) {
+                result = Err(err);
+            }
+        }
+        result
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        let mut result = Ok(());
+        for writer in &self.0 {
+            if let Err(err) = writer.flush() {
+                result = Err(err);
+            }
+        }
+        result
+    }
+    fn shutdown(&self) {
+        for writer in &self.0 {
+            writer.shutdown();
+        }
+    }
+}
+
+/// Combines `writers` into one, `None` when empty.
+pub(crate) fn combine(mut writers: Vec<Box<dyn LogWriter>>) -> Option<Box<dyn LogWriter>> {
+    match writers.len() {
+        0 => None,
+        1 => writers.pop(),
+        _ => Some(Box::new(MultiWriter(writers))),
+    }
+}
+
+/// Writer skipping records less severe than the threshold of its output.
+struct LevelWriter {
+    level: log::Level,
+    writer: Box<dyn LogWriter>,
+}
+impl LogWriter for LevelWriter {
+    fn write(&self, now: &mut DeferredNow, record: &log::Record) -> std::io::Result<()> {
+        if record.level() <= self.level {
+            self.writer.write(now, record)?;
+        }
+        Ok(())
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+    fn max_log_level(&self) -> log::LevelFilter {
+        self.level.to_level_filter()
+    }
+    fn shutdown(&self) {
+        self.writer.shutdown();
+    }
+}
+
+/// Applies the threshold of an output, if set, to its writer.
+pub(crate) fn with_level(writer: Box<dyn LogWriter>, level: Option<log::Level>) -> Box<dyn LogWriter> {
+    match level {
+        Some(level) => Box::new(LevelWriter { level, writer }),
+        None => writer,
+    }
+}
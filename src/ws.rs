@@ -0,0 +1,353 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, RwLock, Weak};
+use std::thread;
+use std::time::Duration;
+
+use crate::{subscribe, LogConfig, LogRecord};
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Records queued per client, more are dropped while the client cannot keep up.
+const CLIENT_QUEUE: usize = 1000;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Clients served at a time per server, more are rejected.
+const MAX_CLIENTS: usize = 16;
+
+/// Records selected by a client with the `level` and `verbosity` query parameters.
+struct ClientFilter {
+    level: log::LevelFilter,
+    overlay: Option<LogConfig>,
+}
+
+/// Frames queued for the client thread, the records and the replies to the client frames.
+enum Outgoing {
+    Record(String),
+    Pong(Vec<u8>),
+    /// Closes the connection, replying with the given close frame payload if any.
+    Close(Option<Vec<u8>>),
+}
+
+/// Counts a served client until dropped.
+struct ClientSlot(Arc<AtomicUsize>);
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Starts the "shvlog-ws" server thread, see `ShvLogHandle::serve_websocket()`.
+pub(crate) fn serve(addr: impl ToSocketAddrs, config: Weak<RwLock<LogConfig>>) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let clients = Arc::new(AtomicUsize::new(0));
+    thread::Builder::new().name("shvlog-ws".into()).spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            if clients.fetch_add(1, Ordering::SeqCst) >= MAX_CLIENTS {
+                clients.fetch_sub(1, Ordering::SeqCst);
+                let _ = reject(&mut stream, "503 Service Unavailable", "too many clients");
+                continue;
+            }
+            let slot = ClientSlot(clients.clone());
+            let config = config.clone();
+            // a client thread writes the records, a slow client does not hold up the others
+            let _ = thread::Builder::new().name("shvlog-ws-client".into()).spawn(move || {
+                let _slot = slot;
+                serve_client(stream, &config);
+            });
+        }
+    })?;
+    Ok(local_addr)
+}
+
+fn serve_client(mut stream: TcpStream, config: &Weak<RwLock<LogConfig>>) {
+    let ClientFilter { level, overlay } = match handshake(&mut stream) {
+        Ok(Some(filter)) => filter,
+        _ => return,
+    };
+    let (sender, receiver) = mpsc::sync_channel(CLIENT_QUEUE);
+    let reader = match stream.try_clone() {
+        Ok(reader) => reader,
+        Err(_) => return,
+    };
+    let reader_sender = sender.clone();
+    // the client frames are read on a companion thread, the records are written while it waits
+    if thread::Builder::new().name("shvlog-ws-reader".into()).spawn(move || read_client(reader, &reader_sender)).is_err() {
+        return;
+    }
+    let has_overlay = overlay.is_some();
+    let subscription = subscribe::subscribe(overlay, Arc::new(move |record: &LogRecord| {
+        if record.level <= level {
+            let _ = sender.try_send(Outgoing::Record(record.to_json()));
+        }
+    }));
+    let update_max_level = || {
+        if let Some(config) = config.upgrade().filter(|_| has_overlay) {
+            config.read().unwrap().update_max_level();
+        }
+    };
+    update_max_level();
+    for outgoing in receiver {
+        let written = match outgoing {
+            Outgoing::Record(json) => write_frame(&mut stream, 0x1, json.as_bytes()),
+            Outgoing::Pong(payload) => write_frame(&mut stream, 0xa, &payload),
+            Outgoing::Close(payload) => {
+                if let Some(payload) = payload {
+                    let _ = write_frame(&mut stream, 0x8, &payload);
+                }
+                break;
+            }
+        };
+        if written.is_err() {
+            break;
+        }
+    }
+    subscribe::unsubscribe(subscription);
+    update_max_level();
+    // wakes up the reader thread when the client did not close the connection
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// Answers the pings and the close of the client, closes on a disconnect or a framing error.
+fn read_client(mut stream: TcpStream, sender: &mpsc::SyncSender<Outgoing>) {
+    if stream.set_read_timeout(None).is_err() {
+        let _ = sender.send(Outgoing::Close(None));
+        return;
+    }
+    loop {
+        let outgoing = match read_frame(&mut stream) {
+            Ok((0x9, payload)) => Outgoing::Pong(payload),
+            // echoes the status code
+            Ok((0x8, payload)) => Outgoing::Close(Some(payload.into_iter().take(2).collect())),
+            Ok(_) => continue,
+            Err(_) => Outgoing::Close(None),
+        };
+        let close = matches!(outgoing, Outgoing::Close(_));
+        if sender.send(outgoing).is_err() || close {
+            break;
+        }
+    }
+}
+
+/// Reads a client frame, returns its opcode and the unmasked payload of a control frame,
+/// the payload of a data frame is skipped as the clients are not expected to send any.
+fn read_frame(stream: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    stream.read_exact(&mut header)?;
+    let opcode = header[0] & 0x0f;
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            stream.read_exact(&mut len)?;
+            u64::from(u16::from_be_bytes(len))
+        }
+        127 => {
+            let mut len = [0; 8];
+            stream.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => u64::from(len),
+    };
+    let mut mask = [0; 4];
+    if header[1] & 0x80 != 0 {
+        stream.read_exact(&mut mask)?;
+    }
+    if opcode & 0x8 == 0 {
+        io::copy(&mut stream.take(len), &mut io::sink())?;
+        return Ok((opcode, Vec::new()));
+    }
+    if len > 125 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "control frame too long"));
+    }
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((opcode, payload))
+}
+
+/// Upgrades the connection, returns the records requested by the client,
+/// or None when the request was rejected.
+fn handshake(stream: &mut TcpStream) -> std::io::Result<Option<ClientFilter>> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut reader = BufReader::new(&*stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut key = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let query = request_line.split_whitespace().nth(1).and_then(|path| path.split_once('?')).map_or("", |(_, query)| query);
+    let param = |wanted: &str| {
+        query.split('&')
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| *name == wanted)
+            .map(|(_, value)| percent_decode(value))
+    };
+    let level = param("level").map_or(Ok(log::LevelFilter::Trace), |level| log::LevelFilter::from_str(&level));
+    let overlay = param("verbosity").map(|verbosity| LogConfig::from_verbosity_string(&verbosity)).transpose();
+    let response = match (key, level, overlay) {
+        (None, _, _) => Err("WebSocket upgrade expected".to_string()),
+        (_, Err(_), _) => Err("invalid level, expected error, warn, info, debug or trace".to_string()),
+        (_, _, Err(err)) => Err(format!("invalid verbosity: {}", err)),
+        (Some(key), Ok(level), Ok(overlay)) => Ok((key, ClientFilter { level, overlay })),
+    };
+    match response {
+        Ok((key, filter)) => {
+            let accept = base64(&sha1(format!("{}{}", key, ACCEPT_GUID).as_bytes()));
+            write!(stream, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", accept)?;
+            Ok(Some(filter))
+        }
+        Err(reason) => {
+            reject(stream, "400 Bad Request", &reason)?;
+            Ok(None)
+        }
+    }
+}
+
+fn reject(stream: &mut TcpStream, status: &str, reason: &str) -> std::io::Result<()> {
+    stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    write!(stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, reason.len(), reason)
+}
+
+/// Decodes a query parameter value, `+` and `%20` stand for a space.
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex: Vec<u8> = input.by_ref().take(2).collect();
+                match std::str::from_utf8(&hex).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(decoded) => bytes.push(decoded),
+                    None => {
+                        bytes.push(b'%');
+                        bytes.extend_from_slice(&hex);
+                    }
+                }
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Writes an unmasked final frame, as sent by servers.
+fn write_frame(stream: &mut TcpStream, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= 0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)
+}
+
+/// SHA-1 digest, needed only for the `Sec-WebSocket-Accept` header.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| bits | u32::from(*byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key() {
+        // RFC 6455 section 1.3
+        let accept = base64(&sha1(format!("{}{}", "dGhlIHNhbXBsZSBub25jZQ==", ACCEPT_GUID).as_bytes()));
+        assert_eq!(accept, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        let hex: String = sha1(b"abc").iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(hex, "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // RFC 4648 section 10
+        assert_eq!([base64(b"f"), base64(b"fo"), base64(b"foo"), base64(b"foob")], ["Zg==", "Zm8=", "Zm9v", "Zm9vYg=="]);
+    }
+
+    #[test]
+    fn client_frames() {
+        // masked "Hello" of RFC 6455 section 5.7 as a ping and as a skipped text frame
+        let hello = [0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+        let frames: Vec<u8> = [&[0x81, 0x85][..], &hello, &[0x89, 0x85], &hello].concat();
+        let mut reader = &frames[..];
+        assert_eq!(read_frame(&mut reader).unwrap(), (0x1, Vec::new()));
+        assert_eq!(read_frame(&mut reader).unwrap(), (0x9, b"Hello".to_vec()));
+        assert!(read_frame(&mut reader).is_err());
+        let mut long_ping = &[0x89, 0xfe, 0x00, 0x7e, 0, 0, 0, 0][..];
+        assert_eq!(read_frame(&mut long_ping).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,16 @@
+//! `init_or_attach()` in a process whose logger is started once, like by a plugin host.
+
+use shvlog::{init_or_attach, Attachment, LogConfig};
+
+#[test]
+fn attaches_to_the_started_logger() {
+    let config = || LogConfig::from_verbosity_string("-d :I").unwrap();
+    let started = init_or_attach(config()).unwrap();
+    assert!(matches!(started, Attachment::Started(_)));
+    let attached = init_or_attach(LogConfig::from_verbosity_string("-d :T").unwrap()).unwrap();
+    assert!(matches!(attached, Attachment::Attached(_)));
+    // the attached plugin shares the filter of the started logger
+    attached.handle().unwrap().set_module_level("plugin", shvlog::log::LevelFilter::Debug);
+    assert!(shvlog::log::log_enabled!(target: "plugin", shvlog::log::Level::Debug));
+    assert!(!shvlog::log::log_enabled!(target: "app", shvlog::log::Level::Debug));
+}
@@ -0,0 +1,102 @@
+//! Property tests of the `-d`/`-v` threshold grammar on generated threshold strings.
+
+use shvlog::log::LevelFilter;
+use shvlog::{format_rules, parse_rules, FilterRule, ParseError, PatternKind};
+
+/// Characters giving the tricky cases: level separators, exclusions, wildcards, compound
+/// and field rules, regexes and whitespace.
+const ALPHABET: &[&str] = &[
+    "a", "b", "rpc", "shv", ":", "::", "!", "*", "@", "=", "~", "^", "/", " ", ",", "T", "d", "I", "warn", "0", "5", "off", "x1",
+];
+const CASES: usize = 20_000;
+
+/// xorshift64*, a fixed seed keeps the failures reproducible.
+struct Rng(u64);
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+    fn thresholds(&mut self) -> String {
+        let len = self.below(12);
+        (0..len).map(|_| ALPHABET[self.below(ALPHABET.len())]).collect()
+    }
+}
+
+fn level(rng: &mut Rng) -> LevelFilter {
+    [LevelFilter::Off, LevelFilter::Error, LevelFilter::Warn, LevelFilter::Info, LevelFilter::Debug, LevelFilter::Trace][rng.below(6)]
+}
+
+#[test]
+fn parsed_rules_round_trip() {
+    let mut rng = Rng(0x5eed_1234_abcd_0001);
+    let mut parsed = 0;
+    for _ in 0..CASES {
+        let thresholds = rng.thresholds();
+        let Ok(rules) = parse_rules(&thresholds) else {
+            continue;
+        };
+        parsed += 1;
+        let formatted = format_rules(&rules);
+        assert_eq!(parse_rules(&formatted).as_ref(), Ok(&rules), "'{}' formatted as '{}'", thresholds, formatted);
+        assert_eq!(format_rules(&parse_rules(&formatted).unwrap()), formatted, "'{}' is not canonical", formatted);
+    }
+    assert!(parsed > CASES / 4, "only {} of the generated strings parsed", parsed);
+}
+
+#[test]
+fn single_rules_round_trip() {
+    let mut rng = Rng(0x5eed_1234_abcd_0002);
+    for _ in 0..CASES {
+        let token = rng.thresholds().replace(',', "");
+        if let Ok(rule) = FilterRule::parse(&token) {
+            assert_eq!(rule.to_string().parse::<FilterRule>(), Ok(rule.clone()), "'{}' formatted as '{}'", token, rule);
+            assert_eq!(rule.kind, PatternKind::of(&rule.pattern));
+        }
+    }
+}
+
+#[test]
+fn generated_rules_round_trip() {
+    let mut rng = Rng(0x5eed_1234_abcd_0003);
+    for _ in 0..CASES {
+        let pattern = rng.thresholds().replace([',', '/'], "").trim().to_string();
+        let rule = FilterRule::new(&pattern, level(&mut rng));
+        assert_eq!(FilterRule::parse(&rule.to_string()), Ok(rule.clone()), "'{}'", rule);
+    }
+}
+
+#[test]
+fn parse_errors() {
+    let invalid_level = ParseError::InvalidLevel { token: "rpc:X".into(), level: "X".into() };
+    assert_eq!(parse_rules("shv:D,rpc:X"), Err(invalid_level));
+    assert_eq!(parse_rules("!rpc:D"), Err(ParseError::ExcludedWithLevel { token: "!rpc:D".into() }));
+    #[cfg(not(feature = "regex"))]
+    assert_eq!(parse_rules("/rpc/:D"), Err(ParseError::RegexUnsupported { token: "/rpc/:D".into() }));
+    #[cfg(feature = "regex")]
+    assert!(matches!(parse_rules("/(rpc/:D"), Err(ParseError::InvalidPattern { .. })));
+}
+
+#[test]
+fn rule_kinds() {
+    let kinds: Vec<_> = parse_rules("shvapp::rpc,shv*:D,shvbroker@RpcData:T,peer=42,path~^test/:W,!tokio")
+        .unwrap()
+        .into_iter()
+        .map(|rule| (rule.kind, rule.level))
+        .collect();
+    assert_eq!(kinds, [
+        (PatternKind::Plain, LevelFilter::Trace),
+        (PatternKind::Glob, LevelFilter::Debug),
+        (PatternKind::ModuleTarget, LevelFilter::Trace),
+        (PatternKind::Field, LevelFilter::Trace),
+        (PatternKind::Field, LevelFilter::Warn),
+        (PatternKind::Plain, LevelFilter::Off),
+    ]);
+    assert_eq!(PatternKind::of("/^rpc/"), PatternKind::Regex);
+    assert_eq!(FilterRule::new("!tokio", LevelFilter::Trace), FilterRule::new("tokio", LevelFilter::Off));
+}